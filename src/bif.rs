@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use binrw::{
     binrw,
@@ -10,6 +10,7 @@ use binrw::{
 use std::fs::{File, OpenOptions};
 
 use super::shared::RES_TYPES;
+use crate::{Error, Result};
 
 #[binrw]
 #[derive(Debug, PartialEq, Eq)]
@@ -29,7 +30,7 @@ struct BIFData {
     size: u32,
     name_offset: u32,
     name_size: u16,
-    #[br(seek_before = SeekFrom::Start(name_offset as u64 + name_size as u64))]
+    #[br(seek_before = SeekFrom::Start(name_offset as u64))]
     name: NullString,
 }
 
@@ -72,36 +73,34 @@ pub struct Bif<'a> {
 }
 
 impl Bif<'_> {
+    /// Reads the BIF listing from `chitin_path`, optionally keeping only the
+    /// resources of one BIF or one resource type.
     pub fn new(
-        installation_path: &mut PathBuf,
+        chitin_path: &Path,
         bif_ix_filter: Option<u32>,
         bif_type_filter: Option<&str>,
-    ) -> Option<Self> {
-        installation_path.push("chitin.key");
-
-        let mut file = Self::open_file(installation_path)
-            .expect("Chitin Key not found or could not be opened.");
+    ) -> Result<Self> {
+        let mut chitin_path = chitin_path.to_owned();
+        let mut file = Self::open_file(&mut chitin_path)?;
 
         let chitin_headers =
-            Self::validate_and_parse_chitin(&mut file).expect("Unable to parse the chitin key.");
+            Self::validate_and_parse_chitin(&mut file).map_err(|_| Error::MissingHeader)?;
 
-        let chitin_body = Self::parse_chitin_key_body(
+        Self::parse_chitin_key_body(
             &mut file,
             chitin_headers,
             bif_ix_filter,
             bif_type_filter,
-            installation_path,
-        );
-
-        Some(chitin_body)
+            &mut chitin_path,
+        )
     }
 
-    fn open_file(path: &mut PathBuf) -> Result<File, std::io::Error> {
-        OpenOptions::new().read(true).open(path)
+    fn open_file(path: &mut PathBuf) -> Result<File> {
+        OpenOptions::new().read(true).open(path).map_err(Into::into)
     }
 
-    fn validate_and_parse_chitin(file: &mut File) -> Result<ChitinHeader, binrw::Error> {
-        ChitinHeader::read(file)
+    fn validate_and_parse_chitin(file: &mut File) -> Result<ChitinHeader> {
+        ChitinHeader::read(file).map_err(Into::into)
     }
 
     fn parse_chitin_key_body<'a>(
@@ -110,21 +109,23 @@ impl Bif<'_> {
         bif_ix_filter: Option<u32>,
         bif_type_filter: Option<&str>,
         installation_path: &mut PathBuf,
-    ) -> Bif<'a> {
+    ) -> Result<Bif<'a>> {
         let mut array: HashMap<&&str, Vec<String>> = HashMap::new();
         let mut bifs: HashMap<String, HashMap<String, BIFResource>> = HashMap::new();
 
         for idx in 0..headers.key_count {
             file.seek(SeekFrom::Start(
                 (headers.offset_keytable + (idx * 22)).into(),
-            ))
-            .unwrap();
+            ))?;
 
-            let resource = BinaryResourceData::read(file).unwrap();
+            let resource = BinaryResourceData::read(file)?;
 
             let bif_index: u32 = resource.id >> 20;
 
-            let resource_type = RES_TYPES.get(&resource.type_id).unwrap();
+            // Entries of a type the engine doesn't know can never be requested by name.
+            let Some(resource_type) = RES_TYPES.get(&resource.type_id) else {
+                continue;
+            };
 
             if let Some(bif_ix_filter) = bif_ix_filter {
                 if bif_index != bif_ix_filter {
@@ -146,12 +147,11 @@ impl Bif<'_> {
             let bif_index_plus_offset: u32 = bif_index * 12;
             file.seek(SeekFrom::Start(
                 (headers.offset_filetable + bif_index_plus_offset).into(),
-            ))
-            .unwrap();
+            ))?;
 
             let index_in_bif = resource.id - (bif_index << 20);
 
-            let inner_bif = BIFData::read(file).unwrap();
+            let inner_bif = BIFData::read(file)?;
 
             let resource = BIFResource {
                 idx: index_in_bif,
@@ -164,28 +164,36 @@ impl Bif<'_> {
                 .or_default()
                 .insert(resource_format, resource);
         }
-        Bif {
+        Ok(Bif {
             path: installation_path.to_owned(),
             bifs,
             _array: array,
-        }
+        })
     }
 
-    fn open_bif_file(&mut self, bif_name: &str) -> Result<File, std::io::Error> {
-        let path = &mut self.path;
-        path.push(bif_name);
+    fn open_bif_file(&mut self, bif_name: &str) -> Result<File> {
+        // `path` points at chitin.key and BIF names are stored with Windows separators.
+        let mut path = self.path.with_file_name(bif_name.replace('\\', "/"));
 
-        Self::open_file(path)
+        Self::open_file(&mut path)
     }
 
-    fn open_resource_file(&self, bif_name: &str, resource_name: String) -> &BIFResource<'_> {
-        let bif_entry = &self.bifs.get(bif_name).unwrap();
-        bif_entry.get(&resource_name).unwrap()
+    fn open_resource_file(
+        &self,
+        bif_name: &str,
+        resource_name: String,
+    ) -> Result<&BIFResource<'_>> {
+        self.bifs
+            .get(bif_name)
+            .and_then(|bif_entry| bif_entry.get(&resource_name))
+            .ok_or_else(|| {
+                Error::InvalidData(format!("{} is not listed in {}", resource_name, bif_name))
+            })
     }
 
     pub fn extract_resource(&mut self, bif_name: &str, resource_name: String) -> File {
         let mut resource_buf = self.open_bif_file(bif_name).unwrap();
-        let resource = self.open_resource_file(bif_name, resource_name);
+        let resource = self.open_resource_file(bif_name, resource_name).unwrap();
 
         resource_buf
             .seek(SeekFrom::Start((24 + (16 * resource.idx)).into()))
@@ -213,31 +221,29 @@ impl Bif<'_> {
         file
     }
 
-    pub fn get_resource(&mut self, bif_name: &str, resource_name: String) -> Vec<u8> {
-        let mut bif_reader = self.open_bif_file(bif_name).unwrap();
-        let resource = self.open_resource_file(bif_name, resource_name);
+    pub fn get_resource(&mut self, bif_name: &str, resource_name: String) -> Result<Vec<u8>> {
+        let mut bif_reader = self.open_bif_file(bif_name)?;
+        let resource = self.open_resource_file(bif_name, resource_name)?;
 
-        bif_reader
-            .seek(SeekFrom::Start((24 + (16 * resource.idx)).into()))
-            .expect("Cannot seem to read the resource at this location.");
+        bif_reader.seek(SeekFrom::Start((24 + (16 * resource.idx)).into()))?;
 
         let mut resource_data = [0; 8];
-        bif_reader
-            .read_exact(&mut resource_data)
-            .expect("Unable to read the resource data.");
+        bif_reader.read_exact(&mut resource_data)?;
         let mut resource_data = Cursor::new(resource_data);
 
-        let resource = ExtractedResource::read(&mut resource_data).unwrap();
+        let resource = ExtractedResource::read(&mut resource_data)?;
+        if resource.offset as u64 + resource.size as u64 > bif_reader.metadata()?.len() {
+            return Err(Error::InvalidData(format!(
+                "resource data in {} is truncated",
+                bif_name
+            )));
+        }
 
-        bif_reader
-            .seek(SeekFrom::Start(resource.offset as u64))
-            .expect("Unable to seek the resource file.");
+        bif_reader.seek(SeekFrom::Start(resource.offset as u64))?;
 
         let mut resource_data = vec![0; resource.size as usize];
-        bif_reader
-            .read_exact(&mut resource_data)
-            .expect("Unable to read the resource data.");
+        bif_reader.read_exact(&mut resource_data)?;
 
-        resource_data.to_owned()
+        Ok(resource_data)
     }
 }
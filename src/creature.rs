@@ -0,0 +1,417 @@
+use crate::gff::{ExoLocString, GffStruct, GffValue};
+use crate::{Error, Result};
+
+/// The six ability scores, in the order the character sheet shows them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Ability {
+    Strength,
+    Dexterity,
+    Constitution,
+    Intelligence,
+    Wisdom,
+    Charisma,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 6] = [
+        Ability::Strength,
+        Ability::Dexterity,
+        Ability::Constitution,
+        Ability::Intelligence,
+        Ability::Wisdom,
+        Ability::Charisma,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Ability::Strength => "Str",
+            Ability::Dexterity => "Dex",
+            Ability::Constitution => "Con",
+            Ability::Intelligence => "Int",
+            Ability::Wisdom => "Wis",
+            Ability::Charisma => "Cha",
+        }
+    }
+}
+
+/// A class the creature has taken levels in, with the Force powers it grants.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CreatureClass {
+    /// The row in `classes.2da`.
+    pub class: i32,
+    pub level: i16,
+    /// Rows in `spells.2da`.
+    pub powers: Vec<u16>,
+}
+
+/// A creature struct, such as the player character in `Mod_PlayerList`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Creature {
+    pub gff_struct: GffStruct,
+}
+
+impl Creature {
+    pub fn new(gff_struct: GffStruct) -> Self {
+        Creature { gff_struct }
+    }
+
+    fn get_int(&self, label: &str) -> i64 {
+        self.gff_struct.get_int(label).unwrap_or_default()
+    }
+
+    pub fn get_first_name(&self) -> Option<&ExoLocString> {
+        self.gff_struct.get_loc_string("FirstName")
+    }
+
+    pub fn get_last_name(&self) -> Option<&ExoLocString> {
+        self.gff_struct.get_loc_string("LastName")
+    }
+
+    pub fn get_ability(&self, ability: Ability) -> u8 {
+        self.get_int(ability.label()) as u8
+    }
+
+    pub fn set_ability(&mut self, ability: Ability, score: u8) -> Result<()> {
+        self.gff_struct
+            .update_int(ability.label(), GffValue::Byte(score))?;
+
+        Ok(())
+    }
+
+    /// Skill ranks, indexed by row in `skills.2da`.
+    pub fn get_skills(&self) -> Vec<u8> {
+        self.gff_struct
+            .get_list("SkillList")
+            .map(|list| {
+                list.iter()
+                    .map(|skill| skill.get_int("Rank").unwrap_or_default() as u8)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_skill(&mut self, skill: usize, rank: u8) -> Result<()> {
+        self.gff_struct
+            .get_list_mut("SkillList")
+            .and_then(|list| list.get_mut(skill))
+            .ok_or_else(|| Error::InvalidData(format!("creature has no skill {}", skill)))?
+            .update_int("Rank", GffValue::Byte(rank))?;
+
+        Ok(())
+    }
+
+    /// Feats, as rows in `feat.2da`.
+    pub fn get_feats(&self) -> Vec<u16> {
+        self.gff_struct
+            .get_list("FeatList")
+            .map(|list| {
+                list.iter()
+                    .map(|feat| feat.get_int("Feat").unwrap_or_default() as u16)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn add_feat(&mut self, feat: u16) {
+        if self.get_feats().contains(&feat) {
+            return;
+        }
+
+        let mut entry = GffStruct::new(1);
+        entry.set("Feat", GffValue::Word(feat));
+
+        match self.gff_struct.get_list_mut("FeatList") {
+            Some(list) => list.push(entry),
+            None => self.gff_struct.set("FeatList", GffValue::List(vec![entry])),
+        }
+    }
+
+    pub fn remove_feat(&mut self, feat: u16) {
+        if let Some(list) = self.gff_struct.get_list_mut("FeatList") {
+            list.retain(|entry| entry.get_int("Feat") != Some(feat.into()));
+        }
+    }
+
+    pub fn get_classes(&self) -> Vec<CreatureClass> {
+        self.gff_struct
+            .get_list("ClassList")
+            .map(|list| {
+                list.iter()
+                    .map(|class| CreatureClass {
+                        class: class.get_int("Class").unwrap_or_default() as i32,
+                        level: class.get_int("ClassLevel").unwrap_or_default() as i16,
+                        powers: class
+                            .get_list("KnownList0")
+                            .map(|powers| {
+                                powers
+                                    .iter()
+                                    .map(|power| power.get_int("Spell").unwrap_or_default() as u16)
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn get_class_mut(&mut self, class: i32) -> Result<&mut GffStruct> {
+        self.gff_struct
+            .get_list_mut("ClassList")
+            .and_then(|list| {
+                list.iter_mut()
+                    .find(|entry| entry.get_int("Class") == Some(class.into()))
+            })
+            .ok_or_else(|| Error::InvalidData(format!("creature has no class {}", class)))
+    }
+
+    pub fn set_class_level(&mut self, class: i32, level: i16) -> Result<()> {
+        self.get_class_mut(class)?
+            .update_int("ClassLevel", GffValue::Short(level))?;
+
+        Ok(())
+    }
+
+    /// Every Force power known through any class.
+    pub fn get_powers(&self) -> Vec<u16> {
+        self.get_classes()
+            .into_iter()
+            .flat_map(|class| class.powers)
+            .collect()
+    }
+
+    /// Teaches a Force power through `class`, which must be a class the creature has.
+    pub fn add_power(&mut self, class: i32, power: u16) -> Result<()> {
+        if self.get_powers().contains(&power) {
+            return Ok(());
+        }
+
+        let mut entry = GffStruct::new(3);
+        entry.set("Spell", GffValue::Word(power));
+        entry.set("SpellFlags", GffValue::Byte(1));
+        entry.set("SpellMetaMagic", GffValue::Byte(0));
+
+        let class = self.get_class_mut(class)?;
+        match class.get_list_mut("KnownList0") {
+            Some(list) => list.push(entry),
+            None => class.set("KnownList0", GffValue::List(vec![entry])),
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_power(&mut self, power: u16) {
+        let Some(classes) = self.gff_struct.get_list_mut("ClassList") else {
+            return;
+        };
+
+        for class in classes {
+            if let Some(list) = class.get_list_mut("KnownList0") {
+                list.retain(|entry| entry.get_int("Spell") != Some(power.into()));
+            }
+        }
+    }
+
+    pub fn get_current_hit_points(&self) -> i16 {
+        self.get_int("CurrentHitPoints") as i16
+    }
+
+    pub fn set_current_hit_points(&mut self, hit_points: i16) -> Result<()> {
+        self.gff_struct
+            .update_int("CurrentHitPoints", GffValue::Short(hit_points))?;
+
+        Ok(())
+    }
+
+    pub fn get_max_hit_points(&self) -> i16 {
+        self.get_int("MaxHitPoints") as i16
+    }
+
+    pub fn set_max_hit_points(&mut self, hit_points: i16) -> Result<()> {
+        self.gff_struct
+            .update_int("MaxHitPoints", GffValue::Short(hit_points))?;
+
+        Ok(())
+    }
+
+    pub fn get_current_force_points(&self) -> i16 {
+        self.get_int("CurrentForce") as i16
+    }
+
+    pub fn set_current_force_points(&mut self, force_points: i16) -> Result<()> {
+        self.gff_struct
+            .update_int("CurrentForce", GffValue::Short(force_points))?;
+
+        Ok(())
+    }
+
+    pub fn get_max_force_points(&self) -> i16 {
+        self.get_int("MaxForcePoints") as i16
+    }
+
+    pub fn set_max_force_points(&mut self, force_points: i16) -> Result<()> {
+        self.gff_struct
+            .update_int("MaxForcePoints", GffValue::Short(force_points))?;
+
+        Ok(())
+    }
+
+    pub fn get_experience(&self) -> u32 {
+        self.get_int("Experience") as u32
+    }
+
+    pub fn set_experience(&mut self, experience: u32) -> Result<()> {
+        self.gff_struct
+            .update_int("Experience", GffValue::Dword(experience))?;
+
+        Ok(())
+    }
+
+    /// Alignment from 0 (dark side) to 100 (light side).
+    pub fn get_alignment(&self) -> u8 {
+        self.get_int("GoodEvil") as u8
+    }
+
+    pub fn set_alignment(&mut self, alignment: u8) -> Result<()> {
+        self.gff_struct
+            .update_int("GoodEvil", GffValue::Byte(alignment.min(100)))?;
+
+        Ok(())
+    }
+
+    /// The row in `appearance.2da`.
+    pub fn get_appearance(&self) -> u16 {
+        self.get_int("Appearance_Type") as u16
+    }
+
+    pub fn set_appearance(&mut self, appearance: u16) -> Result<()> {
+        self.gff_struct
+            .update_int("Appearance_Type", GffValue::Word(appearance))?;
+
+        Ok(())
+    }
+
+    /// The row in `portraits.2da`.
+    pub fn get_portrait(&self) -> u16 {
+        self.get_int("PortraitId") as u16
+    }
+
+    pub fn set_portrait(&mut self, portrait: u16) -> Result<()> {
+        self.gff_struct
+            .update_int("PortraitId", GffValue::Word(portrait))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gff::test_support::{gff_struct, round_trip};
+
+    fn sample_creature() -> Creature {
+        let skills = [4, 0, 2]
+            .into_iter()
+            .map(|rank| gff_struct(0, &[("Rank", GffValue::Byte(rank))]))
+            .collect();
+        let class = gff_struct(
+            2,
+            &[
+                ("Class", GffValue::Int(3)),
+                ("ClassLevel", GffValue::Short(2)),
+            ],
+        );
+
+        Creature::new(gff_struct(
+            u32::MAX,
+            &[
+                ("Str", GffValue::Byte(14)),
+                ("CurrentHitPoints", GffValue::Short(20)),
+                ("Experience", GffValue::Dword(1000)),
+                ("SkillList", GffValue::List(skills)),
+                ("ClassList", GffValue::List(vec![class])),
+            ],
+        ))
+    }
+
+    #[test]
+    fn reads_character_sheet() {
+        let creature = sample_creature();
+
+        assert_eq!(creature.get_ability(Ability::Strength), 14);
+        assert_eq!(creature.get_ability(Ability::Charisma), 0);
+        assert_eq!(creature.get_skills(), vec![4, 0, 2]);
+        assert!(creature.get_feats().is_empty());
+        assert_eq!(
+            creature.get_classes(),
+            vec![CreatureClass {
+                class: 3,
+                level: 2,
+                powers: Vec::new(),
+            }]
+        );
+        assert_eq!(creature.get_current_hit_points(), 20);
+        assert_eq!(creature.get_experience(), 1000);
+    }
+
+    #[test]
+    fn edits_use_the_game_field_types() {
+        let mut creature = sample_creature();
+        creature.gff_struct.set("Wis", GffValue::Int(10));
+
+        creature.set_ability(Ability::Wisdom, 16).unwrap();
+        creature.set_max_force_points(25).unwrap();
+        creature.set_alignment(150).unwrap();
+        creature.set_skill(1, 6).unwrap();
+
+        // Existing fields keep their type and missing ones are added as the game writes them.
+        let fields = &creature.gff_struct;
+        assert_eq!(fields.get("Wis"), Some(&GffValue::Int(16)));
+        assert_eq!(fields.get("MaxForcePoints"), Some(&GffValue::Short(25)));
+        assert_eq!(fields.get("GoodEvil"), Some(&GffValue::Byte(100)));
+        assert_eq!(creature.get_skills(), vec![4, 6, 2]);
+
+        creature
+            .gff_struct
+            .set("PortraitId", GffValue::CExoString("po_pbas".to_owned()));
+        assert!(creature.set_portrait(4).is_err());
+        assert!(creature.set_skill(9, 1).is_err());
+        assert!(creature.set_class_level(8, 1).is_err());
+    }
+
+    #[test]
+    fn feats_and_powers_are_listed_once() {
+        let mut creature = sample_creature();
+
+        creature.add_feat(94);
+        creature.add_feat(94);
+        creature.add_feat(11);
+        creature.remove_feat(94);
+        assert_eq!(creature.get_feats(), vec![11]);
+
+        creature.add_power(3, 7).unwrap();
+        creature.add_power(3, 7).unwrap();
+        assert!(creature.add_power(8, 1).is_err());
+        assert_eq!(creature.get_powers(), vec![7]);
+
+        let class = &creature.gff_struct.get_list("ClassList").unwrap()[0];
+        let power = &class.get_list("KnownList0").unwrap()[0];
+        assert_eq!(power.get("Spell"), Some(&GffValue::Word(7)));
+        assert_eq!(power.get("SpellFlags"), Some(&GffValue::Byte(1)));
+
+        creature.remove_power(7);
+        assert!(creature.get_powers().is_empty());
+    }
+
+    #[test]
+    fn added_lists_survive_writing() {
+        let mut creature = sample_creature();
+        creature.add_feat(11);
+        creature.add_power(3, 7).unwrap();
+        creature.set_class_level(3, 5).unwrap();
+
+        let parsed = Creature::new(round_trip("UTC ", &creature.gff_struct));
+        assert_eq!(parsed, creature);
+        assert_eq!(parsed.get_classes()[0].level, 5);
+    }
+}
@@ -0,0 +1,480 @@
+use crate::texture::{decode_png, encode_png};
+use crate::tpc::{Tpc, TpcMipmap};
+use crate::{Error, Result};
+use binrw::{binrw, io::Cursor, BinRead, BinWrite};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+
+const MAGIC: &[u8; 4] = b"DDS ";
+/// The size of a standard header, including the magic.
+const HEADER_SIZE: usize = 128;
+const BIOWARE_HEADER_SIZE: usize = 20;
+
+/// The pixel format holds a FourCC code such as `DXT1`.
+const PIXEL_FORMAT_FOURCC: u32 = 0x4;
+/// The pixel format holds uncompressed RGB channel masks.
+const PIXEL_FORMAT_RGB: u32 = 0x40;
+/// The pixel format holds a single luminance channel in the red mask.
+const PIXEL_FORMAT_LUMINANCE: u32 = 0x20000;
+/// The pixel format holds an alpha channel mask.
+const PIXEL_FORMAT_ALPHA_PIXELS: u32 = 0x1;
+
+/// BioWare headers store the compression as bytes per pixel of the decoded image.
+const BIOWARE_DXT1: u32 = 3;
+const BIOWARE_DXT5: u32 = 4;
+
+#[binrw]
+#[brw(little)]
+#[derive(Debug, PartialEq)]
+struct DdsPixelFormat {
+    size: u32,
+    flags: u32,
+    four_cc: [u8; 4],
+    rgb_bit_count: u32,
+    r_mask: u32,
+    g_mask: u32,
+    b_mask: u32,
+    a_mask: u32,
+}
+
+/// The header of a standard DirectDraw Surface.
+#[binrw]
+#[brw(little, magic = b"DDS ")]
+#[derive(Debug, PartialEq)]
+struct DdsHeaders {
+    size: u32,
+    flags: u32,
+    height: u32,
+    width: u32,
+    pitch: u32,
+    depth: u32,
+    #[brw(pad_after = 44)]
+    mip_count: u32,
+    #[brw(pad_after = 20)]
+    pixel_format: DdsPixelFormat,
+}
+
+/// The short header BioWare games use in place of the standard one.
+#[binrw]
+#[brw(little)]
+#[derive(Debug, PartialEq)]
+struct BioWareDdsHeaders {
+    width: u32,
+    height: u32,
+    /// `BIOWARE_DXT1` or `BIOWARE_DXT5`.
+    bytes_per_pixel: u32,
+    /// The size of the full size image.
+    data_size: u32,
+    alpha_test: f32,
+}
+
+/// A decoded DDS texture, either a standard DDS or the headerless BioWare variant.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dds {
+    pub width: u16,
+    pub height: u16,
+    /// RGBA8 pixels of the full size image, top row first.
+    pub pixels: Vec<u8>,
+}
+
+impl Dds {
+    pub fn new(dds_filename: &str) -> Result<Self> {
+        let mut file = File::open(dds_filename)?;
+
+        Self::from_reader(&mut file)
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        Self::from_bytes(&buffer)
+    }
+
+    /// Decodes the full size image, ignoring any mip levels.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(MAGIC) {
+            Self::from_standard_bytes(bytes)
+        } else {
+            Self::from_bioware_bytes(bytes)
+        }
+    }
+
+    pub fn from_rgba(width: u16, height: u16, pixels: Vec<u8>) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidData("DDS images cannot be empty".to_owned()));
+        }
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(Error::InvalidData(format!(
+                "expected {} bytes of RGBA data for a {}x{} image, found {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                pixels.len()
+            )));
+        }
+
+        Ok(Dds {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn from_png(bytes: &[u8]) -> Result<Self> {
+        let image = decode_png(bytes)?;
+
+        Self::from_rgba(image.width, image.height, image.pixels)
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        encode_png(self.width, self.height, &self.pixels)
+    }
+
+    pub fn save(&self, dds_filename: &str) -> Result<()> {
+        let mut file = File::create(dds_filename)?;
+
+        self.write_to(&mut file)
+    }
+
+    /// Writes a BioWare DDS with every mip level.
+    ///
+    /// Images that are fully opaque are compressed with DXT1, everything else with DXT5.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_bytes()?)?;
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let opaque = self.pixels.chunks_exact(4).all(|pixel| pixel[3] == 0xFF);
+        let (bytes_per_pixel, block_size) = if opaque {
+            (BIOWARE_DXT1, 8)
+        } else {
+            (BIOWARE_DXT5, 16)
+        };
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        let mut buffer = Cursor::new(Vec::new());
+        BioWareDdsHeaders {
+            width: self.width as u32,
+            height: self.height as u32,
+            bytes_per_pixel,
+            data_size: Tpc::get_dxt_size(width, height, block_size) as u32,
+            alpha_test: 0.0,
+        }
+        .write(&mut buffer)?;
+
+        let mut buffer = buffer.into_inner();
+        let mut mipmap = TpcMipmap {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.clone(),
+        };
+        loop {
+            let (width, height) = (mipmap.width as usize, mipmap.height as usize);
+            buffer.extend(if opaque {
+                Tpc::encode_dxt1(&mipmap.pixels, width, height)
+            } else {
+                Tpc::encode_dxt5(&mipmap.pixels, width, height)
+            });
+
+            if width <= 1 && height <= 1 {
+                break;
+            }
+            mipmap = Tpc::downsample(&mipmap);
+        }
+
+        Ok(buffer)
+    }
+
+    fn from_bioware_bytes(bytes: &[u8]) -> Result<Self> {
+        let headers =
+            BioWareDdsHeaders::read(&mut Cursor::new(bytes)).map_err(|_| Error::MissingHeader)?;
+        let (width, height) = Self::get_dimensions(headers.width, headers.height)?;
+
+        let block_size = match headers.bytes_per_pixel {
+            BIOWARE_DXT1 => 8,
+            BIOWARE_DXT5 => 16,
+            bytes_per_pixel => {
+                return Err(Error::InvalidData(format!(
+                    "unsupported BioWare DDS with {} bytes per pixel",
+                    bytes_per_pixel
+                )))
+            }
+        };
+
+        let offset = BIOWARE_HEADER_SIZE;
+        let size = Tpc::get_dxt_size(width as usize, height as usize, block_size);
+        let data = bytes
+            .get(offset..offset + size)
+            .ok_or_else(|| Error::InvalidData("DDS pixel data is truncated".to_owned()))?;
+
+        let pixels = if block_size == 8 {
+            Tpc::decode_dxt1(data, width as usize, height as usize)
+        } else {
+            Tpc::decode_dxt5(data, width as usize, height as usize)
+        };
+
+        Ok(Dds {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn from_standard_bytes(bytes: &[u8]) -> Result<Self> {
+        let headers =
+            DdsHeaders::read(&mut Cursor::new(bytes)).map_err(|_| Error::MissingHeader)?;
+        let (width, height) = Self::get_dimensions(headers.width, headers.height)?;
+        let format = &headers.pixel_format;
+
+        let offset = HEADER_SIZE;
+        let data = bytes.get(offset..).unwrap_or_default();
+        let truncated = || Error::InvalidData("DDS pixel data is truncated".to_owned());
+
+        let (width_px, height_px) = (width as usize, height as usize);
+        let pixels = if format.flags & PIXEL_FORMAT_FOURCC != 0 {
+            match &format.four_cc {
+                b"DXT1" => {
+                    let data = data
+                        .get(..Tpc::get_dxt_size(width_px, height_px, 8))
+                        .ok_or_else(truncated)?;
+                    Tpc::decode_dxt1(data, width_px, height_px)
+                }
+                b"DXT5" => {
+                    let data = data
+                        .get(..Tpc::get_dxt_size(width_px, height_px, 16))
+                        .ok_or_else(truncated)?;
+                    Tpc::decode_dxt5(data, width_px, height_px)
+                }
+                four_cc => {
+                    return Err(Error::InvalidData(format!(
+                        "unsupported DDS compression {}",
+                        String::from_utf8_lossy(four_cc)
+                    )))
+                }
+            }
+        } else if format.flags & (PIXEL_FORMAT_RGB | PIXEL_FORMAT_LUMINANCE) != 0
+            && matches!(format.rgb_bit_count, 8 | 16 | 24 | 32)
+        {
+            let bytes_per_pixel = format.rgb_bit_count as usize / 8;
+            let data = data
+                .get(..width_px * height_px * bytes_per_pixel)
+                .ok_or_else(truncated)?;
+            Self::decode_masked(data, bytes_per_pixel, format)
+        } else {
+            return Err(Error::InvalidData(format!(
+                "unsupported DDS pixel format {:#x} with {} bits per pixel",
+                format.flags, format.rgb_bit_count
+            )));
+        };
+
+        Ok(Dds {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn get_dimensions(width: u32, height: u32) -> Result<(u16, u16)> {
+        match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) if width != 0 && height != 0 => Ok((width, height)),
+            _ => Err(Error::InvalidData(format!(
+                "invalid DDS dimensions {}x{}",
+                width, height
+            ))),
+        }
+    }
+
+    /// Decodes uncompressed pixels whose channels are described by bit masks.
+    fn decode_masked(data: &[u8], bytes_per_pixel: usize, format: &DdsPixelFormat) -> Vec<u8> {
+        let extract = |value: u32, mask: u32| -> Option<u8> {
+            if mask == 0 {
+                return None;
+            }
+
+            let maximum = mask >> mask.trailing_zeros();
+            Some((((value & mask) >> mask.trailing_zeros()) as u64 * 255 / maximum as u64) as u8)
+        };
+        let luminance = format.flags & PIXEL_FORMAT_LUMINANCE != 0;
+        let has_alpha = format.flags & PIXEL_FORMAT_ALPHA_PIXELS != 0;
+
+        data.chunks_exact(bytes_per_pixel)
+            .flat_map(|pixel| {
+                let mut bytes = [0u8; 4];
+                bytes[..bytes_per_pixel].copy_from_slice(pixel);
+                let value = u32::from_le_bytes(bytes);
+
+                let r = extract(value, format.r_mask).unwrap_or(0);
+                let (g, b) = if luminance {
+                    (r, r)
+                } else {
+                    (
+                        extract(value, format.g_mask).unwrap_or(0),
+                        extract(value, format.b_mask).unwrap_or(0),
+                    )
+                };
+                let a = if has_alpha {
+                    extract(value, format.a_mask).unwrap_or(0xFF)
+                } else {
+                    0xFF
+                };
+
+                [r, g, b, a]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(alpha: impl Fn(u8) -> u8) -> Vec<u8> {
+        (0..8 * 8u8)
+            .flat_map(|idx| [idx * 4, 255 - idx * 4, 128, alpha(idx)])
+            .collect()
+    }
+
+    fn max_difference(left: &[u8], right: &[u8]) -> u8 {
+        left.iter()
+            .zip(right)
+            .map(|(left, right)| left.abs_diff(*right))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn standard_dds(pixel_format: DdsPixelFormat, width: u32, data: &[u8]) -> Vec<u8> {
+        let mut buffer = Cursor::new(Vec::new());
+        DdsHeaders {
+            size: 124,
+            flags: 0x100F,
+            height: 1,
+            width,
+            pitch: 0,
+            depth: 0,
+            mip_count: 1,
+            pixel_format,
+        }
+        .write(&mut buffer)
+        .unwrap();
+
+        let mut bytes = buffer.into_inner();
+        assert_eq!(bytes.len(), HEADER_SIZE);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn masked_format(flags: u32, rgb_bit_count: u32, masks: [u32; 4]) -> DdsPixelFormat {
+        DdsPixelFormat {
+            size: 32,
+            flags,
+            four_cc: [0; 4],
+            rgb_bit_count,
+            r_mask: masks[0],
+            g_mask: masks[1],
+            b_mask: masks[2],
+            a_mask: masks[3],
+        }
+    }
+
+    #[test]
+    fn bioware_dxt1_round_trip() {
+        let pixels = gradient(|_| 0xFF);
+        let bytes = Dds::from_rgba(8, 8, pixels.clone())
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        let headers = BioWareDdsHeaders::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(headers.bytes_per_pixel, BIOWARE_DXT1);
+        assert_eq!(headers.data_size, 32);
+        // 8x8, 4x4, 2x2 and 1x1 each take at least one 8 byte block.
+        assert_eq!(bytes.len(), BIOWARE_HEADER_SIZE + 32 + 8 + 8 + 8);
+
+        let dds = Dds::from_bytes(&bytes).unwrap();
+        assert_eq!((dds.width, dds.height), (8, 8));
+        assert!(dds.pixels.chunks_exact(4).all(|pixel| pixel[3] == 0xFF));
+        assert!(max_difference(&dds.pixels, &pixels) <= 16);
+    }
+
+    #[test]
+    fn bioware_dxt5_round_trip() {
+        let pixels = gradient(|idx| idx * 4);
+        let bytes = Dds::from_rgba(8, 8, pixels.clone())
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        let headers = BioWareDdsHeaders::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(headers.bytes_per_pixel, BIOWARE_DXT5);
+        assert_eq!(headers.data_size, 64);
+
+        let dds = Dds::from_bytes(&bytes).unwrap();
+        assert_eq!((dds.width, dds.height), (8, 8));
+        assert!(max_difference(&dds.pixels, &pixels) <= 16);
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        assert!(matches!(
+            Dds::from_rgba(0, 4, Vec::new()),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(
+            Dds::from_rgba(4, 0, Vec::new()),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn reads_masked_rgb() {
+        let bgr = masked_format(PIXEL_FORMAT_RGB, 24, [0xFF0000, 0xFF00, 0xFF, 0]);
+        let dds = Dds::from_bytes(&standard_dds(bgr, 2, &[3, 2, 1, 30, 20, 10])).unwrap();
+        assert_eq!(dds.pixels, [1, 2, 3, 255, 10, 20, 30, 255]);
+
+        let argb1555 = masked_format(
+            PIXEL_FORMAT_RGB | PIXEL_FORMAT_ALPHA_PIXELS,
+            16,
+            [0x7C00, 0x3E0, 0x1F, 0x8000],
+        );
+        let dds = Dds::from_bytes(&standard_dds(argb1555, 2, &[0x00, 0xFC, 0x1F, 0x00])).unwrap();
+        assert_eq!(dds.pixels, [255, 0, 0, 255, 0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn reads_luminance() {
+        let luminance = masked_format(PIXEL_FORMAT_LUMINANCE, 8, [0xFF, 0, 0, 0]);
+        let dds = Dds::from_bytes(&standard_dds(luminance, 2, &[0x40, 0xC0])).unwrap();
+        assert_eq!(dds.pixels, [64, 64, 64, 255, 192, 192, 192, 255]);
+
+        let luminance_alpha = masked_format(
+            PIXEL_FORMAT_LUMINANCE | PIXEL_FORMAT_ALPHA_PIXELS,
+            16,
+            [0xFF, 0, 0, 0xFF00],
+        );
+        let dds = Dds::from_bytes(&standard_dds(luminance_alpha, 1, &[0x40, 0x80])).unwrap();
+        assert_eq!(dds.pixels, [64, 64, 64, 128]);
+    }
+
+    #[test]
+    fn rejects_bad_standard_headers() {
+        let luminance = masked_format(PIXEL_FORMAT_LUMINANCE, 8, [0xFF, 0, 0, 0]);
+        assert!(matches!(
+            Dds::from_bytes(&standard_dds(luminance, 2, &[0x40])),
+            Err(Error::InvalidData(_))
+        ));
+
+        let palette = masked_format(0x20, 8, [0, 0, 0, 0]);
+        assert!(matches!(
+            Dds::from_bytes(&standard_dds(palette, 1, &[0])),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(
+            Dds::from_bytes(b"DDS "),
+            Err(Error::MissingHeader)
+        ));
+    }
+}
@@ -0,0 +1,520 @@
+use crate::game::Game;
+use crate::gff::Gff;
+use crate::save_info::SaveInfo;
+use crate::shared::{find_path_ignoring_case, resolve_windows_registry_keys};
+use crate::tga::Tga;
+use crate::Result;
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The folders Steam installs each game into, under `steamapps/common`.
+const STEAM_FOLDERS: [&str; 2] = ["swkotor", "Knights of the Old Republic II"];
+
+/// The folders the GOG installers default to.
+const GOG_FOLDERS: [&str; 2] = ["Star Wars - KotOR", "Star Wars - KotOR2"];
+
+/// The folders the retail discs install into, under `Program Files`.
+const RETAIL_FOLDERS: [&str; 2] = ["LucasArts/SWKotOR", "LucasArts/SWKotOR2"];
+
+/// Where the Aspyr ports of KotOR 2 keep the game data, relative to the game folder.
+const ASPYR_DATA_FOLDERS: [&str; 2] = ["steamassets", "KOTOR2.app/Contents/GameData"];
+
+const PROGRAM_FILES: [&str; 2] = ["Program Files (x86)", "Program Files"];
+
+const SAVE_INFO: &str = "savenfo.res";
+const SCREENSHOT: &str = "Screen.tga";
+
+/// A game installation found on this machine.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Installation {
+    /// The folder holding `chitin.key`.
+    pub path: PathBuf,
+    pub game: Game,
+}
+
+impl Installation {
+    /// Every save slot of this installation, most played first.
+    ///
+    /// Saves live in `saves/` for both games. The Aspyr version of KotOR 2
+    /// also keeps Steam Cloud saves in `cloudsaves/<steamid>/`, next to the
+    /// executable rather than the game data.
+    pub fn find_saves(&self) -> Vec<SaveSlot> {
+        let mut roots = vec![self.path.clone()];
+        if self
+            .path
+            .file_name()
+            .is_some_and(|name| name.eq_ignore_ascii_case("steamassets"))
+        {
+            roots.extend(self.path.parent().map(ToOwned::to_owned));
+        }
+
+        let mut save_folders = Vec::new();
+        for root in &roots {
+            if let Some(saves) = find_path_ignoring_case(root, "saves") {
+                save_folders.push(saves);
+            }
+            if self.game == Game::Kotor2 {
+                if let Some(cloud_saves) = find_path_ignoring_case(root, "cloudsaves") {
+                    save_folders.extend(read_subdirectories(&cloud_saves));
+                }
+            }
+        }
+
+        let mut slots: Vec<SaveSlot> = save_folders
+            .iter()
+            .flat_map(|folder| read_subdirectories(folder))
+            .filter_map(|path| SaveSlot::new(path, self.game))
+            .collect();
+        slots.sort_by_key(|slot| Reverse((slot.time_played, slot.modified)));
+
+        slots
+    }
+}
+
+/// What kind of save a slot holds.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SaveKind {
+    Manual,
+    Quick,
+    Auto,
+}
+
+/// A save folder, e.g. `saves/000043 - Game42`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SaveSlot {
+    pub path: PathBuf,
+    /// The number the folder name starts with, e.g. `43`.
+    pub number: u32,
+    /// The rest of the folder name, e.g. `"Game42"`.
+    pub name: String,
+    pub kind: SaveKind,
+    /// How long the save has been played, if `savenfo.res` could be read.
+    pub time_played: Option<Duration>,
+    /// When the save folder was last written.
+    pub modified: Option<SystemTime>,
+}
+
+impl SaveSlot {
+    /// Reads the slot at `path`, returning `None` if it is not a save folder.
+    pub fn new(path: PathBuf, game: Game) -> Option<Self> {
+        let (number, name) = Self::parse_folder_name(&path.file_name()?.to_string_lossy())?;
+        let save_info_path = find_path_ignoring_case(&path, SAVE_INFO)?;
+
+        let kind = if name.eq_ignore_ascii_case("QUICKSAVE") {
+            SaveKind::Quick
+        } else if name.eq_ignore_ascii_case("AUTOSAVE") {
+            SaveKind::Auto
+        } else {
+            SaveKind::Manual
+        };
+
+        let time_played = Gff::new(&save_info_path.to_string_lossy())
+            .ok()
+            .map(|gff| SaveInfo::new(gff, game).get_time_played());
+        let modified = std::fs::metadata(&save_info_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        Some(SaveSlot {
+            path,
+            number,
+            name,
+            kind,
+            time_played,
+            modified,
+        })
+    }
+
+    /// Splits a folder name such as `000043 - Game42` into `43` and `"Game42"`.
+    pub fn parse_folder_name(folder_name: &str) -> Option<(u32, String)> {
+        let (number, name) = folder_name.split_once(" - ")?;
+        if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        Some((number.parse().ok()?, name.to_owned()))
+    }
+
+    /// The `Screen.tga` thumbnail of the slot, if it has one.
+    pub fn get_screenshot(&self) -> Result<Option<Tga>> {
+        find_path_ignoring_case(&self.path, SCREENSHOT)
+            .map(|path| Tga::new(&path.to_string_lossy()))
+            .transpose()
+    }
+}
+
+/// Finds every installation of either game on this machine.
+pub fn find_installations() -> Vec<Installation> {
+    let mut candidates = resolve_windows_registry_keys();
+
+    if cfg!(target_os = "windows") {
+        candidates.extend(search_drive(Path::new("C:\\")));
+    }
+
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    if let Some(home) = home {
+        candidates.extend(search_home(Path::new(&home)));
+    }
+
+    validate_candidates(candidates)
+}
+
+/// Finds every installation under a home folder, through Steam, GOG and Wine.
+pub fn find_installations_in_home(home: &Path) -> Vec<Installation> {
+    validate_candidates(search_home(home))
+}
+
+fn search_home(home: &Path) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    let steam_roots = [
+        ".steam/steam",
+        ".steam/root",
+        ".local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+        "Library/Application Support/Steam",
+    ];
+    for root in steam_roots {
+        candidates.extend(search_steam(&home.join(root)));
+    }
+
+    for folder in GOG_FOLDERS {
+        candidates.push(home.join("GOG Games").join(folder));
+        candidates.push(home.join("Games/Heroic").join(folder));
+    }
+
+    let mut prefixes = vec![home.join(".wine")];
+    for prefix_root in [".local/share/wineprefixes", ".local/share/lutris/prefixes"] {
+        prefixes.extend(read_subdirectories(&home.join(prefix_root)));
+    }
+    for root in steam_roots {
+        for library in read_steam_libraries(&home.join(root)) {
+            // Proton keeps a Wine prefix per game under `compatdata/<appid>/pfx`.
+            prefixes.extend(
+                read_subdirectories(&library.join("steamapps/compatdata"))
+                    .into_iter()
+                    .map(|compatdata| compatdata.join("pfx")),
+            );
+        }
+    }
+    for prefix in prefixes {
+        candidates.extend(search_drive(&prefix.join("drive_c")));
+    }
+
+    candidates
+}
+
+/// Searches the default install locations on a Windows drive or a Wine `drive_c`.
+fn search_drive(drive: &Path) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    for program_files in PROGRAM_FILES {
+        let program_files = drive.join(program_files);
+
+        candidates.extend(search_steam(&program_files.join("Steam")));
+        for folder in RETAIL_FOLDERS {
+            candidates.push(program_files.join(folder));
+        }
+        for folder in GOG_FOLDERS {
+            candidates.push(program_files.join("GOG Galaxy/Games").join(folder));
+        }
+    }
+
+    for folder in GOG_FOLDERS {
+        candidates.push(drive.join("GOG Games").join(folder));
+    }
+
+    candidates
+}
+
+/// Every game folder in every library of the Steam installation at `steam_root`.
+fn search_steam(steam_root: &Path) -> Vec<PathBuf> {
+    read_steam_libraries(steam_root)
+        .into_iter()
+        .flat_map(|library| {
+            STEAM_FOLDERS
+                .iter()
+                .map(move |folder| library.join("steamapps/common").join(folder))
+        })
+        .collect()
+}
+
+/// The Steam installation itself plus every library listed in `libraryfolders.vdf`.
+fn read_steam_libraries(steam_root: &Path) -> Vec<PathBuf> {
+    if !steam_root.is_dir() {
+        return Vec::new();
+    }
+
+    let mut libraries = vec![steam_root.to_owned()];
+    if let Ok(contents) = std::fs::read_to_string(steam_root.join("steamapps/libraryfolders.vdf")) {
+        for library in parse_library_folders(&contents) {
+            if !libraries.contains(&library) {
+                libraries.push(library);
+            }
+        }
+    }
+
+    libraries
+}
+
+/// Reads the library paths out of Steam's `libraryfolders.vdf`.
+///
+/// Current versions store each library as a block with a `"path"` key, while
+/// older versions map the library number straight to its path.
+pub fn parse_library_folders(contents: &str) -> Vec<PathBuf> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.split('"').skip(1).step_by(2);
+            let key = tokens.next()?;
+            let value = tokens.next()?;
+
+            let is_library = key.eq_ignore_ascii_case("path")
+                || (key.bytes().all(|byte| byte.is_ascii_digit()) && value.contains(['/', '\\']));
+
+            is_library.then(|| PathBuf::from(value.replace("\\\\", "\\")))
+        })
+        .collect()
+}
+
+fn read_subdirectories(path: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Keeps the candidates that are really installations, detecting their game.
+fn validate_candidates(candidates: Vec<PathBuf>) -> Vec<Installation> {
+    let mut installations: Vec<Installation> = Vec::new();
+
+    for candidate in candidates {
+        let Some(installation) = detect_installation(&candidate) else {
+            continue;
+        };
+
+        let path = installation
+            .path
+            .canonicalize()
+            .unwrap_or(installation.path);
+        if installations.iter().all(|existing| existing.path != path) {
+            installations.push(Installation {
+                path,
+                game: installation.game,
+            });
+        }
+    }
+
+    installations
+}
+
+fn detect_installation(path: &Path) -> Option<Installation> {
+    if !path.is_dir() {
+        return None;
+    }
+
+    // The Aspyr ports only exist for KotOR 2 and keep the data in a subfolder.
+    for data_folder in ASPYR_DATA_FOLDERS {
+        let data_path = path.join(data_folder);
+        if find_path_ignoring_case(&data_path, "chitin.key").is_some() {
+            return Some(Installation {
+                path: data_path,
+                game: Game::Kotor2,
+            });
+        }
+    }
+
+    find_path_ignoring_case(path, "chitin.key")?;
+
+    Some(Installation {
+        path: path.to_owned(),
+        game: Game::from_installation(path)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gff::{GffStruct, GffValue};
+    use tempfile::TempDir;
+
+    fn create_install(path: &Path, executable: &str) {
+        std::fs::create_dir_all(path).unwrap();
+        std::fs::write(path.join("chitin.key"), b"").unwrap();
+        std::fs::write(path.join(executable), b"").unwrap();
+    }
+
+    #[test]
+    fn parses_both_library_folder_formats() {
+        let current = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+	}
+	"1"
+	{
+		"path"		"D:\\SteamLibrary"
+	}
+}"#;
+        let legacy = r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1700000000"
+	"1"		"/mnt/games/SteamLibrary"
+}"#;
+
+        assert_eq!(
+            parse_library_folders(current),
+            vec![
+                PathBuf::from("/home/user/.local/share/Steam"),
+                PathBuf::from("D:\\SteamLibrary"),
+            ]
+        );
+        assert_eq!(
+            parse_library_folders(legacy),
+            vec![PathBuf::from("/mnt/games/SteamLibrary")]
+        );
+    }
+
+    #[test]
+    fn finds_installs_in_steam_libraries() {
+        let home = TempDir::new().unwrap();
+        let library = TempDir::new().unwrap();
+
+        let steam = home.path().join(".local/share/Steam");
+        std::fs::create_dir_all(steam.join("steamapps")).unwrap();
+        std::fs::write(
+            steam.join("steamapps/libraryfolders.vdf"),
+            format!(
+                "\"1\"\n{{\n\t\"path\"\t\t\"{}\"\n}}",
+                library.path().display()
+            ),
+        )
+        .unwrap();
+
+        let kotor = steam.join("steamapps/common/swkotor");
+        create_install(&kotor, "swkotor.exe");
+        let kotor_2 = library
+            .path()
+            .join("steamapps/common/Knights of the Old Republic II");
+        create_install(&kotor_2.join("steamassets"), "KOTOR2");
+
+        let installations = find_installations_in_home(home.path());
+
+        assert_eq!(
+            installations,
+            vec![
+                Installation {
+                    path: kotor.canonicalize().unwrap(),
+                    game: Game::Kotor,
+                },
+                Installation {
+                    path: kotor_2.join("steamassets").canonicalize().unwrap(),
+                    game: Game::Kotor2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_installs_in_wine_prefixes() {
+        let home = TempDir::new().unwrap();
+
+        let gog = home
+            .path()
+            .join(".wine/drive_c/GOG Games/Star Wars - KotOR2");
+        create_install(&gog, "swkotor2.exe");
+        let retail = home
+            .path()
+            .join(".local/share/wineprefixes/kotor/drive_c/Program Files/LucasArts/SWKotOR");
+        create_install(&retail, "swkotor.exe");
+
+        let installations = find_installations_in_home(home.path());
+
+        assert_eq!(installations.len(), 2);
+        assert!(installations.contains(&Installation {
+            path: gog.canonicalize().unwrap(),
+            game: Game::Kotor2,
+        }));
+        assert!(installations.contains(&Installation {
+            path: retail.canonicalize().unwrap(),
+            game: Game::Kotor,
+        }));
+    }
+
+    #[test]
+    fn parses_save_folder_names() {
+        assert_eq!(
+            SaveSlot::parse_folder_name("000043 - Game42"),
+            Some((43, "Game42".to_owned()))
+        );
+        assert_eq!(
+            SaveSlot::parse_folder_name("000000 - QUICKSAVE"),
+            Some((0, "QUICKSAVE".to_owned()))
+        );
+        assert_eq!(SaveSlot::parse_folder_name("Game42"), None);
+        assert_eq!(SaveSlot::parse_folder_name("backup - Game42"), None);
+    }
+
+    #[test]
+    fn lists_saves_and_cloud_saves() {
+        let root = TempDir::new().unwrap();
+        let installation = Installation {
+            path: root.path().join("steamassets"),
+            game: Game::Kotor2,
+        };
+
+        let create_save = |folder: PathBuf, seconds: u32| {
+            std::fs::create_dir_all(&folder).unwrap();
+
+            let mut root = GffStruct::new(u32::MAX);
+            root.set("TIMEPLAYED", GffValue::Dword(seconds));
+            Gff {
+                file_type: "NFO ".to_owned(),
+                root,
+            }
+            .save(&folder.join(SAVE_INFO).to_string_lossy())
+            .unwrap();
+        };
+        create_save(installation.path.join("saves/000000 - QUICKSAVE"), 60);
+        create_save(installation.path.join("saves/000002 - Game1"), 120);
+        create_save(
+            root.path()
+                .join("cloudsaves/76561198000000000/000001 - AUTOSAVE"),
+            90,
+        );
+        std::fs::create_dir_all(installation.path.join("saves/notes")).unwrap();
+
+        let slots: Vec<(u32, SaveKind, Option<Duration>)> = installation
+            .find_saves()
+            .into_iter()
+            .map(|slot| (slot.number, slot.kind, slot.time_played))
+            .collect();
+
+        assert_eq!(
+            slots,
+            vec![
+                (2, SaveKind::Manual, Some(Duration::from_secs(120))),
+                (1, SaveKind::Auto, Some(Duration::from_secs(90))),
+                (0, SaveKind::Quick, Some(Duration::from_secs(60))),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_folders_without_chitin() {
+        let home = TempDir::new().unwrap();
+        std::fs::create_dir_all(home.path().join(".steam/steam/steamapps/common/swkotor")).unwrap();
+
+        assert!(find_installations_in_home(home.path()).is_empty());
+    }
+}
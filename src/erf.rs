@@ -1,32 +1,37 @@
 use binrw::{binrw, BinRead, BinWrite, BinWriterExt};
 use eos::DateTime;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use super::shared::RES_TYPES;
+use super::shared::{decode_string, get_resource_type_id, RES_TYPES};
+use crate::{Error, Result};
+
+/// The file types sharing the ERF layout.
+const ERF_FILE_TYPES: [&[u8; 4]; 4] = [b"ERF ", b"MOD ", b"SAV ", b"HAK "];
+const HEADER_SIZE: u32 = 160;
 
 #[binrw]
 #[brw(little)]
 #[derive(Debug, Eq, PartialEq)]
 struct LocalizedString {
     language_id: u32,
-    #[br(count=language_id)]
+    string_size: u32,
+    #[br(count=string_size)]
     string: Vec<u8>,
 }
 
 impl LocalizedString {
     pub fn resolve_string(&self) -> String {
-        let mut string: String =
-            String::from_utf8_lossy(&self.string[0..(self.string.len() - 1)]).into_owned();
-
-        string.split_off(8 + string.len())
+        decode_string(&self.string)
+            .trim_end_matches('\0')
+            .to_owned()
     }
 }
 
 #[binrw]
-#[br(little)]
+#[brw(little)]
 #[derive(Debug, Eq, PartialEq)]
 pub struct ErfMetadata {
     localized_string_count: u32,
@@ -37,6 +42,7 @@ pub struct ErfMetadata {
     offset_to_resource_list: u32,
     build_year: u32,
     build_day: u32,
+    #[brw(pad_after = 116)]
     description_str_ref: u32,
 }
 
@@ -46,10 +52,6 @@ pub struct ErfMetadata {
 struct ErfResourceMetadata {
     offset: u32,
     size: u32,
-    #[brw(ignore)]
-    new_offset: Option<u32>,
-    #[brw(ignore)]
-    new_size: Option<u32>,
 }
 
 #[binrw]
@@ -61,62 +63,117 @@ pub struct ErfResource {
     version_char: u8,
 
     #[br(count=if version_char == 48 { 16 } else { 32 }, try_map = |x| String::from_utf8(x).map(|s| s.trim_end_matches('\0').to_owned()))]
-    #[bw(map = |s| s.as_bytes().to_vec(), pad_size_to = if version == [0x86, 0x49, 0x46, 0x48] { 16 } else { 32 })]
+    #[bw(map = |s| s.as_bytes().to_vec(), pad_size_to = if version[3] == b'0' { 16 } else { 32 })]
     reference: String,
 
     id: u32,
     r#type: u32,
-    #[br(seek_before = SeekFrom::Start(resource_offset as u64 + ((id as u64 + 1u64) * 8u64)), restore_position)]
-    #[bw(seek_before = SeekFrom::Start(resource_offset as u64 + ((*id as u64 + 1u64) * 8u64)), restore_position)]
+    #[br(seek_before = SeekFrom::Start(resource_offset as u64 + (id as u64 * 8u64)), restore_position)]
+    #[bw(seek_before = SeekFrom::Start(resource_offset as u64 + (*id as u64 * 8u64)), restore_position)]
     metadata: ErfResourceMetadata,
-    #[br(ignore)]
-    data: Option<Vec<u8>>,
-    #[brw(ignore)]
-    new_data: Option<Vec<u8>>,
     #[brw(ignore)]
-    is_new: bool,
+    data: Option<Vec<u8>>,
 }
 
 impl ErfResource {
     pub fn get_resource_type<'a>(&self) -> &'a str {
-        RES_TYPES[&(self.r#type as u16)]
+        RES_TYPES
+            .get(&(self.r#type as u16))
+            .copied()
+            .unwrap_or("unknown")
+    }
+
+    /// The resource name with its extension, e.g. `"module.ifo"`.
+    pub fn get_filename(&self) -> String {
+        format!("{}.{}", self.reference, self.get_resource_type())
+    }
+
+    pub(crate) fn get_offset(&self) -> u32 {
+        self.metadata.offset
+    }
+
+    pub(crate) fn get_size(&self) -> u32 {
+        self.metadata.size
     }
 }
 
 #[binrw]
-#[brw(little, magic = b"ERF ")]
+#[brw(little)]
 #[derive(Debug, Eq, PartialEq)]
-pub struct Erf<'a> {
+pub struct Erf {
     #[brw(ignore)]
-    filename: &'a str,
+    filename: String,
+    /// One of `ERF `, `MOD `, `SAV ` or `HAK `.
+    file_type: [u8; 4],
     version: [u8; 4],
     metadata: ErfMetadata,
     #[br(seek_before = SeekFrom::Start(metadata.offset_to_localized_string as u64), count=metadata.localized_string_count)]
     localised_strings: Vec<LocalizedString>,
     #[br(seek_before = SeekFrom::Start(metadata.offset_to_key_list as u64), count=metadata.entry_count, args { inner: (version, metadata.offset_to_resource_list) })]
-    #[bw(args(*version, metadata.offset_to_key_list))]
+    #[bw(args(*version, metadata.offset_to_resource_list))]
     resources: Vec<ErfResource>,
     // files: Vec<String> // This is actually just filename + file ext
 }
 
-impl<'a> Erf<'a> {
-    pub fn new(erf_filename: &'a str) -> Self {
+impl Erf {
+    pub fn new(erf_filename: &str) -> Self {
         let mut buffer = Self::open_file(erf_filename).unwrap();
 
         let mut self_return = Self::read(&mut buffer).unwrap();
 
-        self_return.filename = erf_filename;
+        self_return.filename = erf_filename.to_owned();
 
         return self_return;
     }
 
-    fn open_file(filename: &str) -> Result<File, std::io::Error> {
+    /// Parses an ERF from the start of `reader` and loads the data of every resource.
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut signature = [0u8; 8];
+        reader.read_exact(&mut signature)?;
+        if !ERF_FILE_TYPES
+            .iter()
+            .any(|file_type| signature.starts_with(*file_type))
+            || !matches!(&signature[4..], b"V1.0" | b"V1.1")
+        {
+            return Err(Error::MissingHeader);
+        }
+
+        reader.rewind()?;
+        let mut erf = Self::read(reader)?;
+
+        for resource in erf.resources.iter_mut() {
+            reader.seek(SeekFrom::Start(resource.metadata.offset as u64))?;
+            let mut buf = vec![0u8; resource.metadata.size as usize];
+            reader.read_exact(&mut buf)?;
+
+            resource.data = Some(buf);
+        }
+
+        Ok(erf)
+    }
+
+    /// Opens an ERF from disk, loading the data of every resource.
+    pub fn open(erf_filename: &str) -> Result<Self> {
+        let mut file = Self::open_file(erf_filename)?;
+
+        let mut erf = Self::from_reader(&mut file)?;
+        erf.filename = erf_filename.to_owned();
+
+        Ok(erf)
+    }
+
+    /// Parses an ERF held in memory, such as a module save nested in `SAVEGAME.sav`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_reader(&mut Cursor::new(bytes))
+    }
+
+    fn open_file(filename: &str) -> std::io::Result<File> {
         let path = PathBuf::from_str(filename).expect("Path not found.");
 
         OpenOptions::new().read(true).open(path)
     }
 
-    pub fn load_file(&mut self, filename: &str) -> &mut Erf<'a> {
+    pub fn load_file(&mut self, filename: &str) -> &mut Erf {
         let mut file = Self::open_file(filename).unwrap();
 
         for resource in self.resources.iter_mut() {
@@ -126,7 +183,6 @@ impl<'a> Erf<'a> {
             file.read_exact(&mut buf).unwrap();
 
             resource.data = Some(buf);
-            resource.is_new = false
         }
 
         return self;
@@ -136,12 +192,15 @@ impl<'a> Erf<'a> {
         self.metadata.localized_string_count = self.localised_strings.len() as u32;
         let mut total_string_size: u32 = 0;
 
-        for string in &self.localised_strings {
+        for string in &mut self.localised_strings {
+            string.string_size = string.string.len() as u32;
+
             total_string_size += 8;
-            total_string_size += string.string.len() as u32;
+            total_string_size += string.string_size;
         }
 
         self.metadata.localized_string_size = total_string_size;
+        self.metadata.offset_to_localized_string = HEADER_SIZE;
         self.metadata.offset_to_key_list =
             self.metadata.offset_to_localized_string + self.metadata.localized_string_size;
 
@@ -157,53 +216,27 @@ impl<'a> Erf<'a> {
                 self.metadata.offset_to_key_list + (40 * self.metadata.entry_count)
         }
 
-        let offset_to_resource_data =
+        let mut offset_to_resource_data =
             self.metadata.offset_to_resource_list + (8 * self.metadata.entry_count);
 
-        let mut previous_resource_offset = offset_to_resource_data;
-        let mut previous_resource_length = 0;
-        let mut previous_resource_offset_memory = offset_to_resource_data;
-        let mut previous_resource_length_memory = 0;
-
-        for resource in &mut self.resources {
-            resource.metadata.offset = previous_resource_offset + previous_resource_length;
-            resource.metadata.new_offset =
-                Some(previous_resource_offset_memory + previous_resource_length_memory);
-
-            let resource_data_length = resource
+        for (id, resource) in self.resources.iter_mut().enumerate() {
+            // Resource list entries are located by ID, so IDs must stay contiguous.
+            resource.id = id as u32;
+            resource.metadata.offset = offset_to_resource_data;
+            resource.metadata.size = resource
                 .data
-                .as_mut()
+                .as_ref()
                 .expect("No data present in the current ERF file.")
                 .len() as u32;
 
-            if resource_data_length > 0 {
-                resource.metadata.size = resource_data_length;
-                resource.metadata.new_size = Some(resource_data_length);
-            }
-
-            let new_resource_data_length = resource
-                .new_data
-                .as_mut()
-                .expect("No new data found on this ERF resource.")
-                .len() as u32;
-
-            if new_resource_data_length > 0 {
-                resource.metadata.new_size = Some(new_resource_data_length);
-            }
-
-            previous_resource_offset = resource.metadata.offset;
-            previous_resource_offset_memory = resource
-                .metadata
-                .new_offset
-                .expect("No new offset set just yet.");
-            previous_resource_length = resource.metadata.size;
-            previous_resource_length_memory = resource
-                .metadata
-                .new_size
-                .expect("No new size set just yet.");
+            offset_to_resource_data += resource.metadata.size;
         }
     }
 
+    pub fn get_resources(&self) -> &[ErfResource] {
+        &self.resources
+    }
+
     pub fn get_resources_by_type(self, resource_type: &str) -> Vec<u32> {
         let mut resources: Vec<u32> = Vec::new();
 
@@ -219,7 +252,7 @@ impl<'a> Erf<'a> {
     pub fn get_resource_id_by_name(&self, resource_name: &str) -> Option<u32> {
         self.resources
             .iter()
-            .find(|key| format!("{}.{}", key.reference, key.get_resource_type()) == resource_name)
+            .find(|key| key.get_filename().eq_ignore_ascii_case(resource_name))
             .map(|key| key.id)
     }
 
@@ -237,11 +270,82 @@ impl<'a> Erf<'a> {
             .map(|key| key.to_owned())
     }
 
+    /// The loaded data of `resource_name`, e.g. `"inventory.res"`.
+    pub fn get_resource_data(&self, resource_name: &str) -> Option<&[u8]> {
+        self.get_resource_id_by_name(resource_name)
+            .and_then(|id| self.get_resource_by_id(id))
+            .and_then(|key| key.data.as_deref())
+    }
+
+    /// The names of the resources which are themselves ERFs, e.g. `"danm13.sav"`.
+    pub fn get_nested_erf_names(&self) -> Vec<String> {
+        self.resources
+            .iter()
+            .filter(|key| matches!(key.get_resource_type(), "sav" | "mod" | "erf"))
+            .map(ErfResource::get_filename)
+            .collect()
+    }
+
+    /// Parses a resource which is itself an ERF, loading all of its data.
+    pub fn get_nested_erf(&self, resource_name: &str) -> Result<Erf> {
+        let data = self.get_resource_data(resource_name).ok_or_else(|| {
+            Error::InvalidData(format!("{} is not loaded in the ERF", resource_name))
+        })?;
+
+        let mut erf = Self::from_bytes(data)?;
+        erf.filename = resource_name.to_owned();
+
+        Ok(erf)
+    }
+
+    /// Serialises `erf` and stores it as `resource_name`.
+    ///
+    /// Offsets and sizes of this ERF are recalculated when it is next written.
+    pub fn set_nested_erf(&mut self, resource_name: &str, erf: &Erf) -> Result<()> {
+        let data = erf.to_bytes(false)?;
+
+        self.add_resource(resource_name, data).map(|_| ())
+    }
+
+    /// Adds `resource_name` (e.g. `"pc.utc"`), replacing any resource of that name.
+    pub fn add_resource(&mut self, resource_name: &str, data: Vec<u8>) -> Result<u32> {
+        if let Some(id) = self.get_resource_id_by_name(resource_name) {
+            let resource = self.resources.iter_mut().find(|key| key.id == id).unwrap();
+            resource.data = Some(data);
+
+            return Ok(id);
+        }
+
+        let (reference, extension) = resource_name
+            .rsplit_once('.')
+            .ok_or_else(|| Error::InvalidData(format!("{} has no extension", resource_name)))?;
+        let type_id = get_resource_type_id(extension)
+            .ok_or_else(|| Error::InvalidData(format!("unknown resource type {}", extension)))?;
+        let max_length = if self.version[3] == b'0' { 16 } else { 32 };
+        if reference.len() > max_length {
+            return Err(Error::InvalidData(format!(
+                "resource name {} is longer than {} characters",
+                reference, max_length
+            )));
+        }
+
+        let id = self.resources.len() as u32;
+        self.resources.push(ErfResource {
+            reference: reference.to_owned(),
+            id,
+            r#type: type_id.into(),
+            data: Some(data),
+            ..Default::default()
+        });
+
+        Ok(id)
+    }
+
     pub fn export(
         &mut self,
         resource: &mut ErfResource,
         output_path: &mut PathBuf,
-    ) -> Result<(), binrw::Error> {
+    ) -> std::result::Result<(), binrw::Error> {
         output_path.push(&resource.reference);
         let mut output_file = std::fs::File::create(output_path).unwrap();
 
@@ -257,19 +361,94 @@ impl<'a> Erf<'a> {
             input_file
                 .read_exact(&mut buf)
                 .expect("Unable to read from the input file.");
+            output_file.write_all(&buf)?;
             resource.data = Some(buf);
 
-            self.write_erf_data(&mut output_file, true)?;
-
             Ok(())
         }
     }
 
-    pub fn write_erf_data(
+    /// Serialises the ERF, working out the offsets and sizes of the header as it
+    /// goes rather than storing them.
+    pub fn to_bytes(&self, update_build: bool) -> Result<Vec<u8>> {
+        let name_size: u32 = if self.version[3] == b'0' { 16 } else { 32 };
+        let data = self
+            .resources
+            .iter()
+            .map(|resource| {
+                resource.data.as_deref().ok_or_else(|| {
+                    Error::InvalidData(format!("{} is not loaded", resource.get_filename()))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (build_year, build_day) = if update_build {
+            let now = DateTime::now()
+                .map_err(|_| Error::InvalidData("unable to read the local time".to_owned()))?;
+            (now.year() as u32, now.day() as u32)
+        } else {
+            (self.metadata.build_year, self.metadata.build_day)
+        };
+
+        let localized_string_size = self
+            .localised_strings
+            .iter()
+            .map(|string| 8 + string.string.len() as u32)
+            .sum::<u32>();
+        let entry_count = self.resources.len() as u32;
+        let offset_to_key_list = HEADER_SIZE + localized_string_size;
+        let offset_to_resource_list = offset_to_key_list + (name_size + 8) * entry_count;
+
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write_all(&self.file_type)?;
+        cursor.write_all(&self.version)?;
+        ErfMetadata {
+            localized_string_count: self.localised_strings.len() as u32,
+            localized_string_size,
+            entry_count,
+            offset_to_localized_string: HEADER_SIZE,
+            offset_to_key_list,
+            offset_to_resource_list,
+            build_year,
+            build_day,
+            description_str_ref: self.metadata.description_str_ref,
+        }
+        .write(&mut cursor)?;
+
+        for string in &self.localised_strings {
+            cursor.write_all(&string.language_id.to_le_bytes())?;
+            cursor.write_all(&(string.string.len() as u32).to_le_bytes())?;
+            cursor.write_all(&string.string)?;
+        }
+
+        // Resource list entries are located by ID, so IDs follow the key order.
+        for (id, resource) in self.resources.iter().enumerate() {
+            let mut reference = resource.reference.as_bytes().to_vec();
+            reference.resize(name_size as usize, 0);
+            cursor.write_all(&reference)?;
+            cursor.write_all(&(id as u32).to_le_bytes())?;
+            cursor.write_all(&resource.r#type.to_le_bytes())?;
+        }
+
+        let mut offset = offset_to_resource_list + 8 * entry_count;
+        for data in &data {
+            cursor.write_all(&offset.to_le_bytes())?;
+            cursor.write_all(&(data.len() as u32).to_le_bytes())?;
+            offset += data.len() as u32;
+        }
+
+        for data in data {
+            cursor.write_all(data)?;
+        }
+
+        Ok(cursor.into_inner())
+    }
+
+    pub fn write_erf_data<W: Write + Seek>(
         &mut self,
-        output_file: &mut File,
+        output_file: &mut W,
         update_build: bool,
-    ) -> Result<(), binrw::Error> {
+    ) -> std::result::Result<(), binrw::Error> {
         if update_build == true {
             let now = DateTime::now().unwrap();
 
@@ -279,6 +458,199 @@ impl<'a> Erf<'a> {
 
         self.recalculate_sizing();
 
-        self.write(output_file)
+        self.write(output_file)?;
+
+        for resource in &self.resources {
+            output_file.seek(SeekFrom::Start(resource.metadata.offset as u64))?;
+            output_file.write_all(resource.data.as_ref().unwrap())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty ERF with the given localised descriptions, laid out by hand.
+    fn erf_bytes(file_type: &[u8; 4], version: &[u8; 4], strings: &[(u32, &str)]) -> Vec<u8> {
+        let mut string_table = Vec::new();
+        for (language_id, text) in strings {
+            string_table.extend_from_slice(&language_id.to_le_bytes());
+            string_table.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            string_table.extend_from_slice(text.as_bytes());
+        }
+        let key_list = HEADER_SIZE + string_table.len() as u32;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(file_type);
+        bytes.extend_from_slice(version);
+        for value in [
+            strings.len() as u32,
+            string_table.len() as u32,
+            0,
+            HEADER_SIZE,
+            key_list,
+            key_list,
+            123,
+            45,
+            0xFFFF_FFFF,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE as usize, 0);
+        bytes.extend_from_slice(&string_table);
+
+        bytes
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn sav_round_trip() {
+        let mut erf = Erf::from_bytes(&erf_bytes(b"SAV ", b"V1.0", &[])).unwrap();
+        erf.add_resource("inventory.res", vec![1, 2, 3]).unwrap();
+        erf.add_resource("pc.utc", b"UTC V3.2".to_vec()).unwrap();
+
+        let bytes = erf.to_bytes(false).unwrap();
+        assert_eq!(&bytes[..8], b"SAV V1.0");
+        assert_eq!(read_u32(&bytes, 16), 2);
+        assert_eq!(read_u32(&bytes, 20), HEADER_SIZE);
+        assert_eq!(read_u32(&bytes, 24), HEADER_SIZE);
+        assert_eq!(read_u32(&bytes, 28), HEADER_SIZE + 2 * 24);
+        assert_eq!((read_u32(&bytes, 32), read_u32(&bytes, 36)), (123, 45));
+        assert_eq!(read_u32(&bytes, 40), 0xFFFF_FFFF);
+        assert!(bytes[44..HEADER_SIZE as usize].iter().all(|&b| b == 0));
+
+        let mut written = Cursor::new(Vec::new());
+        erf.write_erf_data(&mut written, false).unwrap();
+        assert_eq!(written.into_inner(), bytes);
+
+        let parsed = Erf::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.to_bytes(false).unwrap(), bytes);
+        assert_eq!(
+            parsed.get_resource_data("INVENTORY.RES"),
+            Some(&[1, 2, 3][..])
+        );
+        assert_eq!(parsed.get_resource_data("pc.utc"), Some(&b"UTC V3.2"[..]));
+    }
+
+    #[test]
+    fn localized_strings_round_trip() {
+        let bytes = erf_bytes(b"MOD ", b"V1.0", &[(0, "Dantooine"), (2, "Dantooine\0")]);
+        let mut erf = Erf::from_bytes(&bytes).unwrap();
+        assert_eq!(erf.localised_strings.len(), 2);
+        assert_eq!(erf.localised_strings[0].resolve_string(), "Dantooine");
+        assert_eq!(erf.localised_strings[1].resolve_string(), "Dantooine");
+
+        erf.add_resource("module.ifo", vec![0; 4]).unwrap();
+        let bytes = erf.to_bytes(false).unwrap();
+
+        let string_size = 8 + 9 + 8 + 10;
+        assert_eq!(read_u32(&bytes, 8), 2);
+        assert_eq!(read_u32(&bytes, 12), string_size);
+        assert_eq!(read_u32(&bytes, 24), HEADER_SIZE + string_size);
+        let parsed = Erf::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.localised_strings, erf.localised_strings);
+        assert_eq!(parsed.to_bytes(false).unwrap(), bytes);
+    }
+
+    #[test]
+    fn long_names_need_version_1_1() {
+        let mut erf = Erf::from_bytes(&erf_bytes(b"ERF ", b"V1.0", &[])).unwrap();
+        assert!(erf
+            .add_resource("a_name_longer_than_16.utc", Vec::new())
+            .is_err());
+
+        let mut erf = Erf::from_bytes(&erf_bytes(b"ERF ", b"V1.1", &[])).unwrap();
+        erf.add_resource("a_name_longer_than_16.utc", vec![7])
+            .unwrap();
+
+        let bytes = erf.to_bytes(false).unwrap();
+        assert_eq!(read_u32(&bytes, 28), HEADER_SIZE + 40);
+
+        let parsed = Erf::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            parsed.get_resource_data("a_name_longer_than_16.utc"),
+            Some(&[7][..])
+        );
+    }
+
+    #[test]
+    fn nested_erf_round_trip() {
+        let mut module_save = Erf::from_bytes(&erf_bytes(b"SAV ", b"V1.0", &[])).unwrap();
+        module_save
+            .add_resource("module.ifo", b"IFO V3.2".to_vec())
+            .unwrap();
+
+        let mut savegame = Erf::from_bytes(&erf_bytes(b"SAV ", b"V1.0", &[])).unwrap();
+        savegame.add_resource("inventory.res", vec![1]).unwrap();
+        savegame.set_nested_erf("danm13.sav", &module_save).unwrap();
+        assert_eq!(savegame.get_nested_erf_names(), vec!["danm13.sav"]);
+
+        let mut nested = savegame.get_nested_erf("danm13.sav").unwrap();
+        assert_eq!(nested.filename, "danm13.sav");
+        assert_eq!(
+            nested.to_bytes(false).unwrap(),
+            module_save.to_bytes(false).unwrap()
+        );
+
+        nested.add_resource("m13aa.git", vec![2, 3]).unwrap();
+        nested
+            .add_resource("module.ifo", b"IFO V3.3".to_vec())
+            .unwrap();
+        savegame.set_nested_erf("danm13.sav", &nested).unwrap();
+
+        let parsed = Erf::from_bytes(&savegame.to_bytes(false).unwrap()).unwrap();
+        assert_eq!(parsed.get_resources().len(), 2);
+        assert_eq!(parsed.get_resource_data("inventory.res"), Some(&[1][..]));
+
+        let nested = parsed.get_nested_erf("DANM13.SAV").unwrap();
+        assert_eq!(
+            nested.get_resource_data("module.ifo"),
+            Some(&b"IFO V3.3"[..])
+        );
+        assert_eq!(nested.get_resource_data("m13aa.git"), Some(&[2, 3][..]));
+        assert!(parsed.get_nested_erf("inventory.res").is_err());
+        assert!(parsed.get_nested_erf("missing.sav").is_err());
+    }
+
+    #[test]
+    fn rejects_other_signatures() {
+        let bytes = erf_bytes(b"GFF ", b"V1.0", &[]);
+        assert!(matches!(Erf::from_bytes(&bytes), Err(Error::MissingHeader)));
+
+        let bytes = erf_bytes(b"ERF ", b"V2.0", &[]);
+        assert!(matches!(Erf::from_bytes(&bytes), Err(Error::MissingHeader)));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+
+    /// A V1.0 ERF of `file_type`, e.g. `b"SAV "`, with no resources.
+    pub(crate) fn empty_erf(file_type: &[u8; 4]) -> Erf {
+        let mut bytes = file_type.to_vec();
+        bytes.extend_from_slice(b"V1.0");
+        for value in [
+            0,
+            0,
+            0,
+            HEADER_SIZE,
+            HEADER_SIZE,
+            HEADER_SIZE,
+            0,
+            0,
+            u32::MAX,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE as usize, 0);
+
+        Erf::from_bytes(&bytes).unwrap()
     }
 }
@@ -5,8 +5,20 @@ use thiserror::Error; // Bring the `Error` derive macro into scope
 pub enum Error {
     #[error("unable to read file: {0}")]
     Io(#[from] IoError),
+    #[error("unable to parse binary data: {0}")]
+    Binary(#[from] binrw::Error),
+    #[error("unable to convert CSV data: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("unable to convert JSON data: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unable to decode PNG image: {0}")]
+    PngDecoding(#[from] png::DecodingError),
+    #[error("unable to encode PNG image: {0}")]
+    PngEncoding(#[from] png::EncodingError),
     #[error("missing file format header")]
     MissingHeader,
+    #[error("invalid file data: {0}")]
+    InvalidData(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
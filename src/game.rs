@@ -0,0 +1,128 @@
+use crate::bif::Bif;
+use crate::gff::Gff;
+use crate::shared::find_path_ignoring_case;
+use crate::tlk::Tlk;
+use std::fs::File;
+use std::path::Path;
+
+/// Executables shipped with each game, including the Aspyr ports of KotOR 2.
+const KOTOR_EXECUTABLES: [&str; 1] = ["swkotor.exe"];
+const KOTOR_2_EXECUTABLES: [&str; 2] = ["swkotor2.exe", "KOTOR2"];
+
+/// A 2DA that only exists in KotOR 2, used to tell the games apart by `chitin.key`.
+const KOTOR_2_ONLY_RESOURCE: &str = "upcrystals.2da";
+
+/// The English `dialog.tlk` holds roughly 49,000 strings in KotOR and 137,000 in KotOR 2.
+const KOTOR_2_MIN_STRING_COUNT: u32 = 100_000;
+
+/// The two games sharing this engine, which differ in a number of file layouts.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Game {
+    /// Star Wars: Knights of the Old Republic.
+    Kotor,
+    /// Star Wars: Knights of the Old Republic II - The Sith Lords.
+    Kotor2,
+}
+
+impl Game {
+    /// Works out which game is installed at `installation_path`.
+    ///
+    /// The executable is checked first, then the contents of `chitin.key` and
+    /// finally the size of `dialog.tlk`.
+    pub fn from_installation(installation_path: &Path) -> Option<Self> {
+        Self::from_executable(installation_path)
+            .or_else(|| Self::from_chitin(installation_path))
+            .or_else(|| Self::from_talk_table(installation_path))
+    }
+
+    fn from_executable(installation_path: &Path) -> Option<Self> {
+        let has_any = |executables: &[&str]| {
+            executables
+                .iter()
+                .any(|name| find_path_ignoring_case(installation_path, name).is_some())
+        };
+
+        if has_any(&KOTOR_2_EXECUTABLES) {
+            Some(Game::Kotor2)
+        } else if has_any(&KOTOR_EXECUTABLES) {
+            Some(Game::Kotor)
+        } else {
+            None
+        }
+    }
+
+    fn from_chitin(installation_path: &Path) -> Option<Self> {
+        let chitin_path = find_path_ignoring_case(installation_path, "chitin.key")?;
+        let bif = Bif::new(&chitin_path, None, Some("2da")).ok()?;
+        let is_kotor_2 = bif
+            .bifs
+            .values()
+            .any(|resources| resources.contains_key(KOTOR_2_ONLY_RESOURCE));
+
+        Some(if is_kotor_2 {
+            Game::Kotor2
+        } else {
+            Game::Kotor
+        })
+    }
+
+    fn from_talk_table(installation_path: &Path) -> Option<Self> {
+        let path = find_path_ignoring_case(installation_path, "dialog.tlk")?;
+        let string_count = Tlk::read_string_count(&mut File::open(path).ok()?).ok()?;
+
+        Some(if string_count >= KOTOR_2_MIN_STRING_COUNT {
+            Game::Kotor2
+        } else {
+            Game::Kotor
+        })
+    }
+
+    /// Works out which game a save belongs to from fields only KotOR 2 writes.
+    pub fn from_save(save_info: &Gff, party_table: &Gff) -> Self {
+        if save_info.root.get("PCNAME").is_some() || party_table.root.get("PT_INFLUENCE").is_some()
+        {
+            Game::Kotor2
+        } else {
+            Game::Kotor
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A `chitin.key` header listing `key_count` keys right after it.
+    fn chitin_header(key_count: u32) -> Vec<u8> {
+        let mut bytes = b"KEY V1  ".to_vec();
+        for value in [0, key_count, 24, 24] {
+            bytes.extend_from_slice(&u32::to_le_bytes(value));
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn finds_chitin_regardless_of_case() {
+        let installation = TempDir::new().unwrap();
+        std::fs::write(installation.path().join("CHITIN.KEY"), chitin_header(0)).unwrap();
+
+        assert_eq!(
+            Game::from_installation(installation.path()),
+            Some(Game::Kotor)
+        );
+    }
+
+    #[test]
+    fn ignores_truncated_chitin() {
+        let installation = TempDir::new().unwrap();
+        let chitin_path = installation.path().join("chitin.key");
+
+        for bytes in [Vec::new(), b"KEY V1  ".to_vec(), chitin_header(3)] {
+            std::fs::write(&chitin_path, bytes).unwrap();
+
+            assert_eq!(Game::from_installation(installation.path()), None);
+        }
+    }
+}
@@ -1 +1,1048 @@
-pub struct Gff {}
+use crate::shared::{decode_string, encode_string};
+use crate::{Error, Result};
+use binrw::{binrw, io::Cursor, BinRead, BinWrite};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const FIELD_BYTE: u32 = 0;
+const FIELD_CHAR: u32 = 1;
+const FIELD_WORD: u32 = 2;
+const FIELD_SHORT: u32 = 3;
+const FIELD_DWORD: u32 = 4;
+const FIELD_INT: u32 = 5;
+const FIELD_DWORD64: u32 = 6;
+const FIELD_INT64: u32 = 7;
+const FIELD_FLOAT: u32 = 8;
+const FIELD_DOUBLE: u32 = 9;
+const FIELD_CEXOSTRING: u32 = 10;
+const FIELD_RESREF: u32 = 11;
+const FIELD_CEXOLOCSTRING: u32 = 12;
+const FIELD_VOID: u32 = 13;
+const FIELD_STRUCT: u32 = 14;
+const FIELD_LIST: u32 = 15;
+const FIELD_ORIENTATION: u32 = 16;
+const FIELD_VECTOR: u32 = 17;
+
+const HEADER_SIZE: u32 = 56;
+
+/// Structs nested deeper than this are assumed to be a reference cycle.
+const MAX_DEPTH: usize = 128;
+
+#[binrw]
+#[brw(little)]
+#[derive(Debug, Eq, PartialEq)]
+struct GffHeader {
+    file_type: [u8; 4],
+    version: [u8; 4],
+    struct_offset: u32,
+    struct_count: u32,
+    field_offset: u32,
+    field_count: u32,
+    label_offset: u32,
+    label_count: u32,
+    field_data_offset: u32,
+    field_data_size: u32,
+    field_indices_offset: u32,
+    field_indices_size: u32,
+    list_indices_offset: u32,
+    list_indices_size: u32,
+}
+
+#[binrw]
+#[brw(little)]
+#[derive(Debug, Eq, PartialEq)]
+struct GffStructEntry {
+    type_id: u32,
+    data_or_offset: u32,
+    field_count: u32,
+}
+
+#[binrw]
+#[brw(little)]
+#[derive(Debug, Eq, PartialEq)]
+struct GffFieldEntry {
+    type_id: u32,
+    label_index: u32,
+    data_or_offset: u32,
+}
+
+/// The raw tables of a GFF file, exactly as they are laid out on disk.
+#[binrw]
+#[brw(little)]
+#[derive(Debug, Eq, PartialEq)]
+struct GffData {
+    header: GffHeader,
+    #[br(seek_before = SeekFrom::Start(header.struct_offset as u64), count = header.struct_count)]
+    structs: Vec<GffStructEntry>,
+    #[br(seek_before = SeekFrom::Start(header.field_offset as u64), count = header.field_count)]
+    fields: Vec<GffFieldEntry>,
+    #[br(seek_before = SeekFrom::Start(header.label_offset as u64), count = header.label_count)]
+    labels: Vec<[u8; 16]>,
+    #[br(seek_before = SeekFrom::Start(header.field_data_offset as u64), count = header.field_data_size)]
+    field_data: Vec<u8>,
+    #[br(seek_before = SeekFrom::Start(header.field_indices_offset as u64), count = header.field_indices_size / 4)]
+    field_indices: Vec<u32>,
+    #[br(seek_before = SeekFrom::Start(header.list_indices_offset as u64), count = header.list_indices_size / 4)]
+    list_indices: Vec<u32>,
+}
+
+/// A single language variant of a [`ExoLocString`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExoLocSubString {
+    /// The combined language and gender ID, `language * 2 + gender`.
+    pub id: u32,
+    pub string: String,
+}
+
+impl ExoLocSubString {
+    pub fn language(&self) -> u32 {
+        self.id / 2
+    }
+
+    pub fn is_feminine(&self) -> bool {
+        self.id % 2 == 1
+    }
+}
+
+/// A localised string, referencing the talk table and/or carrying its own text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExoLocString {
+    /// The talk table entry for this string, `u32::MAX` when there is none.
+    pub str_ref: u32,
+    pub strings: Vec<ExoLocSubString>,
+}
+
+impl Default for ExoLocString {
+    fn default() -> Self {
+        ExoLocString {
+            str_ref: u32::MAX,
+            strings: Vec::new(),
+        }
+    }
+}
+
+impl ExoLocString {
+    pub fn get_str_ref(&self) -> Option<u32> {
+        (self.str_ref != u32::MAX).then_some(self.str_ref)
+    }
+
+    pub fn get_string(&self, id: u32) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|substring| substring.id == id)
+            .map(|substring| substring.string.as_str())
+    }
+}
+
+/// The value of a single GFF field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GffValue {
+    Byte(u8),
+    Char(i8),
+    Word(u16),
+    Short(i16),
+    Dword(u32),
+    Int(i32),
+    Dword64(u64),
+    Int64(i64),
+    Float(f32),
+    Double(f64),
+    CExoString(String),
+    ResRef(String),
+    CExoLocString(ExoLocString),
+    Void(Vec<u8>),
+    Struct(GffStruct),
+    List(Vec<GffStruct>),
+    Orientation([f32; 4]),
+    Vector([f32; 3]),
+}
+
+impl GffValue {
+    /// The field type ID used for this value on disk.
+    pub fn type_id(&self) -> u32 {
+        match self {
+            GffValue::Byte(_) => FIELD_BYTE,
+            GffValue::Char(_) => FIELD_CHAR,
+            GffValue::Word(_) => FIELD_WORD,
+            GffValue::Short(_) => FIELD_SHORT,
+            GffValue::Dword(_) => FIELD_DWORD,
+            GffValue::Int(_) => FIELD_INT,
+            GffValue::Dword64(_) => FIELD_DWORD64,
+            GffValue::Int64(_) => FIELD_INT64,
+            GffValue::Float(_) => FIELD_FLOAT,
+            GffValue::Double(_) => FIELD_DOUBLE,
+            GffValue::CExoString(_) => FIELD_CEXOSTRING,
+            GffValue::ResRef(_) => FIELD_RESREF,
+            GffValue::CExoLocString(_) => FIELD_CEXOLOCSTRING,
+            GffValue::Void(_) => FIELD_VOID,
+            GffValue::Struct(_) => FIELD_STRUCT,
+            GffValue::List(_) => FIELD_LIST,
+            GffValue::Orientation(_) => FIELD_ORIENTATION,
+            GffValue::Vector(_) => FIELD_VECTOR,
+        }
+    }
+
+    /// Returns any of the integer field kinds widened to an `i64`.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            GffValue::Byte(value) => Some(value.into()),
+            GffValue::Char(value) => Some(value.into()),
+            GffValue::Word(value) => Some(value.into()),
+            GffValue::Short(value) => Some(value.into()),
+            GffValue::Dword(value) => Some(value.into()),
+            GffValue::Int(value) => Some(value.into()),
+            GffValue::Dword64(value) => Some(value as i64),
+            GffValue::Int64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Replaces the value of an integer field, keeping its type.
+    ///
+    /// Returns `false`, leaving the value untouched, if this is not an integer field.
+    pub fn set_int(&mut self, value: i64) -> bool {
+        match self {
+            GffValue::Byte(existing) => *existing = value as u8,
+            GffValue::Char(existing) => *existing = value as i8,
+            GffValue::Word(existing) => *existing = value as u16,
+            GffValue::Short(existing) => *existing = value as i16,
+            GffValue::Dword(existing) => *existing = value as u32,
+            GffValue::Int(existing) => *existing = value as i32,
+            GffValue::Dword64(existing) => *existing = value as u64,
+            GffValue::Int64(existing) => *existing = value,
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            GffValue::Float(value) => Some(value.into()),
+            GffValue::Double(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the text of a `CExoString` or `ResRef` field.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GffValue::CExoString(value) | GffValue::ResRef(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_loc_string(&self) -> Option<&ExoLocString> {
+        match self {
+            GffValue::CExoLocString(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_struct(&self) -> Option<&GffStruct> {
+        match self {
+            GffValue::Struct(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_struct_mut(&mut self) -> Option<&mut GffStruct> {
+        match self {
+            GffValue::Struct(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&Vec<GffStruct>> {
+        match self {
+            GffValue::List(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut Vec<GffStruct>> {
+        match self {
+            GffValue::List(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// A labelled field within a [`GffStruct`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GffField {
+    pub label: String,
+    pub value: GffValue,
+}
+
+/// A GFF struct: a type ID and an ordered set of labelled fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GffStruct {
+    pub id: u32,
+    pub fields: Vec<GffField>,
+}
+
+impl GffStruct {
+    pub fn new(id: u32) -> Self {
+        GffStruct {
+            id,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, label: &str) -> Option<&GffValue> {
+        self.fields
+            .iter()
+            .find(|field| field.label == label)
+            .map(|field| &field.value)
+    }
+
+    pub fn get_mut(&mut self, label: &str) -> Option<&mut GffValue> {
+        self.fields
+            .iter_mut()
+            .find(|field| field.label == label)
+            .map(|field| &mut field.value)
+    }
+
+    /// Replaces the value of `label`, appending a new field if it is not present.
+    pub fn set(&mut self, label: &str, value: GffValue) {
+        match self.get_mut(label) {
+            Some(existing) => *existing = value,
+            None => self.fields.push(GffField {
+                label: label.to_owned(),
+                value,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<GffValue> {
+        let position = self.fields.iter().position(|field| field.label == label)?;
+
+        Some(self.fields.remove(position).value)
+    }
+
+    pub fn get_int(&self, label: &str) -> Option<i64> {
+        self.get(label).and_then(GffValue::as_int)
+    }
+
+    /// Sets an existing integer field, keeping its type.
+    ///
+    /// Returns `false` if the field is missing or not an integer.
+    pub fn set_int(&mut self, label: &str, value: i64) -> bool {
+        self.get_mut(label)
+            .is_some_and(|existing| existing.set_int(value))
+    }
+
+    /// Sets an integer field to the number held by `value`.
+    ///
+    /// An existing field keeps its type, so a `DWORD` stays a `DWORD` even when
+    /// given a `GffValue::Int`; a missing field is added as `value`. Fields of any
+    /// other type are left untouched and reported as an error.
+    pub fn update_int(&mut self, label: &str, value: GffValue) -> Result<()> {
+        let number = value.as_int().ok_or_else(|| {
+            Error::InvalidData(format!("{:?} is not an integer GFF value", value))
+        })?;
+
+        match self.get_mut(label) {
+            Some(existing) => {
+                if existing.set_int(number) {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidData(format!(
+                        "GFF field {} holds a {:?}, not an integer",
+                        label, existing
+                    )))
+                }
+            }
+            None => {
+                self.set(label, value);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get_float(&self, label: &str) -> Option<f64> {
+        self.get(label).and_then(GffValue::as_float)
+    }
+
+    pub fn get_string(&self, label: &str) -> Option<&str> {
+        self.get(label).and_then(GffValue::as_str)
+    }
+
+    pub fn get_loc_string(&self, label: &str) -> Option<&ExoLocString> {
+        self.get(label).and_then(GffValue::as_loc_string)
+    }
+
+    pub fn get_struct(&self, label: &str) -> Option<&GffStruct> {
+        self.get(label).and_then(GffValue::as_struct)
+    }
+
+    pub fn get_struct_mut(&mut self, label: &str) -> Option<&mut GffStruct> {
+        self.get_mut(label).and_then(GffValue::as_struct_mut)
+    }
+
+    pub fn get_list(&self, label: &str) -> Option<&Vec<GffStruct>> {
+        self.get(label).and_then(GffValue::as_list)
+    }
+
+    pub fn get_list_mut(&mut self, label: &str) -> Option<&mut Vec<GffStruct>> {
+        self.get_mut(label).and_then(GffValue::as_list_mut)
+    }
+}
+
+/// A parsed Generic File Format (V3.2) resource.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gff {
+    /// The four character resource type from the header, e.g. `"UTC "`.
+    pub file_type: String,
+    /// The top level struct, which every other struct hangs off.
+    pub root: GffStruct,
+}
+
+impl Gff {
+    pub fn new(gff_filename: &str) -> Result<Self> {
+        let mut file = Self::open_file(gff_filename)?;
+
+        Self::from_reader(&mut file)
+    }
+
+    fn open_file(filename: &str) -> Result<File> {
+        File::open(filename).map_err(Into::into)
+    }
+
+    /// Parses a GFF starting at the current position of `reader`.
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        Self::from_bytes(&buffer)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(bytes);
+
+        let header = GffHeader::read(&mut cursor)?;
+        if &header.version != b"V3.2" {
+            return Err(Error::MissingHeader);
+        }
+
+        cursor.set_position(0);
+        let data = GffData::read(&mut cursor)?;
+
+        Ok(Gff {
+            file_type: decode_string(&data.header.file_type),
+            root: data.read_struct(0, 0)?,
+        })
+    }
+
+    pub fn save(&self, gff_filename: &str) -> Result<()> {
+        let mut file = File::create(gff_filename)?;
+
+        self.write_to(&mut file)
+    }
+
+    /// Serialises the tree, rebuilding every table from scratch.
+    pub fn write_to<W: Write + Seek>(&self, writer: &mut W) -> Result<()> {
+        let mut builder = GffBuilder::default();
+        builder.write_struct(&self.root)?;
+
+        let mut file_type = encode_string(&self.file_type);
+        file_type.resize(4, b' ');
+
+        let data = builder.finish(file_type.try_into().unwrap());
+        data.write(writer)?;
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write_to(&mut cursor)?;
+
+        Ok(cursor.into_inner())
+    }
+}
+
+impl GffData {
+    fn read_struct(&self, index: u32, depth: usize) -> Result<GffStruct> {
+        if depth > MAX_DEPTH {
+            return Err(Error::InvalidData("GFF structs nest too deeply".to_owned()));
+        }
+
+        let entry = self
+            .structs
+            .get(index as usize)
+            .ok_or_else(|| Error::InvalidData(format!("GFF struct {} out of range", index)))?;
+
+        let field_indices = match entry.field_count {
+            0 => Vec::new(),
+            1 => vec![entry.data_or_offset],
+            count => {
+                let start = entry.data_or_offset as usize / 4;
+                self.field_indices
+                    .get(start..start + count as usize)
+                    .ok_or_else(|| {
+                        Error::InvalidData(format!(
+                            "GFF struct {} field indices out of range",
+                            index
+                        ))
+                    })?
+                    .to_vec()
+            }
+        };
+
+        let mut fields = Vec::with_capacity(field_indices.len());
+        for field_index in field_indices {
+            fields.push(self.read_field(field_index, depth)?);
+        }
+
+        Ok(GffStruct {
+            id: entry.type_id,
+            fields,
+        })
+    }
+
+    fn read_field(&self, index: u32, depth: usize) -> Result<GffField> {
+        let entry = self
+            .fields
+            .get(index as usize)
+            .ok_or_else(|| Error::InvalidData(format!("GFF field {} out of range", index)))?;
+
+        let label = self
+            .labels
+            .get(entry.label_index as usize)
+            .map(|label| decode_string(label).trim_end_matches('\0').to_owned())
+            .ok_or_else(|| {
+                Error::InvalidData(format!("GFF label {} out of range", entry.label_index))
+            })?;
+
+        let data = entry.data_or_offset;
+        let value = match entry.type_id {
+            FIELD_BYTE => GffValue::Byte(data as u8),
+            FIELD_CHAR => GffValue::Char(data as u8 as i8),
+            FIELD_WORD => GffValue::Word(data as u16),
+            FIELD_SHORT => GffValue::Short(data as u16 as i16),
+            FIELD_DWORD => GffValue::Dword(data),
+            FIELD_INT => GffValue::Int(data as i32),
+            FIELD_FLOAT => GffValue::Float(f32::from_bits(data)),
+            FIELD_DWORD64 => GffValue::Dword64(u64::read_le(&mut self.field_data_at(data)?)?),
+            FIELD_INT64 => GffValue::Int64(i64::read_le(&mut self.field_data_at(data)?)?),
+            FIELD_DOUBLE => GffValue::Double(f64::read_le(&mut self.field_data_at(data)?)?),
+            FIELD_CEXOSTRING => {
+                let mut cursor = self.field_data_at(data)?;
+                let length = u32::read_le(&mut cursor)?;
+                GffValue::CExoString(decode_string(&Self::read_bytes(&mut cursor, length)?))
+            }
+            FIELD_RESREF => {
+                let mut cursor = self.field_data_at(data)?;
+                let length = u8::read_le(&mut cursor)?;
+                GffValue::ResRef(decode_string(&Self::read_bytes(
+                    &mut cursor,
+                    length.into(),
+                )?))
+            }
+            FIELD_CEXOLOCSTRING => {
+                let mut cursor = self.field_data_at(data)?;
+                let _total_size = u32::read_le(&mut cursor)?;
+                let str_ref = u32::read_le(&mut cursor)?;
+                let count = u32::read_le(&mut cursor)?;
+
+                let mut strings = Vec::new();
+                for _ in 0..count {
+                    let id = u32::read_le(&mut cursor)?;
+                    let length = u32::read_le(&mut cursor)?;
+                    let string = decode_string(&Self::read_bytes(&mut cursor, length)?);
+                    strings.push(ExoLocSubString { id, string });
+                }
+
+                GffValue::CExoLocString(ExoLocString { str_ref, strings })
+            }
+            FIELD_VOID => {
+                let mut cursor = self.field_data_at(data)?;
+                let length = u32::read_le(&mut cursor)?;
+                GffValue::Void(Self::read_bytes(&mut cursor, length)?)
+            }
+            FIELD_STRUCT => GffValue::Struct(self.read_struct(data, depth + 1)?),
+            FIELD_LIST => {
+                let start = data as usize / 4;
+                let count = *self.list_indices.get(start).ok_or_else(|| {
+                    Error::InvalidData(format!("GFF list offset {} out of range", data))
+                })? as usize;
+
+                let struct_indices = self
+                    .list_indices
+                    .get(start + 1..start + 1 + count)
+                    .ok_or_else(|| {
+                        Error::InvalidData(format!("GFF list at offset {} out of range", data))
+                    })?;
+
+                let mut structs = Vec::with_capacity(count);
+                for struct_index in struct_indices {
+                    structs.push(self.read_struct(*struct_index, depth + 1)?);
+                }
+
+                GffValue::List(structs)
+            }
+            FIELD_ORIENTATION => {
+                let mut cursor = self.field_data_at(data)?;
+                let mut orientation = [0f32; 4];
+                for value in orientation.iter_mut() {
+                    *value = f32::read_le(&mut cursor)?;
+                }
+                GffValue::Orientation(orientation)
+            }
+            FIELD_VECTOR => {
+                let mut cursor = self.field_data_at(data)?;
+                let mut vector = [0f32; 3];
+                for value in vector.iter_mut() {
+                    *value = f32::read_le(&mut cursor)?;
+                }
+                GffValue::Vector(vector)
+            }
+            unknown => {
+                return Err(Error::InvalidData(format!(
+                    "unknown GFF field type {} for {}",
+                    unknown, label
+                )))
+            }
+        };
+
+        Ok(GffField { label, value })
+    }
+
+    fn field_data_at(&self, offset: u32) -> Result<Cursor<&[u8]>> {
+        self.field_data
+            .get(offset as usize..)
+            .map(Cursor::new)
+            .ok_or_else(|| {
+                Error::InvalidData(format!("GFF field data offset {} out of range", offset))
+            })
+    }
+
+    fn read_bytes(cursor: &mut Cursor<&[u8]>, length: u32) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; length as usize];
+        cursor.read_exact(&mut buffer)?;
+
+        Ok(buffer)
+    }
+}
+
+/// Flattens a [`GffStruct`] tree back into the on-disk tables.
+///
+/// Structs and fields are numbered depth first in the order they appear, so
+/// rewriting the writer's own output gives the same bytes. Files written by the
+/// game order their tables differently, so they only round-trip to an equal tree.
+#[derive(Default)]
+struct GffBuilder {
+    structs: Vec<GffStructEntry>,
+    fields: Vec<GffFieldEntry>,
+    labels: Vec<[u8; 16]>,
+    field_data: Vec<u8>,
+    field_indices: Vec<u32>,
+    list_indices: Vec<u32>,
+}
+
+impl GffBuilder {
+    fn write_struct(&mut self, gff_struct: &GffStruct) -> Result<u32> {
+        let index = self.structs.len();
+        self.structs.push(GffStructEntry {
+            type_id: gff_struct.id,
+            data_or_offset: 0,
+            field_count: gff_struct.fields.len() as u32,
+        });
+
+        let data_or_offset = match gff_struct.fields.as_slice() {
+            [] => u32::MAX,
+            [field] => self.write_field(field)?,
+            fields => {
+                let start = self.field_indices.len();
+                self.field_indices.resize(start + fields.len(), 0);
+
+                for (position, field) in fields.iter().enumerate() {
+                    self.field_indices[start + position] = self.write_field(field)?;
+                }
+
+                (start * 4) as u32
+            }
+        };
+
+        self.structs[index].data_or_offset = data_or_offset;
+
+        Ok(index as u32)
+    }
+
+    fn write_field(&mut self, field: &GffField) -> Result<u32> {
+        let index = self.fields.len();
+        let label_index = self.label_index(&field.label)?;
+        self.fields.push(GffFieldEntry {
+            type_id: field.value.type_id(),
+            label_index,
+            data_or_offset: 0,
+        });
+
+        let field_data_offset = self.field_data.len() as u32;
+        let data_or_offset = match &field.value {
+            GffValue::Byte(value) => (*value).into(),
+            GffValue::Char(value) => (*value as u8).into(),
+            GffValue::Word(value) => (*value).into(),
+            GffValue::Short(value) => (*value as u16).into(),
+            GffValue::Dword(value) => *value,
+            GffValue::Int(value) => *value as u32,
+            GffValue::Float(value) => value.to_bits(),
+            GffValue::Dword64(value) => {
+                self.field_data.extend_from_slice(&value.to_le_bytes());
+                field_data_offset
+            }
+            GffValue::Int64(value) => {
+                self.field_data.extend_from_slice(&value.to_le_bytes());
+                field_data_offset
+            }
+            GffValue::Double(value) => {
+                self.field_data.extend_from_slice(&value.to_le_bytes());
+                field_data_offset
+            }
+            GffValue::CExoString(value) => {
+                let bytes = encode_string(value);
+                self.field_data
+                    .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                self.field_data.extend_from_slice(&bytes);
+                field_data_offset
+            }
+            GffValue::ResRef(value) => {
+                let bytes = encode_string(value);
+                let length = u8::try_from(bytes.len()).map_err(|_| {
+                    Error::InvalidData(format!("ResRef {} is longer than 255 bytes", value))
+                })?;
+                self.field_data.push(length);
+                self.field_data.extend_from_slice(&bytes);
+                field_data_offset
+            }
+            GffValue::CExoLocString(value) => {
+                let strings: Vec<(u32, Vec<u8>)> = value
+                    .strings
+                    .iter()
+                    .map(|substring| (substring.id, encode_string(&substring.string)))
+                    .collect();
+                let total_size = 8 + strings
+                    .iter()
+                    .map(|(_, bytes)| 8 + bytes.len())
+                    .sum::<usize>();
+
+                self.field_data
+                    .extend_from_slice(&(total_size as u32).to_le_bytes());
+                self.field_data
+                    .extend_from_slice(&value.str_ref.to_le_bytes());
+                self.field_data
+                    .extend_from_slice(&(strings.len() as u32).to_le_bytes());
+                for (id, bytes) in strings {
+                    self.field_data.extend_from_slice(&id.to_le_bytes());
+                    self.field_data
+                        .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                    self.field_data.extend_from_slice(&bytes);
+                }
+                field_data_offset
+            }
+            GffValue::Void(value) => {
+                self.field_data
+                    .extend_from_slice(&(value.len() as u32).to_le_bytes());
+                self.field_data.extend_from_slice(value);
+                field_data_offset
+            }
+            GffValue::Orientation(value) => {
+                for component in value {
+                    self.field_data.extend_from_slice(&component.to_le_bytes());
+                }
+                field_data_offset
+            }
+            GffValue::Vector(value) => {
+                for component in value {
+                    self.field_data.extend_from_slice(&component.to_le_bytes());
+                }
+                field_data_offset
+            }
+            GffValue::Struct(value) => self.write_struct(value)?,
+            GffValue::List(value) => {
+                let start = self.list_indices.len();
+                self.list_indices.push(value.len() as u32);
+                self.list_indices.resize(start + 1 + value.len(), 0);
+
+                for (position, gff_struct) in value.iter().enumerate() {
+                    self.list_indices[start + 1 + position] = self.write_struct(gff_struct)?;
+                }
+
+                (start * 4) as u32
+            }
+        };
+
+        self.fields[index].data_or_offset = data_or_offset;
+
+        Ok(index as u32)
+    }
+
+    fn label_index(&mut self, label: &str) -> Result<u32> {
+        let bytes = encode_string(label);
+        if bytes.len() > 16 {
+            return Err(Error::InvalidData(format!(
+                "GFF label {} is longer than 16 bytes",
+                label
+            )));
+        }
+
+        let mut padded = [0u8; 16];
+        padded[..bytes.len()].copy_from_slice(&bytes);
+
+        let index = match self.labels.iter().position(|existing| *existing == padded) {
+            Some(index) => index,
+            None => {
+                self.labels.push(padded);
+                self.labels.len() - 1
+            }
+        };
+
+        Ok(index as u32)
+    }
+
+    fn finish(self, file_type: [u8; 4]) -> GffData {
+        let struct_offset = HEADER_SIZE;
+        let field_offset = struct_offset + 12 * self.structs.len() as u32;
+        let label_offset = field_offset + 12 * self.fields.len() as u32;
+        let field_data_offset = label_offset + 16 * self.labels.len() as u32;
+        let field_indices_offset = field_data_offset + self.field_data.len() as u32;
+        let list_indices_offset = field_indices_offset + 4 * self.field_indices.len() as u32;
+
+        GffData {
+            header: GffHeader {
+                file_type,
+                version: *b"V3.2",
+                struct_offset,
+                struct_count: self.structs.len() as u32,
+                field_offset,
+                field_count: self.fields.len() as u32,
+                label_offset,
+                label_count: self.labels.len() as u32,
+                field_data_offset,
+                field_data_size: self.field_data.len() as u32,
+                field_indices_offset,
+                field_indices_size: 4 * self.field_indices.len() as u32,
+                list_indices_offset,
+                list_indices_size: 4 * self.list_indices.len() as u32,
+            },
+            structs: self.structs,
+            fields: self.fields,
+            labels: self.labels,
+            field_data: self.field_data,
+            field_indices: self.field_indices,
+            list_indices: self.list_indices,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(label: &str, value: GffValue) -> GffField {
+        GffField {
+            label: label.to_owned(),
+            value,
+        }
+    }
+
+    fn sample_gff() -> Gff {
+        let mut item = GffStruct::new(0);
+        item.set(
+            "TemplateResRef",
+            GffValue::ResRef("g_w_lghtsbr01".to_owned()),
+        );
+        item.set("StackSize", GffValue::Word(1));
+
+        let mut second_item = GffStruct::new(1);
+        second_item.set(
+            "TemplateResRef",
+            GffValue::ResRef("g_i_medeqpmnt01".to_owned()),
+        );
+        second_item.set("StackSize", GffValue::Word(12));
+
+        let mut single = GffStruct::new(7);
+        single.set("Only", GffValue::Int(-1));
+
+        let root = GffStruct {
+            id: u32::MAX,
+            fields: vec![
+                field("Byte", GffValue::Byte(200)),
+                field("Char", GffValue::Char(-5)),
+                field("Word", GffValue::Word(65000)),
+                field("Short", GffValue::Short(-1234)),
+                field("Dword", GffValue::Dword(4_000_000_000)),
+                field("Int", GffValue::Int(-123_456)),
+                field("Dword64", GffValue::Dword64(u64::MAX - 1)),
+                field("Int64", GffValue::Int64(i64::MIN + 1)),
+                field("Float", GffValue::Float(1.5)),
+                field("Double", GffValue::Double(-2.25)),
+                field(
+                    "String",
+                    GffValue::CExoString("Dantooine \u{e9}".to_owned()),
+                ),
+                field("ResRef", GffValue::ResRef("p_bastila".to_owned())),
+                field(
+                    "LocString",
+                    GffValue::CExoLocString(ExoLocString {
+                        str_ref: 42,
+                        strings: vec![
+                            ExoLocSubString {
+                                id: 0,
+                                string: "Hello".to_owned(),
+                            },
+                            ExoLocSubString {
+                                id: 5,
+                                string: "Bonjour".to_owned(),
+                            },
+                        ],
+                    }),
+                ),
+                field("Void", GffValue::Void(vec![0, 1, 2, 3, 255])),
+                field("Orientation", GffValue::Orientation([0.0, 0.5, 1.0, -1.0])),
+                field("Vector", GffValue::Vector([10.0, -20.5, 0.25])),
+                field("Single", GffValue::Struct(single)),
+                field("EmptyStruct", GffValue::Struct(GffStruct::new(3))),
+                field("ItemList", GffValue::List(vec![item, second_item])),
+                field("EmptyList", GffValue::List(Vec::new())),
+            ],
+        };
+
+        Gff {
+            file_type: "UTC ".to_owned(),
+            root,
+        }
+    }
+
+    #[test]
+    fn round_trip_preserves_tree() {
+        let gff = sample_gff();
+
+        let bytes = gff.to_bytes().unwrap();
+        let parsed = Gff::from_bytes(&bytes).unwrap();
+
+        assert_eq!(parsed, gff);
+    }
+
+    #[test]
+    fn rewrite_is_stable() {
+        let bytes = sample_gff().to_bytes().unwrap();
+
+        let rewritten = Gff::from_bytes(&bytes).unwrap().to_bytes().unwrap();
+
+        assert_eq!(rewritten, bytes);
+    }
+
+    #[test]
+    fn labels_are_shared_between_fields() {
+        let bytes = sample_gff().to_bytes().unwrap();
+        let data = GffData::read(&mut Cursor::new(&bytes)).unwrap();
+
+        let template_labels = data
+            .labels
+            .iter()
+            .filter(|label| label.starts_with(b"TemplateResRef"))
+            .count();
+
+        assert_eq!(template_labels, 1);
+        assert_eq!(data.header.label_count as usize, data.labels.len());
+    }
+
+    #[test]
+    fn reads_from_reader_position() {
+        let mut bytes = b"padding".to_vec();
+        bytes.extend(sample_gff().to_bytes().unwrap());
+
+        let mut cursor = Cursor::new(bytes);
+        cursor.set_position(7);
+
+        assert_eq!(Gff::from_reader(&mut cursor).unwrap(), sample_gff());
+    }
+
+    #[test]
+    fn set_int_keeps_field_type() {
+        let mut gff = sample_gff();
+
+        assert!(gff.root.set_int("Word", 7));
+        assert!(gff.root.set_int("Int64", -9));
+        assert!(!gff.root.set_int("Float", 1));
+        assert!(!gff.root.set_int("Missing", 1));
+
+        let parsed = Gff::from_bytes(&gff.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.root.get("Word"), Some(&GffValue::Word(7)));
+        assert_eq!(parsed.root.get("Int64"), Some(&GffValue::Int64(-9)));
+        assert_eq!(parsed.root.get("Float"), Some(&GffValue::Float(1.5)));
+        assert_eq!(parsed.root.get("Missing"), None);
+    }
+
+    #[test]
+    fn update_int_adds_missing_fields() {
+        let mut gff = sample_gff();
+
+        gff.root.update_int("Dword", GffValue::Int(12)).unwrap();
+        gff.root.update_int("Added", GffValue::Short(-3)).unwrap();
+
+        let parsed = Gff::from_bytes(&gff.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.root.get("Dword"), Some(&GffValue::Dword(12)));
+        assert_eq!(parsed.root.get("Added"), Some(&GffValue::Short(-3)));
+    }
+
+    #[test]
+    fn update_int_refuses_other_field_types() {
+        let mut gff = sample_gff();
+
+        assert!(matches!(
+            gff.root.update_int("String", GffValue::Byte(1)),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(
+            gff.root.update_int("Added", GffValue::Float(1.0)),
+            Err(Error::InvalidData(_))
+        ));
+
+        assert_eq!(gff, sample_gff());
+    }
+
+    #[test]
+    fn rejects_overlong_labels() {
+        let mut gff = sample_gff();
+        gff.root.set("ThisLabelIsFarTooLong", GffValue::Byte(0));
+
+        assert!(matches!(gff.to_bytes(), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut bytes = sample_gff().to_bytes().unwrap();
+        bytes[4..8].copy_from_slice(b"V4.0");
+
+        assert!(matches!(Gff::from_bytes(&bytes), Err(Error::MissingHeader)));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+
+    /// A struct holding `fields` in order.
+    pub(crate) fn gff_struct(id: u32, fields: &[(&str, GffValue)]) -> GffStruct {
+        let mut gff_struct = GffStruct::new(id);
+        for (label, value) in fields {
+            gff_struct.set(label, value.clone());
+        }
+
+        gff_struct
+    }
+
+    /// Writes `root` as a GFF of `file_type` and parses it back.
+    pub(crate) fn round_trip(file_type: &str, root: &GffStruct) -> GffStruct {
+        let gff = Gff {
+            file_type: file_type.to_owned(),
+            root: root.clone(),
+        };
+
+        Gff::from_bytes(&gff.to_bytes().unwrap()).unwrap().root
+    }
+}
@@ -0,0 +1,443 @@
+use crate::gff::{Gff, GffStruct, GffValue};
+use crate::{Error, Result};
+use bitvec::prelude::*;
+
+/// Each location is stored as a position followed by a facing vector.
+const LOCATION_SIZE: usize = 6 * 4;
+
+/// A location global: where something is and which way it faces.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Location {
+    pub position: [f32; 3],
+    pub orientation: [f32; 3],
+}
+
+/// The plot variables stored in `globalvars.res`.
+///
+/// Every kind of variable is stored as a `Cat*` list holding the names and a
+/// `Val*` field holding the values in the same order. Booleans are packed
+/// eight to a byte, most significant bit first, and numbers take one byte each.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlobalVars {
+    /// The GFF the variables were read from, kept for any fields not modelled here.
+    pub gff: Gff,
+    pub booleans: Vec<(String, bool)>,
+    pub numbers: Vec<(String, u8)>,
+    pub strings: Vec<(String, String)>,
+    pub locations: Vec<(String, Location)>,
+}
+
+impl GlobalVars {
+    pub fn new(gff: Gff) -> Result<Self> {
+        let booleans = {
+            let names = Self::read_names(&gff.root, "CatBoolean");
+            let values = Self::read_void(&gff.root, "ValBoolean");
+            let bits = values.view_bits::<Msb0>();
+
+            names
+                .into_iter()
+                .enumerate()
+                .map(|(idx, name)| (name, bits.get(idx).is_some_and(|bit| *bit)))
+                .collect()
+        };
+
+        let numbers = {
+            let names = Self::read_names(&gff.root, "CatNumber");
+            let values = Self::read_void(&gff.root, "ValNumber");
+
+            names
+                .into_iter()
+                .enumerate()
+                .map(|(idx, name)| (name, values.get(idx).copied().unwrap_or_default()))
+                .collect()
+        };
+
+        let strings = {
+            let names = Self::read_names(&gff.root, "CatString");
+            let values = gff.root.get_list("ValString");
+
+            names
+                .into_iter()
+                .enumerate()
+                .map(|(idx, name)| {
+                    let value = values
+                        .and_then(|list| list.get(idx))
+                        .and_then(|entry| entry.get_string("String"))
+                        .unwrap_or_default()
+                        .to_owned();
+                    (name, value)
+                })
+                .collect()
+        };
+
+        let locations = {
+            let names = Self::read_names(&gff.root, "CatLocation");
+            let values = Self::read_void(&gff.root, "ValLocation");
+
+            let mut locations = Vec::with_capacity(names.len());
+            for (idx, name) in names.into_iter().enumerate() {
+                let start = idx * LOCATION_SIZE;
+                let bytes = values.get(start..start + LOCATION_SIZE).ok_or_else(|| {
+                    Error::InvalidData(format!("no value stored for location {}", name))
+                })?;
+
+                let mut floats = bytes
+                    .chunks_exact(4)
+                    .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()));
+                let mut location = Location::default();
+                for value in location
+                    .position
+                    .iter_mut()
+                    .chain(location.orientation.iter_mut())
+                {
+                    *value = floats.next().unwrap();
+                }
+
+                locations.push((name, location));
+            }
+
+            locations
+        };
+
+        Ok(GlobalVars {
+            gff,
+            booleans,
+            numbers,
+            strings,
+            locations,
+        })
+    }
+
+    fn read_names(root: &GffStruct, label: &str) -> Vec<String> {
+        root.get_list(label)
+            .map(|list| {
+                list.iter()
+                    .map(|entry| entry.get_string("Name").unwrap_or_default().to_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn read_void<'a>(root: &'a GffStruct, label: &str) -> &'a [u8] {
+        match root.get(label) {
+            Some(GffValue::Void(bytes)) => bytes,
+            _ => &[],
+        }
+    }
+
+    fn find<'a, T>(variables: &'a [(String, T)], name: &str) -> Option<&'a T> {
+        variables
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Sets a variable, appending it if the save does not know about it yet.
+    fn upsert<T>(variables: &mut Vec<(String, T)>, name: &str, value: T) {
+        match variables
+            .iter_mut()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+        {
+            Some((_, existing)) => *existing = value,
+            None => variables.push((name.to_owned(), value)),
+        }
+    }
+
+    pub fn get_boolean(&self, name: &str) -> Option<bool> {
+        Self::find(&self.booleans, name).copied()
+    }
+
+    pub fn set_boolean(&mut self, name: &str, value: bool) {
+        Self::upsert(&mut self.booleans, name, value);
+    }
+
+    pub fn get_number(&self, name: &str) -> Option<u8> {
+        Self::find(&self.numbers, name).copied()
+    }
+
+    pub fn set_number(&mut self, name: &str, value: u8) {
+        Self::upsert(&mut self.numbers, name, value);
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        Self::find(&self.strings, name).map(String::as_str)
+    }
+
+    pub fn set_string(&mut self, name: &str, value: &str) {
+        Self::upsert(&mut self.strings, name, value.to_owned());
+    }
+
+    pub fn get_location(&self, name: &str) -> Option<Location> {
+        Self::find(&self.locations, name).copied()
+    }
+
+    pub fn set_location(&mut self, name: &str, value: Location) {
+        Self::upsert(&mut self.locations, name, value);
+    }
+
+    fn write_names(root: &mut GffStruct, label: &str, names: impl Iterator<Item = String>) {
+        let list = names
+            .map(|name| {
+                let mut entry = GffStruct::new(0);
+                entry.set("Name", GffValue::CExoString(name));
+                entry
+            })
+            .collect();
+
+        root.set(label, GffValue::List(list));
+    }
+
+    /// Stores `values` as the whole field, dropping any bytes of a longer original.
+    fn write_void(root: &mut GffStruct, label: &str, values: Vec<u8>) {
+        root.set(label, GffValue::Void(values));
+    }
+
+    /// Encodes the variables back into a `globalvars.res` GFF.
+    pub fn to_gff(&self) -> Gff {
+        let mut gff = self.gff.clone();
+        let root = &mut gff.root;
+
+        let mut bits: BitVec<u8, Msb0> = BitVec::with_capacity(self.booleans.len());
+        bits.extend(self.booleans.iter().map(|(_, value)| *value));
+        Self::write_names(
+            root,
+            "CatBoolean",
+            self.booleans.iter().map(|(name, _)| name.clone()),
+        );
+        Self::write_void(root, "ValBoolean", bits.into_vec());
+
+        Self::write_names(
+            root,
+            "CatNumber",
+            self.numbers.iter().map(|(name, _)| name.clone()),
+        );
+        Self::write_void(
+            root,
+            "ValNumber",
+            self.numbers.iter().map(|(_, value)| *value).collect(),
+        );
+
+        if !self.strings.is_empty() || root.get("CatString").is_some() {
+            Self::write_names(
+                root,
+                "CatString",
+                self.strings.iter().map(|(name, _)| name.clone()),
+            );
+
+            let values = self
+                .strings
+                .iter()
+                .map(|(_, value)| {
+                    let mut entry = GffStruct::new(0);
+                    entry.set("String", GffValue::CExoString(value.clone()));
+                    entry
+                })
+                .collect();
+            root.set("ValString", GffValue::List(values));
+        }
+
+        if !self.locations.is_empty() || root.get("CatLocation").is_some() {
+            Self::write_names(
+                root,
+                "CatLocation",
+                self.locations.iter().map(|(name, _)| name.clone()),
+            );
+
+            let values = self
+                .locations
+                .iter()
+                .flat_map(|(_, location)| location.position.iter().chain(&location.orientation))
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+            Self::write_void(root, "ValLocation", values);
+        }
+
+        gff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gff::test_support::{gff_struct, round_trip};
+
+    fn names(values: &[&str]) -> GffValue {
+        GffValue::List(
+            values
+                .iter()
+                .map(|name| gff_struct(0, &[("Name", GffValue::CExoString((*name).to_owned()))]))
+                .collect(),
+        )
+    }
+
+    fn sample_gff() -> Gff {
+        let mut location = Vec::new();
+        for value in [1.0f32, 2.5, -3.0, 0.0, 1.0, 0.0] {
+            location.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut root = GffStruct::new(u32::MAX);
+        root.set(
+            "CatBoolean",
+            names(&[
+                "K_FIRST",
+                "K_SECOND",
+                "K_THIRD",
+                "K_FOURTH",
+                "K_FIFTH",
+                "K_SIXTH",
+                "K_SEVENTH",
+                "K_EIGHTH",
+                "K_NINTH",
+            ]),
+        );
+        root.set(
+            "ValBoolean",
+            GffValue::Void(vec![0b1010_0000, 0b1000_0000, 0, 0]),
+        );
+        root.set("CatNumber", names(&["K_CREDITS", "K_STATE"]));
+        root.set("ValNumber", GffValue::Void(vec![200, 3, 0, 0]));
+        root.set("CatLocation", names(&["K_SPAWN"]));
+        root.set("ValLocation", GffValue::Void(location));
+
+        Gff {
+            file_type: "GVT ".to_owned(),
+            root,
+        }
+    }
+
+    /// Writes the variables out and reads them back.
+    fn reread(global_vars: &GlobalVars) -> GlobalVars {
+        let gff = global_vars.to_gff();
+
+        GlobalVars::new(Gff {
+            root: round_trip(&gff.file_type, &gff.root),
+            ..gff
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn booleans_are_most_significant_bit_first() {
+        let global_vars = GlobalVars::new(sample_gff()).unwrap();
+
+        let set: Vec<&str> = global_vars
+            .booleans
+            .iter()
+            .filter(|(_, value)| *value)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(set, ["K_FIRST", "K_THIRD", "K_NINTH"]);
+        assert_eq!(global_vars.get_boolean("k_second"), Some(false));
+        assert_eq!(global_vars.get_boolean("K_MISSING"), None);
+    }
+
+    #[test]
+    fn reads_numbers_and_locations() {
+        let global_vars = GlobalVars::new(sample_gff()).unwrap();
+
+        assert_eq!(global_vars.get_number("K_CREDITS"), Some(200));
+        assert_eq!(global_vars.get_number("K_STATE"), Some(3));
+        assert_eq!(
+            global_vars.get_location("K_SPAWN"),
+            Some(Location {
+                position: [1.0, 2.5, -3.0],
+                orientation: [0.0, 1.0, 0.0],
+            })
+        );
+    }
+
+    #[test]
+    fn edits_match_names_ignoring_case() {
+        let mut global_vars = GlobalVars::new(sample_gff()).unwrap();
+        global_vars.set_boolean("k_first", false);
+        global_vars.set_boolean("K_EIGHTH", true);
+        global_vars.set_number("k_state", 7);
+        global_vars.set_number("K_NEW", 1);
+
+        // Existing variables keep their stored name and position; new ones are appended.
+        assert_eq!(global_vars.booleans[0], ("K_FIRST".to_owned(), false));
+        assert_eq!(global_vars.booleans.len(), 9);
+        assert_eq!(
+            global_vars.numbers,
+            [
+                ("K_CREDITS".to_owned(), 200),
+                ("K_STATE".to_owned(), 7),
+                ("K_NEW".to_owned(), 1),
+            ]
+        );
+
+        let gff = global_vars.to_gff();
+        assert_eq!(
+            gff.root.get("ValBoolean"),
+            Some(&GffValue::Void(vec![0b0010_0001, 0b1000_0000]))
+        );
+        assert_eq!(
+            gff.root.get("ValNumber"),
+            Some(&GffValue::Void(vec![200, 7, 1]))
+        );
+    }
+
+    #[test]
+    fn strings_and_locations_are_written_when_used() {
+        let mut gff = sample_gff();
+        gff.root.remove("CatLocation");
+        gff.root.remove("ValLocation");
+        let mut global_vars = GlobalVars::new(gff).unwrap();
+
+        let written = global_vars.to_gff();
+        assert!(written.root.get("CatString").is_none());
+        assert!(written.root.get("CatLocation").is_none());
+
+        global_vars.set_string("K_NAME", "Revan");
+        global_vars.set_location(
+            "K_SPAWN",
+            Location {
+                position: [4.0, 5.0, 6.0],
+                orientation: [-1.0, 0.0, 0.0],
+            },
+        );
+
+        let parsed = reread(&global_vars);
+        assert_eq!(parsed.get_string("k_name"), Some("Revan"));
+        assert_eq!(
+            parsed.get_location("K_SPAWN"),
+            global_vars.get_location("K_SPAWN")
+        );
+        assert_eq!(parsed.booleans, global_vars.booleans);
+        assert_eq!(parsed.numbers, global_vars.numbers);
+    }
+
+    #[test]
+    fn removing_variables_shrinks_values() {
+        let mut global_vars = GlobalVars::new(sample_gff()).unwrap();
+        global_vars.booleans.truncate(2);
+        global_vars.numbers.clear();
+        global_vars.locations.clear();
+
+        let parsed = reread(&global_vars);
+        assert_eq!(
+            parsed.gff.root.get("ValBoolean"),
+            Some(&GffValue::Void(vec![0b1000_0000]))
+        );
+        assert_eq!(
+            parsed.gff.root.get("ValNumber"),
+            Some(&GffValue::Void(Vec::new()))
+        );
+        assert_eq!(
+            parsed.gff.root.get("ValLocation"),
+            Some(&GffValue::Void(Vec::new()))
+        );
+        assert_eq!(parsed.booleans, global_vars.booleans);
+        assert!(parsed.locations.is_empty());
+    }
+
+    #[test]
+    fn rejects_missing_location_values() {
+        let mut gff = sample_gff();
+        gff.root
+            .set("ValLocation", GffValue::Void(vec![0; LOCATION_SIZE - 1]));
+
+        assert!(matches!(GlobalVars::new(gff), Err(Error::InvalidData(_))));
+    }
+}
@@ -0,0 +1,302 @@
+use crate::gff::{Gff, GffStruct, GffValue};
+use crate::resource_manager::ResourceManager;
+use crate::tlk::TalkTables;
+use crate::{Error, Result};
+
+/// A single stack of items, as stored in an inventory or a `.uti` blueprint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub gff_struct: GffStruct,
+}
+
+impl Item {
+    pub fn new(gff_struct: GffStruct) -> Self {
+        Item { gff_struct }
+    }
+
+    /// Creates an item from a `.uti` blueprint.
+    pub fn from_blueprint(uti: Gff) -> Self {
+        let mut gff_struct = uti.root;
+        gff_struct.id = 0;
+
+        let mut item = Item { gff_struct };
+        if item.gff_struct.get("StackSize").is_none() {
+            item.gff_struct.set("StackSize", GffValue::Word(1));
+        }
+
+        item
+    }
+
+    /// The blueprint the item was created from, e.g. `"g_w_lghtsbr01"`.
+    pub fn get_template_resref(&self) -> Option<&str> {
+        self.gff_struct.get_string("TemplateResRef")
+    }
+
+    pub fn get_tag(&self) -> Option<&str> {
+        self.gff_struct.get_string("Tag")
+    }
+
+    /// The name shown in game, resolved through the talk tables if needed.
+    pub fn get_name(&self, talk_tables: &TalkTables) -> Option<String> {
+        self.gff_struct
+            .get_loc_string("LocalizedName")
+            .and_then(|name| talk_tables.resolve(name, false))
+    }
+
+    /// The row in `baseitems.2da`.
+    pub fn get_base_item(&self) -> i32 {
+        self.gff_struct.get_int("BaseItem").unwrap_or_default() as i32
+    }
+
+    pub fn get_stack_size(&self) -> u16 {
+        self.gff_struct.get_int("StackSize").unwrap_or(1) as u16
+    }
+
+    pub fn set_stack_size(&mut self, stack_size: u16) -> Result<()> {
+        self.gff_struct
+            .update_int("StackSize", GffValue::Word(stack_size))?;
+
+        Ok(())
+    }
+}
+
+/// A typed view over `inventory.res`, the party's shared inventory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inventory {
+    pub gff: Gff,
+}
+
+impl Inventory {
+    pub fn new(gff: Gff) -> Self {
+        Inventory { gff }
+    }
+
+    fn get_item_list(&self) -> &[GffStruct] {
+        self.gff
+            .root
+            .get_list("ItemList")
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn get_item_mut(&mut self, index: usize) -> Result<&mut GffStruct> {
+        self.gff
+            .root
+            .get_list_mut("ItemList")
+            .and_then(|list| list.get_mut(index))
+            .ok_or_else(|| Error::InvalidData(format!("inventory has no item {}", index)))
+    }
+
+    pub fn get_items(&self) -> Vec<Item> {
+        self.get_item_list()
+            .iter()
+            .cloned()
+            .map(Item::new)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.get_item_list().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.get_item_list().is_empty()
+    }
+
+    /// Appends an item, returning its index.
+    pub fn add_item(&mut self, item: Item) -> usize {
+        match self.gff.root.get_list_mut("ItemList") {
+            Some(list) => {
+                list.push(item.gff_struct);
+                list.len() - 1
+            }
+            None => {
+                self.gff
+                    .root
+                    .set("ItemList", GffValue::List(vec![item.gff_struct]));
+                0
+            }
+        }
+    }
+
+    /// Adds a stack of the blueprint `resref`, e.g. `"g_i_medeqpmnt01"`, from the installation.
+    pub fn add_blueprint(
+        &mut self,
+        resource_manager: &mut ResourceManager,
+        resref: &str,
+        stack_size: u16,
+    ) -> Result<usize> {
+        let data = resource_manager.get_resource(&format!("{}.uti", resref))?;
+
+        let mut item = Item::from_blueprint(Gff::from_bytes(&data)?);
+        item.set_stack_size(stack_size)?;
+
+        Ok(self.add_item(item))
+    }
+
+    pub fn remove_item(&mut self, index: usize) -> Result<Item> {
+        let list = self
+            .gff
+            .root
+            .get_list_mut("ItemList")
+            .filter(|list| index < list.len())
+            .ok_or_else(|| Error::InvalidData(format!("inventory has no item {}", index)))?;
+
+        Ok(Item::new(list.remove(index)))
+    }
+
+    pub fn set_stack_size(&mut self, index: usize, stack_size: u16) -> Result<()> {
+        self.get_item_mut(index)?
+            .update_int("StackSize", GffValue::Word(stack_size))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gff::test_support::{gff_struct, round_trip};
+    use crate::rim::Rim;
+    use std::fs;
+
+    fn blueprint(tag: &str, stack_size: Option<u16>) -> Gff {
+        let mut root = gff_struct(
+            u32::MAX,
+            &[
+                (
+                    "TemplateResRef",
+                    GffValue::ResRef("g_i_medeqpmnt01".to_owned()),
+                ),
+                ("Tag", GffValue::CExoString(tag.to_owned())),
+                ("BaseItem", GffValue::Int(53)),
+            ],
+        );
+        if let Some(stack_size) = stack_size {
+            root.set("StackSize", GffValue::Word(stack_size));
+        }
+
+        Gff {
+            file_type: "UTI ".to_owned(),
+            root,
+        }
+    }
+
+    fn empty_inventory() -> Inventory {
+        Inventory::new(Gff {
+            file_type: "INV ".to_owned(),
+            root: GffStruct::new(u32::MAX),
+        })
+    }
+
+    #[test]
+    fn blueprints_become_list_entries() {
+        let item = Item::from_blueprint(blueprint("G_I_MEDEQPMNT01", None));
+
+        // The blueprint root becomes an element of ItemList, so it loses the root ID.
+        assert_eq!(item.gff_struct.id, 0);
+        assert_eq!(item.get_tag(), Some("G_I_MEDEQPMNT01"));
+        assert_eq!(item.get_base_item(), 53);
+        assert_eq!(item.get_stack_size(), 1);
+        assert_eq!(item.gff_struct.get("StackSize"), Some(&GffValue::Word(1)));
+
+        let stack = Item::from_blueprint(blueprint("G_I_MEDEQPMNT01", Some(5)));
+        assert_eq!(stack.get_stack_size(), 5);
+    }
+
+    #[test]
+    fn add_blueprint_resolves_through_the_resource_manager() {
+        let installation = tempfile::tempdir().unwrap();
+        let modules_path = installation.path().join("modules");
+        fs::create_dir(&modules_path).unwrap();
+        let mut rim = Rim::default();
+        rim.add_resource(
+            "g_i_medeqpmnt01.uti",
+            blueprint("MODULE", None).to_bytes().unwrap(),
+        )
+        .unwrap();
+        rim.add_resource(
+            "g_i_credits001.uti",
+            blueprint("MODULE_ONLY", None).to_bytes().unwrap(),
+        )
+        .unwrap();
+        rim.save(&modules_path.join("danm13.rim").to_string_lossy())
+            .unwrap();
+
+        let override_path = installation.path().join("Override");
+        fs::create_dir(&override_path).unwrap();
+        blueprint("OVERRIDE", Some(7))
+            .save(&override_path.join("G_I_MedEqpMnt01.uti").to_string_lossy())
+            .unwrap();
+
+        let mut resource_manager = ResourceManager::new(installation.path()).unwrap();
+        resource_manager.load_module("danm13").unwrap();
+        let mut inventory = empty_inventory();
+
+        // The override copy wins over the module, and the requested stack size
+        // replaces the one in the blueprint.
+        assert_eq!(
+            inventory
+                .add_blueprint(&mut resource_manager, "g_i_medeqpmnt01", 3)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            inventory
+                .add_blueprint(&mut resource_manager, "g_i_credits001", 2)
+                .unwrap(),
+            1
+        );
+        assert!(inventory
+            .add_blueprint(&mut resource_manager, "missing", 1)
+            .is_err());
+        assert_eq!(inventory.len(), 2);
+
+        let inventory = Inventory::new(Gff {
+            file_type: "INV ".to_owned(),
+            root: round_trip("INV ", &inventory.gff.root),
+        });
+        let items = inventory.get_items();
+        assert_eq!(items[0].get_tag(), Some("OVERRIDE"));
+        assert_eq!(items[0].get_stack_size(), 3);
+        assert_eq!(items[1].get_tag(), Some("MODULE_ONLY"));
+        assert_eq!(items[1].get_stack_size(), 2);
+    }
+
+    #[test]
+    fn edits_need_an_existing_item() {
+        let mut inventory = empty_inventory();
+        assert!(inventory.is_empty());
+        assert!(inventory.remove_item(0).is_err());
+        assert!(inventory.set_stack_size(0, 2).is_err());
+
+        inventory.add_item(Item::from_blueprint(blueprint("FIRST", None)));
+        inventory.add_item(Item::from_blueprint(blueprint("SECOND", None)));
+        inventory.set_stack_size(1, 12).unwrap();
+        assert!(inventory.set_stack_size(2, 1).is_err());
+
+        // Removing shifts the later items down.
+        assert_eq!(inventory.remove_item(0).unwrap().get_tag(), Some("FIRST"));
+        let items = inventory.get_items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].get_tag(), Some("SECOND"));
+        assert_eq!(items[0].get_stack_size(), 12);
+    }
+
+    #[test]
+    fn stack_size_keeps_its_field_type() {
+        let mut item = Item::new(gff_struct(0, &[("StackSize", GffValue::Dword(4))]));
+        item.set_stack_size(9).unwrap();
+        assert_eq!(item.gff_struct.get("StackSize"), Some(&GffValue::Dword(9)));
+
+        let mut item = Item::new(gff_struct(
+            0,
+            &[("StackSize", GffValue::CExoString("4".to_owned()))],
+        ));
+        assert!(item.set_stack_size(9).is_err());
+        assert_eq!(
+            item.gff_struct.get("StackSize"),
+            Some(&GffValue::CExoString("4".to_owned()))
+        );
+    }
+}
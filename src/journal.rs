@@ -0,0 +1,238 @@
+use crate::gff::{ExoLocString, Gff};
+use crate::resource_manager::ResourceManager;
+use crate::tlk::TalkTables;
+use crate::Result;
+
+const GLOBAL_JOURNAL: &str = "global.jrl";
+
+/// A quest's progress as recorded in the save's `JNL_Entries`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct JournalEntry {
+    /// The tag of the quest in `global.jrl`.
+    pub plot_id: String,
+    /// The ID of the quest's current entry in `global.jrl`.
+    pub state: i32,
+    /// The in-game day the quest last changed.
+    pub date: u32,
+    /// The in-game time of day, in milliseconds, the quest last changed.
+    pub time: u32,
+}
+
+impl JournalEntry {
+    /// Whether the quest is in a state that finishes it.
+    pub fn is_completed(&self, journal: &Journal) -> bool {
+        journal
+            .get_quest(&self.plot_id)
+            .and_then(|quest| quest.get_state(self.state))
+            .is_some_and(|state| state.end)
+    }
+}
+
+/// One of the entries a quest can show, e.g. "Find the Sith codes".
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuestState {
+    pub id: i32,
+    /// Whether reaching this entry completes the quest.
+    pub end: bool,
+    pub text: ExoLocString,
+}
+
+impl QuestState {
+    pub fn get_text(&self, talk_tables: &TalkTables) -> Option<String> {
+        talk_tables.resolve(&self.text, false)
+    }
+}
+
+/// A quest definition from a journal file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quest {
+    pub tag: String,
+    pub name: ExoLocString,
+    pub states: Vec<QuestState>,
+}
+
+impl Quest {
+    pub fn get_name(&self, talk_tables: &TalkTables) -> Option<String> {
+        talk_tables.resolve(&self.name, false)
+    }
+
+    pub fn get_state(&self, id: i32) -> Option<&QuestState> {
+        self.states.iter().find(|state| state.id == id)
+    }
+}
+
+/// A journal file such as `global.jrl`, defining every quest and its entries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Journal {
+    pub quests: Vec<Quest>,
+}
+
+impl Journal {
+    pub fn new(gff: &Gff) -> Self {
+        let quests = gff
+            .root
+            .get_list("Categories")
+            .map(|categories| {
+                categories
+                    .iter()
+                    .map(|category| Quest {
+                        tag: category.get_string("Tag").unwrap_or_default().to_owned(),
+                        name: category.get_loc_string("Name").cloned().unwrap_or_default(),
+                        states: category
+                            .get_list("EntryList")
+                            .map(|entries| {
+                                entries
+                                    .iter()
+                                    .map(|entry| QuestState {
+                                        id: entry.get_int("ID").unwrap_or_default() as i32,
+                                        end: entry.get_int("End").unwrap_or_default() != 0,
+                                        text: entry
+                                            .get_loc_string("Text")
+                                            .cloned()
+                                            .unwrap_or_default(),
+                                    })
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Journal { quests }
+    }
+
+    /// Loads `global.jrl` from the installation.
+    pub fn from_installation(resource_manager: &mut ResourceManager) -> Result<Self> {
+        let data = resource_manager.get_resource(GLOBAL_JOURNAL)?;
+
+        Ok(Journal::new(&Gff::from_bytes(&data)?))
+    }
+
+    pub fn get_quest(&self, tag: &str) -> Option<&Quest> {
+        self.quests
+            .iter()
+            .find(|quest| quest.tag.eq_ignore_ascii_case(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+    use crate::gff::test_support::{gff_struct, round_trip};
+    use crate::gff::{ExoLocSubString, GffStruct, GffValue};
+    use crate::party_table::PartyTable;
+
+    fn text(string: &str) -> GffValue {
+        GffValue::CExoLocString(ExoLocString {
+            str_ref: u32::MAX,
+            strings: vec![ExoLocSubString {
+                id: 0,
+                string: string.to_owned(),
+            }],
+        })
+    }
+
+    fn sample_journal() -> Gff {
+        let entries = [(10, false, "Find the codes"), (20, true, "Escaped Taris")]
+            .into_iter()
+            .map(|(id, end, string)| {
+                gff_struct(
+                    1,
+                    &[
+                        ("ID", GffValue::Dword(id)),
+                        ("End", GffValue::Word(end.into())),
+                        ("Text", text(string)),
+                    ],
+                )
+            })
+            .collect();
+
+        let category = gff_struct(
+            0,
+            &[
+                ("Tag", GffValue::CExoString("tar_escape".to_owned())),
+                ("Name", text("Escape from Taris")),
+                ("EntryList", GffValue::List(entries)),
+            ],
+        );
+
+        Gff {
+            file_type: "JRL ".to_owned(),
+            root: gff_struct(u32::MAX, &[("Categories", GffValue::List(vec![category]))]),
+        }
+    }
+
+    fn empty_party_table() -> PartyTable {
+        PartyTable::new(
+            Gff {
+                file_type: "PT  ".to_owned(),
+                root: GffStruct::new(u32::MAX),
+            },
+            Game::Kotor,
+        )
+    }
+
+    #[test]
+    fn reads_quests() {
+        let gff = sample_journal();
+        let journal = Journal::new(&Gff {
+            root: round_trip(&gff.file_type, &gff.root),
+            ..gff
+        });
+        let talk_tables = TalkTables::default();
+
+        let quest = journal.get_quest("TAR_ESCAPE").unwrap();
+        assert_eq!(
+            quest.get_name(&talk_tables).as_deref(),
+            Some("Escape from Taris")
+        );
+        assert_eq!(quest.states.len(), 2);
+        assert!(!quest.get_state(10).unwrap().end);
+        assert_eq!(
+            quest
+                .get_state(20)
+                .unwrap()
+                .get_text(&talk_tables)
+                .as_deref(),
+            Some("Escaped Taris")
+        );
+        assert!(quest.get_state(30).is_none());
+        assert!(journal.get_quest("missing").is_none());
+    }
+
+    #[test]
+    fn quest_state_round_trip() {
+        let journal = Journal::new(&sample_journal());
+        let mut party_table = empty_party_table();
+
+        party_table.set_quest_state("tar_escape", 10).unwrap();
+        party_table.set_quest_state("dan_ruins", 5).unwrap();
+        party_table.set_quest_state("TAR_ESCAPE", 20).unwrap();
+
+        let mut parsed = PartyTable::new(
+            Gff {
+                file_type: "PT  ".to_owned(),
+                root: round_trip("PT  ", &party_table.gff.root),
+            },
+            Game::Kotor,
+        );
+        let entries = parsed.get_journal_entries();
+        assert_eq!(
+            entries[0],
+            JournalEntry {
+                plot_id: "tar_escape".to_owned(),
+                state: 20,
+                date: 0,
+                time: 0,
+            }
+        );
+        assert!(entries[0].is_completed(&journal));
+        assert!(!entries[1].is_completed(&journal));
+
+        assert!(parsed.remove_quest("Dan_Ruins"));
+        assert!(!parsed.remove_quest("dan_ruins"));
+        assert_eq!(parsed.get_journal_entries().len(), 1);
+    }
+}
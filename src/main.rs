@@ -1,32 +1,151 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 mod bif;
+mod creature;
+mod dds;
+mod discovery;
 mod erf;
 mod error;
+mod game;
+mod gff;
+mod global_vars;
+mod inventory;
+mod journal;
+mod party_table;
+mod resource_manager;
+mod rim;
+mod save_info;
+mod savegame;
 mod shared;
+mod texture;
+mod tga;
+mod tlk;
 mod tpc;
 mod twoda;
+mod txi;
 
 use bif::Bif;
 use erf::Erf;
-use std::{path::PathBuf, str::FromStr};
-// use tlk::Tlk;
 pub use error::{Error, Result};
-use tpc::Tpc;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use texture::TextureKind;
+use tlk::Tlk;
+use tpc::{Tpc, TpcFormat};
 use twoda::TwoDA;
+use txi::Txi;
+
+const TEXTURE_USAGE: &str = "usage:
+    kotka texture export <texture.tpc|tga|dds> [output.png]
+    kotka texture import <input.png> <output.tpc|tga|dds> [greyscale|rgb|rgba|dxt1|dxt5] [cube]";
+
+/// Converts textures to and from PNG, writing a TPC's TXI text next to the PNG and
+/// reading it back from there on import.
+fn run_texture_command(args: &[String]) -> Result<()> {
+    let texture_kind = |path: &Path| {
+        TextureKind::from_path(path).ok_or_else(|| {
+            Error::InvalidData(format!("{} is not a TPC, TGA or DDS file", path.display()))
+        })
+    };
+
+    match args {
+        [command, input, rest @ ..] if command == "export" && rest.len() <= 1 => {
+            let input = Path::new(input);
+            let output = rest
+                .first()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("png"));
+
+            let png = match texture_kind(input)? {
+                TextureKind::Tpc => {
+                    let tpc = Tpc::new(&input.to_string_lossy())?;
+                    if let Some(txi) = tpc.get_txi() {
+                        std::fs::write(output.with_extension("txi"), txi.to_text())?;
+                    }
+                    tpc.to_png()?
+                }
+                kind => texture::texture_to_png(&std::fs::read(input)?, kind)?,
+            };
+            std::fs::write(output, png)?;
+        }
+        [command, input, output, rest @ ..] if command == "import" && rest.len() <= 2 => {
+            let input = Path::new(input);
+            let output = Path::new(output);
+
+            let mut format = None;
+            let mut cube_map = false;
+            for option in rest {
+                match option.as_str() {
+                    "cube" if !cube_map => cube_map = true,
+                    "greyscale" if format.is_none() => format = Some(TpcFormat::Greyscale),
+                    "rgb" if format.is_none() => format = Some(TpcFormat::Rgb),
+                    "rgba" if format.is_none() => format = Some(TpcFormat::Rgba),
+                    "dxt1" if format.is_none() => format = Some(TpcFormat::Dxt1),
+                    "dxt5" if format.is_none() => format = Some(TpcFormat::Dxt5),
+                    option => {
+                        return Err(Error::InvalidData(format!(
+                            "unknown or repeated import option {}",
+                            option
+                        )))
+                    }
+                }
+            }
+            let txi_path = input.with_extension("txi");
+            let txi = if txi_path.is_file() {
+                Some(Txi::from_bytes(&std::fs::read(txi_path)?))
+            } else {
+                None
+            };
+
+            // Cube maps are usually marked in their TXI text, which export writes out.
+            let cube_map = cube_map || txi.as_ref().and_then(|txi| txi.get_int("cube")) == Some(1);
+
+            let texture = texture::png_to_texture(
+                &std::fs::read(input)?,
+                texture_kind(output)?,
+                format,
+                cube_map,
+                txi,
+            )?;
+            std::fs::write(output, texture)?;
+        }
+        _ => {
+            eprintln!("{}", TEXTURE_USAGE);
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
 
 fn main() {
-    // let mut installation_path = shared::resolve_windows_registry_key()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some((command, args)) = args.split_first() {
+        if command == "texture" {
+            if let Err(error) = run_texture_command(args) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
+    // let mut installation_path = discovery::find_installations()
+    //     .into_iter()
+    //     .next()
+    //     .map(|installation| installation.path)
     //     .or(FileDialog::new().pick_folder())
     //     .unwrap();
 
     // let mut installation_path = PathBuf::from_str("example_files/kotor2").unwrap();
 
-    // let bif = Bif::new(&mut installation_path, None, None).unwrap();
+    // let bif = Bif::new(&installation_path.join("chitin.key"), None, None).unwrap();
     // println!("{:#?}", bif);
 
     let erf = Erf::new("example_files/kotor/patch.erf");
     println!("{:#?}", erf);
 
-    // let tlk = Tlk::new("").unwrap();
+    // let tlk = Tlk::new("example_files/kotor/dialog.tlk").unwrap();
     // println!("{:#?}", tlk);
 }
@@ -0,0 +1,504 @@
+use crate::game::Game;
+use crate::gff::{Gff, GffStruct, GffValue};
+use crate::journal::JournalEntry;
+use crate::{Error, Result};
+
+/// A member of the active party.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PartyMember {
+    /// The NPC ID, or -1 for the player character.
+    pub id: i32,
+    pub is_leader: bool,
+}
+
+/// Whether a companion has joined and whether they can be picked for the party.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NpcAvailability {
+    pub available: bool,
+    pub selectable: bool,
+}
+
+/// A typed view over `partytable.res`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartyTable {
+    pub gff: Gff,
+    /// The game the save belongs to, which decides the fields that are read.
+    pub game: Game,
+}
+
+impl PartyTable {
+    pub fn new(gff: Gff, game: Game) -> Self {
+        PartyTable { gff, game }
+    }
+
+    fn root(&self) -> &GffStruct {
+        &self.gff.root
+    }
+
+    fn root_mut(&mut self) -> &mut GffStruct {
+        &mut self.gff.root
+    }
+
+    fn get_list_ints(&self, list_label: &str, label: &str) -> Vec<i64> {
+        self.root()
+            .get_list(list_label)
+            .map(|list| {
+                list.iter()
+                    .map(|entry| entry.get_int(label).unwrap_or_default())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn get_list_entry_mut(&mut self, list_label: &str, index: usize) -> Result<&mut GffStruct> {
+        self.root_mut()
+            .get_list_mut(list_label)
+            .and_then(|list| list.get_mut(index))
+            .ok_or_else(|| Error::InvalidData(format!("{} has no entry {}", list_label, index)))
+    }
+
+    fn require_kotor_2(&self, what: &str) -> Result<()> {
+        if self.game != Game::Kotor2 {
+            return Err(Error::InvalidData(format!(
+                "{} are only stored by KotOR 2",
+                what
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// The party's credits.
+    pub fn get_credits(&self) -> u32 {
+        self.root().get_int("PT_GOLD").unwrap_or_default() as u32
+    }
+
+    pub fn set_credits(&mut self, credits: u32) -> Result<()> {
+        self.root_mut()
+            .update_int("PT_GOLD", GffValue::Dword(credits))?;
+
+        Ok(())
+    }
+
+    /// Experience waiting to be shared with companions who join later.
+    pub fn get_xp_pool(&self) -> i32 {
+        self.root().get_int("PT_XP_POOL").unwrap_or_default() as i32
+    }
+
+    pub fn set_xp_pool(&mut self, xp: i32) -> Result<()> {
+        self.root_mut()
+            .update_int("PT_XP_POOL", GffValue::Int(xp))?;
+
+        Ok(())
+    }
+
+    pub fn get_members(&self) -> Vec<PartyMember> {
+        self.root()
+            .get_list("PT_MEMBERS")
+            .map(|list| {
+                list.iter()
+                    .map(|entry| PartyMember {
+                        id: entry.get_int("PT_MEMBER_ID").unwrap_or(-1) as i32,
+                        is_leader: entry.get_int("PT_IS_LEADER").unwrap_or_default() != 0,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_members(&mut self, members: &[PartyMember]) -> Result<()> {
+        let list = members
+            .iter()
+            .map(|member| {
+                let mut entry = GffStruct::new(0);
+                entry.set("PT_IS_LEADER", GffValue::Byte(member.is_leader.into()));
+                entry.set("PT_MEMBER_ID", GffValue::Int(member.id));
+                entry
+            })
+            .collect();
+
+        self.root_mut().set("PT_MEMBERS", GffValue::List(list));
+        self.root_mut()
+            .update_int("PT_NUM_MEMBERS", GffValue::Byte(members.len() as u8))?;
+
+        Ok(())
+    }
+
+    /// The availability of every companion, indexed by NPC ID.
+    pub fn get_npcs(&self) -> Vec<NpcAvailability> {
+        self.root()
+            .get_list("PT_AVAIL_NPCS")
+            .map(|list| {
+                list.iter()
+                    .map(|entry| NpcAvailability {
+                        available: entry.get_int("PT_NPC_AVAIL").unwrap_or_default() != 0,
+                        selectable: entry.get_int("PT_NPC_SELECT").unwrap_or_default() != 0,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_npc(&mut self, npc_id: usize, availability: NpcAvailability) -> Result<()> {
+        let entry = self.get_list_entry_mut("PT_AVAIL_NPCS", npc_id)?;
+        entry.update_int(
+            "PT_NPC_AVAIL",
+            GffValue::Byte(availability.available.into()),
+        )?;
+        entry.update_int(
+            "PT_NPC_SELECT",
+            GffValue::Byte(availability.selectable.into()),
+        )?;
+
+        Ok(())
+    }
+
+    /// Companion influence, indexed by NPC ID. Only KotOR 2 tracks influence.
+    pub fn get_influence(&self) -> Option<Vec<i32>> {
+        if self.game != Game::Kotor2 {
+            return None;
+        }
+
+        self.root().get_list("PT_INFLUENCE")?;
+
+        Some(
+            self.get_list_ints("PT_INFLUENCE", "PT_NPC_INFLUENCE")
+                .into_iter()
+                .map(|influence| influence as i32)
+                .collect(),
+        )
+    }
+
+    pub fn set_influence(&mut self, npc_id: usize, influence: i32) -> Result<()> {
+        self.get_list_entry_mut("PT_INFLUENCE", npc_id)?
+            .update_int("PT_NPC_INFLUENCE", GffValue::Int(influence))?;
+
+        Ok(())
+    }
+
+    /// Crafting components. Only present in KotOR 2.
+    pub fn get_components(&self) -> Option<i32> {
+        if self.game != Game::Kotor2 {
+            return None;
+        }
+
+        self.root()
+            .get_int("PT_ITEM_COMPONEN")
+            .map(|count| count as i32)
+    }
+
+    pub fn set_components(&mut self, count: i32) -> Result<()> {
+        self.require_kotor_2("crafting components")?;
+        self.root_mut()
+            .update_int("PT_ITEM_COMPONEN", GffValue::Int(count))?;
+
+        Ok(())
+    }
+
+    /// Crafting chemicals. Only present in KotOR 2.
+    pub fn get_chemicals(&self) -> Option<i32> {
+        if self.game != Game::Kotor2 {
+            return None;
+        }
+
+        self.root()
+            .get_int("PT_ITEM_CHEMICAL")
+            .map(|count| count as i32)
+    }
+
+    pub fn set_chemicals(&mut self, count: i32) -> Result<()> {
+        self.require_kotor_2("crafting chemicals")?;
+        self.root_mut()
+            .update_int("PT_ITEM_CHEMICAL", GffValue::Int(count))?;
+
+        Ok(())
+    }
+
+    /// How many of each Pazaak card the player owns, indexed by card.
+    pub fn get_pazaak_cards(&self) -> Vec<i32> {
+        self.get_list_ints("PT_PAZAAKCARDS", "PT_PAZAAKCOUNT")
+            .into_iter()
+            .map(|count| count as i32)
+            .collect()
+    }
+
+    pub fn set_pazaak_card_count(&mut self, card: usize, count: i32) -> Result<()> {
+        self.get_list_entry_mut("PT_PAZAAKCARDS", card)?
+            .update_int("PT_PAZAAKCOUNT", GffValue::Int(count))?;
+
+        Ok(())
+    }
+
+    /// The cards in the player's Pazaak side deck.
+    pub fn get_pazaak_side_deck(&self) -> Vec<i32> {
+        self.get_list_ints("PT_PAZSIDELIST", "PT_PAZSIDECARD")
+            .into_iter()
+            .map(|card| card as i32)
+            .collect()
+    }
+
+    pub fn set_pazaak_side_deck(&mut self, cards: &[i32]) {
+        let list = cards
+            .iter()
+            .map(|card| {
+                let mut entry = GffStruct::new(0);
+                entry.set("PT_PAZSIDECARD", GffValue::Int(*card));
+                entry
+            })
+            .collect();
+
+        self.root_mut().set("PT_PAZSIDELIST", GffValue::List(list));
+    }
+
+    /// The quests the player has started, in journal order.
+    pub fn get_journal_entries(&self) -> Vec<JournalEntry> {
+        self.root()
+            .get_list("JNL_Entries")
+            .map(|list| {
+                list.iter()
+                    .map(|entry| JournalEntry {
+                        plot_id: entry
+                            .get_string("JNL_PlotID")
+                            .unwrap_or_default()
+                            .to_owned(),
+                        state: entry.get_int("JNL_State").unwrap_or_default() as i32,
+                        date: entry.get_int("JNL_Date").unwrap_or_default() as u32,
+                        time: entry.get_int("JNL_Time").unwrap_or_default() as u32,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Moves a quest to `state`, starting it if the player does not have it yet.
+    pub fn set_quest_state(&mut self, plot_id: &str, state: i32) -> Result<()> {
+        let find_entry = |entry: &&mut GffStruct| {
+            entry
+                .get_string("JNL_PlotID")
+                .is_some_and(|existing| existing.eq_ignore_ascii_case(plot_id))
+        };
+
+        if let Some(entry) = self
+            .root_mut()
+            .get_list_mut("JNL_Entries")
+            .and_then(|list| list.iter_mut().find(find_entry))
+        {
+            return entry.update_int("JNL_State", GffValue::Int(state));
+        }
+
+        let mut entry = GffStruct::new(0);
+        entry.set("JNL_PlotID", GffValue::CExoString(plot_id.to_owned()));
+        entry.set("JNL_State", GffValue::Int(state));
+        entry.set("JNL_Date", GffValue::Dword(0));
+        entry.set("JNL_Time", GffValue::Dword(0));
+
+        match self.root_mut().get_list_mut("JNL_Entries") {
+            Some(list) => list.push(entry),
+            None => self
+                .root_mut()
+                .set("JNL_Entries", GffValue::List(vec![entry])),
+        }
+
+        Ok(())
+    }
+
+    /// Removes a quest from the journal, returning whether it was there.
+    pub fn remove_quest(&mut self, plot_id: &str) -> bool {
+        let Some(list) = self.root_mut().get_list_mut("JNL_Entries") else {
+            return false;
+        };
+
+        let len = list.len();
+        list.retain(|entry| {
+            !entry
+                .get_string("JNL_PlotID")
+                .is_some_and(|existing| existing.eq_ignore_ascii_case(plot_id))
+        });
+
+        list.len() != len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gff::test_support::gff_struct;
+
+    fn int_list(label: &str, values: &[i32]) -> GffValue {
+        GffValue::List(
+            values
+                .iter()
+                .map(|value| gff_struct(0, &[(label, GffValue::Int(*value))]))
+                .collect(),
+        )
+    }
+
+    /// A party table holding the KotOR 2 fields too, read as `game`.
+    fn party_table(game: Game) -> PartyTable {
+        let npcs = [true, false]
+            .into_iter()
+            .map(|available| {
+                gff_struct(
+                    0,
+                    &[
+                        ("PT_NPC_AVAIL", GffValue::Byte(available.into())),
+                        ("PT_NPC_SELECT", GffValue::Byte(0)),
+                    ],
+                )
+            })
+            .collect();
+
+        let root = gff_struct(
+            u32::MAX,
+            &[
+                ("PT_GOLD", GffValue::Dword(1500)),
+                ("PT_XP_POOL", GffValue::Int(-20)),
+                ("PT_NUM_MEMBERS", GffValue::Byte(0)),
+                ("PT_MEMBERS", GffValue::List(Vec::new())),
+                ("PT_AVAIL_NPCS", GffValue::List(npcs)),
+                ("PT_PAZAAKCARDS", int_list("PT_PAZAAKCOUNT", &[1, 0, 2])),
+                ("PT_INFLUENCE", int_list("PT_NPC_INFLUENCE", &[50, 75])),
+                ("PT_ITEM_COMPONEN", GffValue::Int(10)),
+                ("PT_ITEM_CHEMICAL", GffValue::Int(4)),
+            ],
+        );
+
+        PartyTable::new(
+            Gff {
+                file_type: "PT  ".to_owned(),
+                root,
+            },
+            game,
+        )
+    }
+
+    #[test]
+    fn kotor_2_fields_are_ignored_for_kotor_1() {
+        let kotor = party_table(Game::Kotor);
+        assert_eq!(kotor.get_influence(), None);
+        assert_eq!(kotor.get_components(), None);
+        assert_eq!(kotor.get_chemicals(), None);
+
+        let kotor_2 = party_table(Game::Kotor2);
+        assert_eq!(kotor_2.get_influence(), Some(vec![50, 75]));
+        assert_eq!(kotor_2.get_components(), Some(10));
+        assert_eq!(kotor_2.get_chemicals(), Some(4));
+
+        // Both games share the rest of the table.
+        assert_eq!(kotor.get_credits(), kotor_2.get_credits());
+        assert_eq!(kotor.get_pazaak_cards(), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn kotor_1_has_no_crafting_fields() {
+        let mut party_table = party_table(Game::Kotor);
+        party_table.gff.root.remove("PT_ITEM_COMPONEN");
+        party_table.gff.root.remove("PT_ITEM_CHEMICAL");
+
+        assert!(party_table.set_components(5).is_err());
+        assert!(party_table.set_chemicals(5).is_err());
+        assert_eq!(party_table.gff.root.get("PT_ITEM_COMPONEN"), None);
+        assert_eq!(party_table.gff.root.get("PT_ITEM_CHEMICAL"), None);
+    }
+
+    #[test]
+    fn kotor_2_edits() {
+        let mut party_table = party_table(Game::Kotor2);
+        party_table.set_influence(1, 100).unwrap();
+        party_table.set_components(0).unwrap();
+        party_table.set_chemicals(30).unwrap();
+        assert!(party_table.set_influence(2, 0).is_err());
+
+        assert_eq!(party_table.get_influence(), Some(vec![50, 100]));
+        assert_eq!(party_table.get_components(), Some(0));
+        assert_eq!(party_table.get_chemicals(), Some(30));
+    }
+
+    #[test]
+    fn edits_keep_the_stored_field_types() {
+        let mut party_table = party_table(Game::Kotor);
+        party_table.set_credits(99_999).unwrap();
+        party_table.set_xp_pool(0).unwrap();
+
+        assert_eq!(
+            party_table.gff.root.get("PT_GOLD"),
+            Some(&GffValue::Dword(99_999))
+        );
+        assert_eq!(
+            party_table.gff.root.get("PT_XP_POOL"),
+            Some(&GffValue::Int(0))
+        );
+
+        party_table
+            .gff
+            .root
+            .set("PT_GOLD", GffValue::CExoString("1500".to_owned()));
+        assert!(party_table.set_credits(0).is_err());
+    }
+
+    #[test]
+    fn members_update_the_member_count() {
+        let mut party_table = party_table(Game::Kotor);
+        let members = [
+            PartyMember {
+                id: 0,
+                is_leader: false,
+            },
+            PartyMember {
+                id: -1,
+                is_leader: true,
+            },
+        ];
+
+        party_table.set_members(&members).unwrap();
+        assert_eq!(party_table.get_members(), members);
+        assert_eq!(
+            party_table.gff.root.get("PT_NUM_MEMBERS"),
+            Some(&GffValue::Byte(2))
+        );
+
+        party_table.set_members(&[]).unwrap();
+        assert!(party_table.get_members().is_empty());
+        assert_eq!(party_table.gff.root.get_int("PT_NUM_MEMBERS"), Some(0));
+    }
+
+    #[test]
+    fn list_edits_need_an_existing_entry() {
+        let mut party_table = party_table(Game::Kotor);
+        let recruited = NpcAvailability {
+            available: true,
+            selectable: true,
+        };
+
+        party_table.set_npc(1, recruited).unwrap();
+        assert_eq!(party_table.get_npcs()[1], recruited);
+        assert!(party_table.set_npc(2, recruited).is_err());
+
+        party_table.set_pazaak_card_count(2, 4).unwrap();
+        assert_eq!(party_table.get_pazaak_cards(), vec![1, 0, 4]);
+        assert!(party_table.set_pazaak_card_count(3, 1).is_err());
+
+        // The side deck is replaced as a whole rather than edited in place.
+        party_table.set_pazaak_side_deck(&[3, -3]);
+        party_table.set_pazaak_side_deck(&[1]);
+        assert_eq!(party_table.get_pazaak_side_deck(), vec![1]);
+    }
+
+    #[test]
+    fn quest_states_start_or_update_entries() {
+        let mut party_table = party_table(Game::Kotor);
+        party_table.set_quest_state("tar_escape", 10).unwrap();
+        party_table.set_quest_state("TAR_ESCAPE", 20).unwrap();
+
+        let entries = party_table.get_journal_entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].plot_id, "tar_escape");
+        assert_eq!(entries[0].state, 20);
+
+        let list = party_table.gff.root.get_list_mut("JNL_Entries").unwrap();
+        list[0].set("JNL_State", GffValue::CExoString("20".to_owned()));
+        assert!(party_table.set_quest_state("tar_escape", 30).is_err());
+        assert!(party_table.remove_quest("Tar_Escape"));
+        assert!(party_table.get_journal_entries().is_empty());
+    }
+}
@@ -0,0 +1,578 @@
+use crate::bif::Bif;
+use crate::erf::Erf;
+use crate::game::Game;
+use crate::rim::Rim;
+use crate::shared::find_path_ignoring_case;
+use crate::{Error, Result};
+use binrw::BinRead;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The texture packs searched, GUI textures first and then the high quality pack.
+const TEXTURE_PACKS: [&str; 2] = ["swpc_tex_gui.erf", "swpc_tex_tpa.erf"];
+
+/// Where a resource was found, in the order the engine searches them.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ResourceSource {
+    Override,
+    Module,
+    TexturePack,
+    Chitin,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Storage {
+    File,
+    Archive {
+        offset: u32,
+        size: u32,
+    },
+    Bif {
+        bif_name: String,
+        resource_name: String,
+    },
+}
+
+/// A single copy of a resource within the installation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResourceLocation {
+    pub source: ResourceSource,
+    /// The loose file, archive or BIF holding the resource.
+    pub container: PathBuf,
+    storage: Storage,
+}
+
+/// Resolves resource names across an installation the way the engine does.
+///
+/// Lookups check the override folder, then the loaded module, then the texture
+/// packs and finally the BIFs listed in `chitin.key`.
+#[derive(Debug)]
+pub struct ResourceManager {
+    pub installation_path: PathBuf,
+    /// The game installed, if it could be detected.
+    pub game: Option<Game>,
+    /// The name of the loaded module, e.g. `"danm13"`.
+    pub module: Option<String>,
+    bif: Option<Bif<'static>>,
+    index: HashMap<String, Vec<ResourceLocation>>,
+}
+
+impl ResourceManager {
+    pub fn new(installation_path: &Path) -> Result<Self> {
+        let mut manager = ResourceManager {
+            installation_path: installation_path.to_owned(),
+            game: Game::from_installation(installation_path),
+            module: None,
+            bif: None,
+            index: HashMap::new(),
+        };
+
+        manager.index_override()?;
+        manager.index_texture_packs()?;
+        manager.index_chitin()?;
+
+        Ok(manager)
+    }
+
+    fn index_override(&mut self) -> Result<()> {
+        let Some(override_path) = find_path_ignoring_case(&self.installation_path, "override")
+        else {
+            return Ok(());
+        };
+
+        for entry in std::fs::read_dir(override_path)? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            self.insert(
+                &name,
+                ResourceLocation {
+                    source: ResourceSource::Override,
+                    container: path,
+                    storage: Storage::File,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn index_texture_packs(&mut self) -> Result<()> {
+        let Some(packs_path) = find_path_ignoring_case(&self.installation_path, "texturepacks")
+        else {
+            return Ok(());
+        };
+
+        for pack in TEXTURE_PACKS {
+            if let Some(path) = find_path_ignoring_case(&packs_path, pack) {
+                self.index_erf(&path, ResourceSource::TexturePack)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn index_chitin(&mut self) -> Result<()> {
+        let Some(chitin_path) = find_path_ignoring_case(&self.installation_path, "chitin.key")
+        else {
+            return Ok(());
+        };
+
+        let bif = Bif::new(&chitin_path, None, None)?;
+
+        for (bif_name, resources) in &bif.bifs {
+            let container = bif.path.with_file_name(bif_name.replace('\\', "/"));
+
+            for resource_name in resources.keys() {
+                self.insert(
+                    resource_name,
+                    ResourceLocation {
+                        source: ResourceSource::Chitin,
+                        container: container.clone(),
+                        storage: Storage::Bif {
+                            bif_name: bif_name.to_owned(),
+                            resource_name: resource_name.to_owned(),
+                        },
+                    },
+                );
+            }
+        }
+
+        self.bif = Some(bif);
+
+        Ok(())
+    }
+
+    fn index_erf(&mut self, path: &Path, source: ResourceSource) -> Result<()> {
+        let mut file = File::open(path)?;
+        let erf = Erf::read(&mut file)?;
+
+        for resource in erf.get_resources() {
+            // Entries of a type the engine doesn't know can never be requested by name.
+            if resource.get_resource_type() == "unknown" {
+                continue;
+            }
+
+            self.insert(
+                &resource.get_filename(),
+                ResourceLocation {
+                    source,
+                    container: path.to_owned(),
+                    storage: Storage::Archive {
+                        offset: resource.get_offset(),
+                        size: resource.get_size(),
+                    },
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn index_rim(&mut self, path: &Path) -> Result<()> {
+        let rim = Rim::new(&path.to_string_lossy())?;
+
+        for resource in &rim.resources {
+            self.insert(
+                &resource.get_filename(),
+                ResourceLocation {
+                    source: ResourceSource::Module,
+                    container: path.to_owned(),
+                    storage: Storage::Archive {
+                        offset: resource.get_offset(),
+                        size: resource.get_size(),
+                    },
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, resource_name: &str, location: ResourceLocation) {
+        let locations = self
+            .index
+            .entry(resource_name.to_ascii_lowercase())
+            .or_default();
+
+        // Keep each list sorted by precedence, preserving insertion order within a source.
+        let position = locations.partition_point(|existing| existing.source <= location.source);
+        locations.insert(position, location);
+    }
+
+    /// Makes the resources of `modules/<module_name>` visible to lookups.
+    ///
+    /// A `.mod` replaces the module's RIMs entirely, as it does in game.
+    pub fn load_module(&mut self, module_name: &str) -> Result<()> {
+        self.unload_module();
+
+        let modules_path = find_path_ignoring_case(&self.installation_path, "modules")
+            .ok_or_else(|| Error::InvalidData("installation has no modules folder".to_owned()))?;
+
+        if let Some(path) = find_path_ignoring_case(&modules_path, &format!("{}.mod", module_name))
+        {
+            self.index_erf(&path, ResourceSource::Module)?;
+        } else {
+            let mut found = false;
+            for suffix in [".rim", "_s.rim"] {
+                if let Some(path) =
+                    find_path_ignoring_case(&modules_path, &format!("{}{}", module_name, suffix))
+                {
+                    self.index_rim(&path)?;
+                    found = true;
+                }
+            }
+
+            if let Some(path) =
+                find_path_ignoring_case(&modules_path, &format!("{}_dlg.erf", module_name))
+            {
+                self.index_erf(&path, ResourceSource::Module)?;
+                found = true;
+            }
+
+            if !found {
+                return Err(Error::InvalidData(format!(
+                    "module {} not found in the installation",
+                    module_name
+                )));
+            }
+        }
+
+        self.module = Some(module_name.to_owned());
+
+        Ok(())
+    }
+
+    pub fn unload_module(&mut self) {
+        for locations in self.index.values_mut() {
+            locations.retain(|location| location.source != ResourceSource::Module);
+        }
+        self.index.retain(|_, locations| !locations.is_empty());
+
+        self.module = None;
+    }
+
+    /// The copy of `resource_name` (e.g. `"p_bastila.utc"`) the game would load.
+    pub fn locate(&self, resource_name: &str) -> Option<&ResourceLocation> {
+        self.locate_all(resource_name).first()
+    }
+
+    /// Every copy of `resource_name`, the first shadowing all of the others.
+    pub fn locate_all(&self, resource_name: &str) -> &[ResourceLocation] {
+        self.index
+            .get(&resource_name.to_ascii_lowercase())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Every resource name of the given type, e.g. `"utc"`.
+    pub fn get_resources_by_type(&self, resource_type: &str) -> Vec<&str> {
+        let suffix = format!(".{}", resource_type.to_ascii_lowercase());
+
+        let mut names: Vec<&str> = self
+            .index
+            .keys()
+            .filter(|name| name.ends_with(&suffix))
+            .map(String::as_str)
+            .collect();
+        names.sort_unstable();
+
+        names
+    }
+
+    pub fn get_resource(&mut self, resource_name: &str) -> Result<Vec<u8>> {
+        let location = self.locate(resource_name).cloned().ok_or_else(|| {
+            Error::InvalidData(format!("{} not found in the installation", resource_name))
+        })?;
+
+        self.read_location(&location)
+    }
+
+    /// Reads a specific copy, e.g. one returned by [`ResourceManager::locate_all`].
+    pub fn read_location(&mut self, location: &ResourceLocation) -> Result<Vec<u8>> {
+        match &location.storage {
+            Storage::File => std::fs::read(&location.container).map_err(Into::into),
+            Storage::Archive { offset, size } => {
+                let mut file = File::open(&location.container)?;
+                file.seek(SeekFrom::Start((*offset).into()))?;
+
+                let mut buf = vec![0u8; *size as usize];
+                file.read_exact(&mut buf)?;
+
+                Ok(buf)
+            }
+            Storage::Bif {
+                bif_name,
+                resource_name,
+            } => {
+                let bif = self
+                    .bif
+                    .as_mut()
+                    .ok_or_else(|| Error::InvalidData("chitin.key is not loaded".to_owned()))?;
+
+                bif.get_resource(bif_name, resource_name.to_owned())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erf::test_support::empty_erf;
+    use tempfile::TempDir;
+
+    const BIF_NAME: &str = "data\\templates.bif";
+
+    /// A `chitin.key` listing every resource as stored in `BIF_NAME`.
+    fn chitin_bytes(resources: &[(&str, u16)]) -> Vec<u8> {
+        let name = format!("{}\0", BIF_NAME);
+        let name_offset = 64 + 12;
+        let key_table = name_offset + name.len() as u32;
+
+        let mut bytes = b"KEY V1  ".to_vec();
+        for value in [1, resources.len() as u32, 64, key_table] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(64, 0);
+
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&name_offset.to_le_bytes());
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+
+        for (id, (reference, type_id)) in resources.iter().enumerate() {
+            let mut reference = reference.as_bytes().to_vec();
+            reference.resize(16, 0);
+            bytes.extend_from_slice(&reference);
+            bytes.extend_from_slice(&type_id.to_le_bytes());
+            bytes.extend_from_slice(&(id as u32).to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// A BIF holding `resources` in order, matching the IDs of `chitin_bytes`.
+    fn bif_bytes(resources: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"BIFFV1  ".to_vec();
+        for value in [resources.len() as u32, 0, 20] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut offset = 20 + 16 * resources.len() as u32;
+        for (id, data) in resources.iter().enumerate() {
+            for value in [id as u32, offset, data.len() as u32, 0] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            offset += data.len() as u32;
+        }
+        for data in resources {
+            bytes.extend_from_slice(data);
+        }
+
+        bytes
+    }
+
+    fn erf_bytes(file_type: &[u8; 4], resources: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut erf = empty_erf(file_type);
+        for (name, data) in resources {
+            erf.add_resource(name, data.to_vec()).unwrap();
+        }
+
+        erf.to_bytes(false).unwrap()
+    }
+
+    fn write_rim(path: &Path, resources: &[(&str, &[u8])]) {
+        let mut rim = Rim::default();
+        for (name, data) in resources {
+            rim.add_resource(name, data.to_vec()).unwrap();
+        }
+
+        rim.save(&path.to_string_lossy()).unwrap();
+    }
+
+    /// An installation where `shared.utc` is in the override folder, the `danm13`
+    /// RIMs and the BIFs, with a few resources found in only one of them.
+    fn fake_installation() -> TempDir {
+        let installation = TempDir::new().unwrap();
+        let path = installation.path();
+
+        std::fs::write(
+            path.join("chitin.key"),
+            chitin_bytes(&[
+                ("shared", 0x07EB),
+                ("appearance", 0x07E1),
+                ("mystery", 0xBEEF),
+            ]),
+        )
+        .unwrap();
+        std::fs::create_dir(path.join("data")).unwrap();
+        std::fs::write(
+            path.join("data/templates.bif"),
+            bif_bytes(&[b"chitin utc", b"chitin 2da", b"unknown"]),
+        )
+        .unwrap();
+
+        std::fs::create_dir(path.join("Override")).unwrap();
+        std::fs::write(path.join("Override/shared.utc"), b"override utc").unwrap();
+
+        let modules = path.join("modules");
+        std::fs::create_dir(&modules).unwrap();
+        write_rim(
+            &modules.join("danm13.rim"),
+            &[("shared.utc", b"module utc"), ("danm13.ifo", b"ifo")],
+        );
+        write_rim(&modules.join("danm13_s.rim"), &[("m13aa.git", b"git")]);
+        std::fs::write(
+            modules.join("danm13_dlg.erf"),
+            erf_bytes(b"ERF ", &[("bastila.dlg", b"dlg")]),
+        )
+        .unwrap();
+
+        installation
+    }
+
+    fn sources(manager: &ResourceManager, resource_name: &str) -> Vec<ResourceSource> {
+        manager
+            .locate_all(resource_name)
+            .iter()
+            .map(|location| location.source)
+            .collect()
+    }
+
+    #[test]
+    fn override_beats_module_beats_chitin() {
+        let installation = fake_installation();
+        let mut manager = ResourceManager::new(installation.path()).unwrap();
+        assert_eq!(
+            sources(&manager, "shared.utc"),
+            [ResourceSource::Override, ResourceSource::Chitin]
+        );
+
+        manager.load_module("danm13").unwrap();
+        assert_eq!(
+            sources(&manager, "SHARED.UTC"),
+            [
+                ResourceSource::Override,
+                ResourceSource::Module,
+                ResourceSource::Chitin
+            ]
+        );
+
+        let contents: Vec<Vec<u8>> = manager
+            .locate_all("shared.utc")
+            .to_vec()
+            .iter()
+            .map(|location| manager.read_location(location).unwrap())
+            .collect();
+        assert_eq!(
+            contents,
+            [&b"override utc"[..], b"module utc", b"chitin utc"]
+        );
+        assert_eq!(manager.get_resource("shared.utc").unwrap(), b"override utc");
+
+        let location = manager.locate("shared.utc").unwrap();
+        assert_eq!(
+            location.container,
+            installation.path().join("Override/shared.utc")
+        );
+    }
+
+    #[test]
+    fn reads_from_every_container() {
+        let installation = fake_installation();
+        let mut manager = ResourceManager::new(installation.path()).unwrap();
+        manager.load_module("danm13").unwrap();
+
+        assert_eq!(
+            manager.get_resource("appearance.2da").unwrap(),
+            b"chitin 2da"
+        );
+        assert_eq!(
+            manager.locate("appearance.2da").unwrap().container,
+            installation.path().join("data/templates.bif")
+        );
+        assert_eq!(manager.get_resource("m13aa.git").unwrap(), b"git");
+        assert_eq!(
+            manager.locate("m13aa.git").unwrap().container,
+            installation.path().join("modules/danm13_s.rim")
+        );
+        assert_eq!(manager.get_resource("bastila.dlg").unwrap(), b"dlg");
+        assert_eq!(
+            manager.locate("bastila.dlg").unwrap().container,
+            installation.path().join("modules/danm13_dlg.erf")
+        );
+        assert_eq!(manager.get_resources_by_type("UTC"), vec!["shared.utc"]);
+        assert!(manager.get_resource("missing.utc").is_err());
+    }
+
+    #[test]
+    fn skips_unknown_chitin_types() {
+        let installation = fake_installation();
+        let manager = ResourceManager::new(installation.path()).unwrap();
+
+        assert!(manager
+            .index
+            .keys()
+            .all(|name| !name.starts_with("mystery")));
+        assert!(manager.locate("appearance.2da").is_some());
+    }
+
+    #[test]
+    fn load_module_replaces_the_previous_module() {
+        let installation = fake_installation();
+        let modules = installation.path().join("modules");
+        write_rim(
+            &modules.join("tar_m02aa.rim"),
+            &[("tar_m02aa.ifo", b"rim ifo")],
+        );
+        std::fs::write(
+            modules.join("tar_m02aa.mod"),
+            erf_bytes(b"MOD ", &[("shared.utc", b"mod utc")]),
+        )
+        .unwrap();
+
+        let mut manager = ResourceManager::new(installation.path()).unwrap();
+        manager.load_module("danm13").unwrap();
+        manager.load_module("tar_m02aa").unwrap();
+
+        assert_eq!(manager.module.as_deref(), Some("tar_m02aa"));
+        assert!(manager.locate("danm13.ifo").is_none());
+        // The .mod is used instead of the module's RIMs.
+        assert!(manager.locate("tar_m02aa.ifo").is_none());
+        let module_copy = manager.locate_all("shared.utc")[1].clone();
+        assert_eq!(module_copy.container, modules.join("tar_m02aa.mod"));
+        assert_eq!(manager.read_location(&module_copy).unwrap(), b"mod utc");
+
+        assert!(manager.load_module("missing").is_err());
+        assert_eq!(manager.module, None);
+        assert_eq!(
+            sources(&manager, "shared.utc"),
+            [ResourceSource::Override, ResourceSource::Chitin]
+        );
+    }
+
+    #[test]
+    fn missing_or_truncated_bifs_are_errors() {
+        let installation = fake_installation();
+        let bif_path = installation.path().join("data/templates.bif");
+        let mut manager = ResourceManager::new(installation.path()).unwrap();
+
+        let mut truncated = bif_bytes(&[b"chitin utc", b"chitin 2da", b"unknown"]);
+        truncated.truncate(truncated.len() - 10);
+        std::fs::write(&bif_path, truncated).unwrap();
+        assert!(manager.get_resource("appearance.2da").is_err());
+
+        std::fs::write(&bif_path, b"BIFF").unwrap();
+        assert!(manager.get_resource("appearance.2da").is_err());
+
+        std::fs::remove_file(&bif_path).unwrap();
+        assert!(manager.get_resource("appearance.2da").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use crate::shared::{decode_string, encode_string, get_resource_type_id, RES_TYPES};
+use crate::{Error, Result};
+use binrw::{binrw, io::Cursor, BinRead, BinWrite};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+const HEADER_SIZE: u32 = 120;
+const KEY_SIZE: u32 = 32;
+
+#[binrw]
+#[brw(little, magic = b"RIM ")]
+#[derive(Debug, Eq, PartialEq)]
+struct RimHeader {
+    version: [u8; 4],
+    reserved: u32,
+    entry_count: u32,
+    #[brw(pad_after = 100)]
+    offset_to_key_list: u32,
+}
+
+#[binrw]
+#[brw(little)]
+#[derive(Default, Debug, Eq, PartialEq)]
+pub struct RimResource {
+    #[br(map = |x: [u8; 16]| decode_string(&x).trim_end_matches('\0').to_owned())]
+    #[bw(map = |s| encode_string(s), pad_size_to = 16)]
+    pub reference: String,
+    pub r#type: u32,
+    pub id: u32,
+    offset: u32,
+    size: u32,
+    #[brw(ignore)]
+    data: Vec<u8>,
+}
+
+impl RimResource {
+    pub fn get_resource_type<'a>(&self) -> &'a str {
+        RES_TYPES
+            .get(&(self.r#type as u16))
+            .copied()
+            .unwrap_or("unknown")
+    }
+
+    /// The resource name with its extension, e.g. `"module.ifo"`.
+    pub fn get_filename(&self) -> String {
+        format!("{}.{}", self.reference, self.get_resource_type())
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(crate) fn get_offset(&self) -> u32 {
+        self.offset
+    }
+
+    pub(crate) fn get_size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// A struct representing a RIM archive, the format module resources ship in.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Rim {
+    /// The path the RIM was read from, if any.
+    pub path: PathBuf,
+    pub resources: Vec<RimResource>,
+}
+
+impl Rim {
+    pub fn new(rim_filename: &str) -> Result<Self> {
+        let mut file = Self::open_file(rim_filename)?;
+
+        let mut rim = Self::from_reader(&mut file)?;
+        rim.path = PathBuf::from(rim_filename);
+
+        Ok(rim)
+    }
+
+    fn open_file(filename: &str) -> Result<File> {
+        File::open(filename).map_err(Into::into)
+    }
+
+    /// Parses a RIM, loading every resource into memory.
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let header = RimHeader::read(reader).map_err(|_| Error::MissingHeader)?;
+        if &header.version != b"V1.0" {
+            return Err(Error::MissingHeader);
+        }
+
+        let offset_to_key_list = match header.offset_to_key_list {
+            0 => HEADER_SIZE,
+            offset => offset,
+        };
+        reader.seek(SeekFrom::Start(offset_to_key_list.into()))?;
+
+        let mut resources = Vec::with_capacity(header.entry_count as usize);
+        for _ in 0..header.entry_count {
+            resources.push(RimResource::read(reader)?);
+        }
+
+        for resource in resources.iter_mut() {
+            reader.seek(SeekFrom::Start(resource.offset.into()))?;
+
+            let mut buf = vec![0u8; resource.size as usize];
+            reader.read_exact(&mut buf)?;
+
+            resource.data = buf;
+        }
+
+        Ok(Rim {
+            path: PathBuf::new(),
+            resources,
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_reader(&mut Cursor::new(bytes))
+    }
+
+    pub fn get_resources_by_type(&self, resource_type: &str) -> Vec<u32> {
+        self.resources
+            .iter()
+            .filter(|key| key.get_resource_type() == resource_type)
+            .map(|key| key.id)
+            .collect()
+    }
+
+    pub fn get_resource_id_by_name(&self, resource_name: &str) -> Option<u32> {
+        self.resources
+            .iter()
+            .find(|key| key.get_filename().eq_ignore_ascii_case(resource_name))
+            .map(|key| key.id)
+    }
+
+    pub fn get_resource_id_by_type(&self, resource_type: &str) -> Option<u32> {
+        self.resources
+            .iter()
+            .find(|key| key.get_resource_type() == resource_type)
+            .map(|key| key.id)
+    }
+
+    pub fn get_resource_by_id(&self, resource_id: u32) -> Option<&RimResource> {
+        self.resources.iter().find(|key| key.id == resource_id)
+    }
+
+    pub fn get_resource_data(&self, resource_name: &str) -> Option<&[u8]> {
+        self.get_resource_id_by_name(resource_name)
+            .and_then(|id| self.get_resource_by_id(id))
+            .map(RimResource::get_data)
+    }
+
+    /// Writes a single resource to `output_path`, named after the resource.
+    pub fn export(&self, resource_id: u32, output_path: &mut PathBuf) -> Result<()> {
+        let resource = self.get_resource_by_id(resource_id).ok_or_else(|| {
+            Error::InvalidData(format!("no resource with ID {} in the RIM", resource_id))
+        })?;
+
+        output_path.push(resource.get_filename());
+        let mut output_file = File::create(output_path)?;
+        output_file.write_all(&resource.data)?;
+
+        Ok(())
+    }
+
+    /// Adds `resource_name` (e.g. `"m01aa.are"`), replacing any resource of that name.
+    pub fn add_resource(&mut self, resource_name: &str, data: Vec<u8>) -> Result<u32> {
+        let (reference, extension) = resource_name
+            .rsplit_once('.')
+            .ok_or_else(|| Error::InvalidData(format!("{} has no extension", resource_name)))?;
+        let type_id = get_resource_type_id(extension)
+            .ok_or_else(|| Error::InvalidData(format!("unknown resource type {}", extension)))?;
+        if reference.len() > 16 {
+            return Err(Error::InvalidData(format!(
+                "resource name {} is longer than 16 characters",
+                reference
+            )));
+        }
+
+        if let Some(id) = self.get_resource_id_by_name(resource_name) {
+            let resource = self.resources.iter_mut().find(|key| key.id == id).unwrap();
+            resource.data = data;
+
+            return Ok(id);
+        }
+
+        let id = self.resources.len() as u32;
+        self.resources.push(RimResource {
+            reference: reference.to_owned(),
+            r#type: type_id.into(),
+            id,
+            data,
+            ..Default::default()
+        });
+
+        Ok(id)
+    }
+
+    pub fn remove_resource(&mut self, resource_name: &str) -> Option<RimResource> {
+        let position = self
+            .resources
+            .iter()
+            .position(|key| key.get_filename().eq_ignore_ascii_case(resource_name))?;
+
+        let removed = self.resources.remove(position);
+        for (id, resource) in self.resources.iter_mut().enumerate() {
+            resource.id = id as u32;
+        }
+
+        Some(removed)
+    }
+
+    fn recalculate_sizing(&mut self) {
+        let mut offset = HEADER_SIZE + KEY_SIZE * self.resources.len() as u32;
+
+        for (id, resource) in self.resources.iter_mut().enumerate() {
+            resource.id = id as u32;
+            resource.offset = offset;
+            resource.size = resource.data.len() as u32;
+
+            offset += resource.size;
+        }
+    }
+
+    pub fn write_to<W: Write + Seek>(&mut self, writer: &mut W) -> Result<()> {
+        self.recalculate_sizing();
+
+        RimHeader {
+            version: *b"V1.0",
+            reserved: 0,
+            entry_count: self.resources.len() as u32,
+            offset_to_key_list: HEADER_SIZE,
+        }
+        .write(writer)?;
+
+        for resource in &self.resources {
+            resource.write(writer)?;
+        }
+        for resource in &self.resources {
+            writer.write_all(&resource.data)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&mut self, rim_filename: &str) -> Result<()> {
+        let mut file = File::create(rim_filename)?;
+
+        self.write_to(&mut file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_rim() -> Rim {
+        let mut rim = Rim::default();
+        rim.add_resource("module.ifo", b"IFO V3.2".to_vec())
+            .unwrap();
+        rim.add_resource("m01aa.are", vec![1, 2, 3]).unwrap();
+        rim.add_resource("m01aa.git", Vec::new()).unwrap();
+
+        rim
+    }
+
+    fn to_bytes(rim: &mut Rim) -> Vec<u8> {
+        let mut cursor = Cursor::new(Vec::new());
+        rim.write_to(&mut cursor).unwrap();
+
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let mut rim = sample_rim();
+        let bytes = to_bytes(&mut rim);
+
+        let parsed = Rim::from_bytes(&bytes).unwrap();
+
+        assert_eq!(parsed, rim);
+        assert_eq!(
+            parsed.get_resource_data("MODULE.IFO"),
+            Some(&b"IFO V3.2"[..])
+        );
+        assert_eq!(parsed.get_resource_data("m01aa.are"), Some(&[1, 2, 3][..]));
+        assert_eq!(parsed.get_resources_by_type("git"), vec![2]);
+    }
+
+    #[test]
+    fn add_replaces_and_remove_renumbers() {
+        let mut rim = sample_rim();
+        assert_eq!(rim.add_resource("m01aa.are", vec![9]).unwrap(), 1);
+        assert_eq!(rim.resources.len(), 3);
+
+        let removed = rim.remove_resource("module.ifo").unwrap();
+        assert_eq!(removed.get_filename(), "module.ifo");
+
+        let parsed = Rim::from_bytes(&to_bytes(&mut rim)).unwrap();
+        assert_eq!(parsed.get_resource_id_by_name("m01aa.are"), Some(0));
+        assert_eq!(parsed.get_resource_data("m01aa.are"), Some(&[9][..]));
+    }
+
+    #[test]
+    fn zero_key_list_offset_means_after_header() {
+        let mut rim = sample_rim();
+        let mut bytes = to_bytes(&mut rim);
+        bytes[16..20].copy_from_slice(&0u32.to_le_bytes());
+
+        assert_eq!(Rim::from_bytes(&bytes).unwrap(), rim);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut rim = Rim::default();
+        assert!(rim.add_resource("noextension", Vec::new()).is_err());
+        assert!(rim.add_resource("a.notatype", Vec::new()).is_err());
+        assert!(rim
+            .add_resource("a_name_longer_than_16.are", Vec::new())
+            .is_err());
+
+        let mut bytes = to_bytes(&mut sample_rim());
+        bytes[4..8].copy_from_slice(b"V2.0");
+        assert!(matches!(Rim::from_bytes(&bytes), Err(Error::MissingHeader)));
+    }
+}
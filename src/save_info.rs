@@ -0,0 +1,196 @@
+use crate::game::Game;
+use crate::gff::{Gff, GffStruct, GffValue};
+use crate::Result;
+use std::time::Duration;
+
+/// The number of party portraits shown by the load game screen.
+pub const PORTRAIT_COUNT: usize = 3;
+
+/// A typed view over `savenfo.res`, the metadata shown by the load game screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveInfo {
+    pub gff: Gff,
+    /// The game the save belongs to, which decides the fields that are read.
+    pub game: Game,
+}
+
+impl SaveInfo {
+    pub fn new(gff: Gff, game: Game) -> Self {
+        SaveInfo { gff, game }
+    }
+
+    fn root(&self) -> &GffStruct {
+        &self.gff.root
+    }
+
+    fn root_mut(&mut self) -> &mut GffStruct {
+        &mut self.gff.root
+    }
+
+    /// The name the player gave the save.
+    pub fn get_save_name(&self) -> &str {
+        self.root().get_string("SAVEGAMENAME").unwrap_or_default()
+    }
+
+    pub fn set_save_name(&mut self, name: &str) {
+        self.root_mut()
+            .set("SAVEGAMENAME", GffValue::CExoString(name.to_owned()));
+    }
+
+    /// The display name of the area, e.g. `"Taris - Upper City North"`.
+    pub fn get_area_name(&self) -> &str {
+        self.root().get_string("AREANAME").unwrap_or_default()
+    }
+
+    /// The module the game was saved in, e.g. `"tar_m02aa"`.
+    pub fn get_last_module(&self) -> Option<&str> {
+        self.root().get_string("LASTMODULE")
+    }
+
+    pub fn get_time_played(&self) -> Duration {
+        Duration::from_secs(self.root().get_int("TIMEPLAYED").unwrap_or_default() as u64)
+    }
+
+    /// The portraits of the party, leader first. Empty slots are skipped.
+    pub fn get_portraits(&self) -> Vec<&str> {
+        (0..PORTRAIT_COUNT)
+            .filter_map(|idx| self.root().get_string(&format!("PORTRAIT{}", idx)))
+            .filter(|portrait| !portrait.is_empty())
+            .collect()
+    }
+
+    /// Whether the cheat console was used, which disables some achievements.
+    pub fn get_cheat_used(&self) -> bool {
+        self.root().get_int("CHEATUSED").unwrap_or_default() != 0
+    }
+
+    pub fn clear_cheat_used(&mut self) -> Result<()> {
+        self.root_mut().update_int("CHEATUSED", GffValue::Byte(0))?;
+
+        Ok(())
+    }
+
+    /// The player character's name. Only KotOR 2 stores it.
+    pub fn get_pc_name(&self) -> Option<&str> {
+        if self.game != Game::Kotor2 {
+            return None;
+        }
+
+        self.root().get_string("PCNAME")
+    }
+
+    /// The row in the gameplay hint table shown while loading.
+    pub fn get_gameplay_hint(&self) -> Option<u8> {
+        self.root().get_int("GAMEPLAYHINT").map(|hint| hint as u8)
+    }
+
+    /// The row in the story hint table shown while loading.
+    pub fn get_story_hint(&self) -> Option<u8> {
+        self.root().get_int("STORYHINT").map(|hint| hint as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gff::test_support::gff_struct;
+
+    fn new_save_info(game: Game, fields: &[(&str, GffValue)]) -> SaveInfo {
+        SaveInfo::new(
+            Gff {
+                file_type: "NFO ".to_owned(),
+                root: gff_struct(u32::MAX, fields),
+            },
+            game,
+        )
+    }
+
+    fn sample_fields() -> Vec<(&'static str, GffValue)> {
+        vec![
+            (
+                "SAVEGAMENAME",
+                GffValue::CExoString("Before Malak".to_owned()),
+            ),
+            ("AREANAME", GffValue::CExoString("Star Forge".to_owned())),
+            ("LASTMODULE", GffValue::CExoString("sta_m45ac".to_owned())),
+            ("TIMEPLAYED", GffValue::Dword(3661)),
+            ("CHEATUSED", GffValue::Byte(1)),
+            ("GAMEPLAYHINT", GffValue::Byte(4)),
+            ("PORTRAIT0", GffValue::ResRef("po_pmhc01".to_owned())),
+            ("PORTRAIT1", GffValue::ResRef(String::new())),
+            ("PORTRAIT2", GffValue::ResRef("po_pbastila".to_owned())),
+            ("PCNAME", GffValue::CExoString("Meetra".to_owned())),
+        ]
+    }
+
+    #[test]
+    fn reads_load_screen_fields() {
+        let save_info = new_save_info(Game::Kotor, &sample_fields());
+
+        assert_eq!(save_info.get_save_name(), "Before Malak");
+        assert_eq!(save_info.get_area_name(), "Star Forge");
+        assert_eq!(save_info.get_last_module(), Some("sta_m45ac"));
+        assert_eq!(save_info.get_time_played(), Duration::from_secs(3661));
+        // Empty portrait slots are skipped rather than returned as blank names.
+        assert_eq!(save_info.get_portraits(), vec!["po_pmhc01", "po_pbastila"]);
+        assert!(save_info.get_cheat_used());
+        assert_eq!(save_info.get_gameplay_hint(), Some(4));
+        assert_eq!(save_info.get_story_hint(), None);
+    }
+
+    #[test]
+    fn missing_fields_read_as_defaults() {
+        let save_info = new_save_info(Game::Kotor2, &[]);
+
+        assert_eq!(save_info.get_save_name(), "");
+        assert_eq!(save_info.get_last_module(), None);
+        assert_eq!(save_info.get_time_played(), Duration::ZERO);
+        assert!(save_info.get_portraits().is_empty());
+        assert!(!save_info.get_cheat_used());
+        assert_eq!(save_info.get_pc_name(), None);
+    }
+
+    #[test]
+    fn pc_name_is_only_read_for_kotor_2() {
+        let fields = sample_fields();
+
+        assert_eq!(new_save_info(Game::Kotor, &fields).get_pc_name(), None);
+        assert_eq!(
+            new_save_info(Game::Kotor2, &fields).get_pc_name(),
+            Some("Meetra")
+        );
+
+        let party_table = Gff {
+            file_type: "PT  ".to_owned(),
+            root: GffStruct::new(u32::MAX),
+        };
+        let kotor_2 = new_save_info(Game::Kotor, &fields);
+        assert_eq!(Game::from_save(&kotor_2.gff, &party_table), Game::Kotor2);
+        let kotor = new_save_info(Game::Kotor, &fields[..fields.len() - 1]);
+        assert_eq!(Game::from_save(&kotor.gff, &party_table), Game::Kotor);
+    }
+
+    #[test]
+    fn clearing_cheats_keeps_the_field_type() {
+        let mut save_info = new_save_info(Game::Kotor, &[("CHEATUSED", GffValue::Dword(1))]);
+        save_info.clear_cheat_used().unwrap();
+        assert_eq!(
+            save_info.gff.root.get("CHEATUSED"),
+            Some(&GffValue::Dword(0))
+        );
+
+        let mut save_info = new_save_info(Game::Kotor, &[]);
+        save_info.clear_cheat_used().unwrap();
+        assert_eq!(
+            save_info.gff.root.get("CHEATUSED"),
+            Some(&GffValue::Byte(0))
+        );
+        assert!(!save_info.get_cheat_used());
+
+        save_info.set_save_name("After Malak");
+        assert_eq!(
+            save_info.gff.root.get("SAVEGAMENAME"),
+            Some(&GffValue::CExoString("After Malak".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use crate::creature::Creature;
+use crate::erf::Erf;
+use crate::game::Game;
+use crate::gff::Gff;
+use crate::global_vars::GlobalVars;
+use crate::inventory::Inventory;
+use crate::party_table::PartyTable;
+use crate::save_info::SaveInfo;
+use crate::shared::find_path_ignoring_case;
+use crate::tga::Tga;
+use crate::{Error, Result};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const SAVEGAME: &str = "SAVEGAME.sav";
+const SAVE_INFO: &str = "savenfo.res";
+const PARTY_TABLE: &str = "PARTYTABLE.res";
+const GLOBAL_VARS: &str = "GLOBALVARS.res";
+const SCREENSHOT: &str = "Screen.tga";
+const INVENTORY: &str = "inventory.res";
+const MODULE_INFO: &str = "module.ifo";
+
+/// A save game folder, e.g. `saves/000043 - Game42`.
+///
+/// The folder is a bundle of files: the `SAVEGAME.sav` ERF holding the module
+/// saves and inventory, plus the loose GFF resources describing the save.
+#[derive(Debug)]
+pub struct SaveGame {
+    pub path: PathBuf,
+    /// The game the save was made by.
+    pub game: Game,
+    /// `SAVEGAME.sav`, with the data of every resource loaded.
+    pub savegame: Erf,
+    /// `savenfo.res`, the metadata shown by the load game screen.
+    pub save_info: SaveInfo,
+    /// `partytable.res`, the party, credits and experience.
+    pub party_table: PartyTable,
+    /// `globalvars.res`, the plot variables.
+    pub global_vars: GlobalVars,
+    /// The raw `screen.tga` thumbnail, if the save has one.
+    pub screenshot: Option<Vec<u8>>,
+}
+
+impl SaveGame {
+    pub fn new(save_path: &Path) -> Result<Self> {
+        let savegame = Erf::open(&Self::resolve(save_path, SAVEGAME).to_string_lossy())?;
+        let save_info = Gff::new(&Self::resolve(save_path, SAVE_INFO).to_string_lossy())?;
+        let party_table = Gff::new(&Self::resolve(save_path, PARTY_TABLE).to_string_lossy())?;
+
+        let game = Game::from_save(&save_info, &party_table);
+        let save_info = SaveInfo::new(save_info, game);
+        let party_table = PartyTable::new(party_table, game);
+        let global_vars = GlobalVars::new(Gff::new(
+            &Self::resolve(save_path, GLOBAL_VARS).to_string_lossy(),
+        )?)?;
+
+        let screenshot_path = Self::resolve(save_path, SCREENSHOT);
+        let screenshot = if screenshot_path.is_file() {
+            Some(std::fs::read(screenshot_path)?)
+        } else {
+            None
+        };
+
+        Ok(SaveGame {
+            path: save_path.to_owned(),
+            game,
+            savegame,
+            save_info,
+            party_table,
+            global_vars,
+            screenshot,
+        })
+    }
+
+    /// Finds a file in the save folder regardless of the case it was saved with.
+    fn resolve(save_path: &Path, name: &str) -> PathBuf {
+        find_path_ignoring_case(save_path, name).unwrap_or_else(|| save_path.join(name))
+    }
+
+    /// The modules with a save in `SAVEGAME.sav`, e.g. `"danm13"`.
+    pub fn get_module_names(&self) -> Vec<String> {
+        self.savegame
+            .get_nested_erf_names()
+            .into_iter()
+            .filter_map(|name| name.strip_suffix(".sav").map(ToOwned::to_owned))
+            .collect()
+    }
+
+    /// The save of a single module, holding its `.ifo`, `.are` and `.git`.
+    pub fn get_module_save(&self, module_name: &str) -> Result<Erf> {
+        self.savegame
+            .get_nested_erf(&format!("{}.sav", module_name))
+    }
+
+    pub fn set_module_save(&mut self, module_name: &str, module_save: &Erf) -> Result<()> {
+        self.savegame
+            .set_nested_erf(&format!("{}.sav", module_name), module_save)
+    }
+
+    /// The module the game was saved in, whose save holds the player character.
+    pub fn get_last_module(&self) -> Result<String> {
+        self.save_info
+            .get_last_module()
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| Error::InvalidData(format!("{} has no LASTMODULE", SAVE_INFO)))
+    }
+
+    fn get_module_info(module_save: &Erf) -> Result<Gff> {
+        let data = module_save.get_resource_data(MODULE_INFO).ok_or_else(|| {
+            Error::InvalidData(format!("{} is missing from the module save", MODULE_INFO))
+        })?;
+
+        Gff::from_bytes(data)
+    }
+
+    /// The player character, from `Mod_PlayerList` in the last module's `module.ifo`.
+    pub fn get_player(&self) -> Result<Creature> {
+        let module_save = self.get_module_save(&self.get_last_module()?)?;
+        let module_info = Self::get_module_info(&module_save)?;
+
+        module_info
+            .root
+            .get_list("Mod_PlayerList")
+            .and_then(|list| list.first())
+            .cloned()
+            .map(Creature::new)
+            .ok_or_else(|| Error::InvalidData("Mod_PlayerList is empty".to_owned()))
+    }
+
+    /// Writes the player character back into the last module's save.
+    pub fn set_player(&mut self, player: &Creature) -> Result<()> {
+        let module_name = self.get_last_module()?;
+        let mut module_save = self.get_module_save(&module_name)?;
+        let mut module_info = Self::get_module_info(&module_save)?;
+
+        let entry = module_info
+            .root
+            .get_list_mut("Mod_PlayerList")
+            .and_then(|list| list.first_mut())
+            .ok_or_else(|| Error::InvalidData("Mod_PlayerList is empty".to_owned()))?;
+        *entry = player.gff_struct.clone();
+
+        module_save.add_resource(MODULE_INFO, module_info.to_bytes()?)?;
+        self.set_module_save(&module_name, &module_save)
+    }
+
+    /// `inventory.res`, the party's shared inventory.
+    pub fn get_inventory(&self) -> Result<Inventory> {
+        let data = self
+            .savegame
+            .get_resource_data(INVENTORY)
+            .ok_or_else(|| Error::InvalidData(format!("{} is missing from the save", INVENTORY)))?;
+
+        Gff::from_bytes(data).map(Inventory::new)
+    }
+
+    pub fn set_inventory(&mut self, inventory: &Inventory) -> Result<()> {
+        self.savegame
+            .add_resource(INVENTORY, inventory.gff.to_bytes()?)
+            .map(|_| ())
+    }
+
+    /// The decoded `Screen.tga` thumbnail, if the save has one.
+    pub fn get_screenshot(&self) -> Result<Option<Tga>> {
+        self.screenshot.as_deref().map(Tga::from_bytes).transpose()
+    }
+
+    /// Replaces the thumbnail shown in the save and load menus.
+    pub fn set_screenshot(&mut self, screenshot: &Tga) -> Result<()> {
+        self.screenshot = Some(screenshot.to_bytes()?);
+
+        Ok(())
+    }
+
+    /// Writes every file back to the folder the save was loaded from.
+    pub fn save(&mut self) -> Result<()> {
+        let path = self.path.clone();
+
+        self.save_to(&path)
+    }
+
+    /// Writes every file of the save to `save_path`.
+    ///
+    /// The new folder is built in a sibling staging directory, carrying over any
+    /// files the editor doesn't model, and only then swapped in with a rename. The
+    /// old folder is left untouched until the swap, and is restored should the
+    /// new folder fail to move into place.
+    pub fn save_to(&mut self, save_path: &Path) -> Result<()> {
+        let mut files = vec![
+            (SAVEGAME, self.savegame.to_bytes(false)?),
+            (SAVE_INFO, self.save_info.gff.to_bytes()?),
+            (PARTY_TABLE, self.party_table.gff.to_bytes()?),
+            (GLOBAL_VARS, self.global_vars.to_gff().to_bytes()?),
+        ];
+        if let Some(screenshot) = &self.screenshot {
+            files.push((SCREENSHOT, screenshot.clone()));
+        }
+
+        let parent = save_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)?;
+        let staging = tempfile::Builder::new()
+            .prefix(".kotka-save-")
+            .tempdir_in(parent)?;
+
+        if save_path.is_dir() {
+            for entry in std::fs::read_dir(save_path)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                let is_written = files
+                    .iter()
+                    .any(|(written, _)| name.to_string_lossy().eq_ignore_ascii_case(written));
+
+                if entry.file_type()?.is_file() && !is_written {
+                    std::fs::copy(entry.path(), staging.path().join(name))?;
+                }
+            }
+        }
+
+        for (name, data) in files {
+            // Keep the case the files already have, as the game looks them up exactly.
+            let name = Self::resolve(save_path, name)
+                .file_name()
+                .map(ToOwned::to_owned)
+                .unwrap_or_else(|| name.into());
+
+            let mut file = File::create(staging.path().join(name))?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+
+        Self::swap_folders(staging.path(), save_path)?;
+        self.path = save_path.to_owned();
+
+        Ok(())
+    }
+
+    /// Moves the `staging` folder to `save_path`, putting any old folder back on failure.
+    fn swap_folders(staging: &Path, save_path: &Path) -> Result<()> {
+        if !save_path.exists() {
+            return std::fs::rename(staging, save_path).map_err(Into::into);
+        }
+
+        let mut backup = staging.as_os_str().to_owned();
+        backup.push(".old");
+        let backup = PathBuf::from(backup);
+
+        std::fs::rename(save_path, &backup)?;
+        if let Err(error) = std::fs::rename(staging, save_path) {
+            std::fs::rename(&backup, save_path)?;
+            return Err(error.into());
+        }
+
+        // The new save is already in place, so a leftover copy of the old one is harmless.
+        let _ = std::fs::remove_dir_all(&backup);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creature::Ability;
+    use crate::erf::test_support::empty_erf;
+    use crate::gff::test_support::gff_struct;
+    use crate::gff::{GffStruct, GffValue};
+    use crate::inventory::Item;
+    use tempfile::TempDir;
+
+    fn gff(file_type: &str, fields: &[(&str, GffValue)]) -> Gff {
+        Gff {
+            file_type: file_type.to_owned(),
+            root: gff_struct(u32::MAX, fields),
+        }
+    }
+
+    /// A KotOR 1 save last made in `danm13`, with the player's strength at 14.
+    fn write_save(save_path: &Path) {
+        let mut player = GffStruct::new(0);
+        player.set("Str", GffValue::Byte(14));
+        let module_info = gff("IFO ", &[("Mod_PlayerList", GffValue::List(vec![player]))]);
+
+        let mut module_save = empty_erf(b"SAV ");
+        module_save
+            .add_resource(MODULE_INFO, module_info.to_bytes().unwrap())
+            .unwrap();
+
+        let inventory = gff("INV ", &[("ItemList", GffValue::List(Vec::new()))]);
+        let mut savegame = empty_erf(b"SAV ");
+        savegame
+            .add_resource(INVENTORY, inventory.to_bytes().unwrap())
+            .unwrap();
+        savegame.set_nested_erf("danm13.sav", &module_save).unwrap();
+
+        let save_info = gff(
+            "NFO ",
+            &[
+                ("SAVEGAMENAME", GffValue::CExoString("Game1".to_owned())),
+                ("LASTMODULE", GffValue::ResRef("danm13".to_owned())),
+            ],
+        );
+        let party_table = gff("PT  ", &[("PT_GOLD", GffValue::Dword(100))]);
+
+        std::fs::create_dir_all(save_path).unwrap();
+        for (name, data) in [
+            (SAVEGAME, savegame.to_bytes(false).unwrap()),
+            (SAVE_INFO, save_info.to_bytes().unwrap()),
+            (PARTY_TABLE, party_table.to_bytes().unwrap()),
+            (GLOBAL_VARS, gff("GVT ", &[]).to_bytes().unwrap()),
+            ("pifo.ifo", b"not modelled".to_vec()),
+        ] {
+            std::fs::write(save_path.join(name), data).unwrap();
+        }
+    }
+
+    fn folder_names(path: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    #[test]
+    fn edits_survive_saving() {
+        let saves = TempDir::new().unwrap();
+        let save_path = saves.path().join("000001 - Game1");
+        write_save(&save_path);
+
+        let mut save = SaveGame::new(&save_path).unwrap();
+        assert_eq!(save.game, Game::Kotor);
+        assert_eq!(save.save_info.get_save_name(), "Game1");
+        assert_eq!(save.get_module_names(), vec!["danm13"]);
+        assert_eq!(save.party_table.get_credits(), 100);
+        assert!(save.get_inventory().unwrap().is_empty());
+
+        save.save_info.set_save_name("Edited");
+        save.party_table.set_credits(5000).unwrap();
+        save.global_vars.set_number("K_TEST", 3);
+        let mut inventory = save.get_inventory().unwrap();
+        let mut item = GffStruct::new(0);
+        item.set(
+            "TemplateResRef",
+            GffValue::ResRef("g_i_medeqpmnt01".to_owned()),
+        );
+        inventory.add_item(Item::new(item));
+        save.set_inventory(&inventory).unwrap();
+        save.save().unwrap();
+
+        // Only the save folder is left behind, with the unmodelled file kept.
+        assert_eq!(folder_names(saves.path()), ["000001 - Game1"]);
+        assert_eq!(
+            std::fs::read(save_path.join("pifo.ifo")).unwrap(),
+            b"not modelled"
+        );
+
+        let save = SaveGame::new(&save_path).unwrap();
+        assert_eq!(save.save_info.get_save_name(), "Edited");
+        assert_eq!(save.party_table.get_credits(), 5000);
+        assert_eq!(save.global_vars.get_number("K_TEST"), Some(3));
+        assert_eq!(
+            save.get_inventory().unwrap().get_items()[0].get_template_resref(),
+            Some("g_i_medeqpmnt01")
+        );
+        assert_eq!(save.get_module_names(), vec!["danm13"]);
+    }
+
+    #[test]
+    fn player_lives_in_the_last_module() {
+        let saves = TempDir::new().unwrap();
+        let save_path = saves.path().join("000001 - Game1");
+        write_save(&save_path);
+
+        let mut save = SaveGame::new(&save_path).unwrap();
+        let mut player = save.get_player().unwrap();
+        assert_eq!(player.get_ability(Ability::Strength), 14);
+
+        player.set_ability(Ability::Strength, 18).unwrap();
+        save.set_player(&player).unwrap();
+        save.save().unwrap();
+
+        let save = SaveGame::new(&save_path).unwrap();
+        assert_eq!(
+            save.get_player().unwrap().get_ability(Ability::Strength),
+            18
+        );
+
+        let module_save = save.get_module_save("danm13").unwrap();
+        let module_info =
+            Gff::from_bytes(module_save.get_resource_data(MODULE_INFO).unwrap()).unwrap();
+        let players = module_info.root.get_list("Mod_PlayerList").unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].get_int("Str"), Some(18));
+    }
+
+    #[test]
+    fn player_needs_a_last_module() {
+        let saves = TempDir::new().unwrap();
+        let save_path = saves.path().join("000001 - Game1");
+        write_save(&save_path);
+
+        let mut save = SaveGame::new(&save_path).unwrap();
+        save.save_info.gff.root.remove("LASTMODULE");
+        assert!(save.get_player().is_err());
+
+        save.save_info
+            .gff
+            .root
+            .set("LASTMODULE", GffValue::ResRef("tar_m02aa".to_owned()));
+        assert!(save.get_player().is_err());
+    }
+
+    #[test]
+    fn saves_to_a_new_folder() {
+        let saves = TempDir::new().unwrap();
+        let save_path = saves.path().join("000001 - Game1");
+        let copy_path = saves.path().join("000002 - Copy");
+        write_save(&save_path);
+        let original = std::fs::read(save_path.join(SAVE_INFO)).unwrap();
+
+        let mut save = SaveGame::new(&save_path).unwrap();
+        save.save_info.set_save_name("Copy");
+        save.save_to(&copy_path).unwrap();
+
+        assert_eq!(save.path, copy_path);
+        assert_eq!(
+            SaveGame::new(&copy_path).unwrap().save_info.get_save_name(),
+            "Copy"
+        );
+        assert_eq!(std::fs::read(save_path.join(SAVE_INFO)).unwrap(), original);
+        assert_eq!(
+            folder_names(saves.path()),
+            ["000001 - Game1", "000002 - Copy"]
+        );
+    }
+
+    #[test]
+    fn failed_save_leaves_the_folder_alone() {
+        let saves = TempDir::new().unwrap();
+        let save_path = saves.path().join("000001 - Game1");
+        write_save(&save_path);
+        let before: Vec<Vec<u8>> = folder_names(&save_path)
+            .iter()
+            .map(|name| std::fs::read(save_path.join(name)).unwrap())
+            .collect();
+
+        let mut save = SaveGame::new(&save_path).unwrap();
+        save.party_table.set_credits(5000).unwrap();
+        save.save_info
+            .gff
+            .root
+            .set("ThisLabelIsFarTooLong", GffValue::Byte(0));
+        assert!(save.save().is_err());
+
+        let after: Vec<Vec<u8>> = folder_names(&save_path)
+            .iter()
+            .map(|name| std::fs::read(save_path.join(name)).unwrap())
+            .collect();
+        assert_eq!(after, before);
+        assert_eq!(folder_names(saves.path()), ["000001 - Game1"]);
+    }
+}
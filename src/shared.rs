@@ -1,5 +1,5 @@
 use phf::phf_map;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[cfg(target_os = "windows")]
 use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
@@ -96,20 +96,78 @@ pub(crate) static RES_TYPES: phf::Map<u16, &'static str> = phf_map! {
     0x270Fu16 => "key"
 };
 
-#[cfg(target_os = "windows")]
-pub(crate) fn resolve_windows_registry_key() -> Option<PathBuf> {
-    use std::str::FromStr;
+/// Looks up the resource type ID for a file extension, e.g. `"utc"`.
+pub(crate) fn get_resource_type_id(extension: &str) -> Option<u16> {
+    RES_TYPES
+        .entries()
+        .find(|(_, name)| name.eq_ignore_ascii_case(extension))
+        .map(|(type_id, _)| *type_id)
+}
+
+/// Finds `name` inside `directory`, ignoring case.
+///
+/// Installations copied from Windows mix `Override`, `override` and friends,
+/// which matters on case sensitive file systems.
+pub(crate) fn find_path_ignoring_case(directory: &Path, name: &str) -> Option<PathBuf> {
+    let exact = directory.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
 
-    let path: String = RegKey::predef(HKEY_LOCAL_MACHINE)
-        .open_subkey("SOFTWARE//Bioware//SW//Kotor")
+    std::fs::read_dir(directory)
         .ok()?
-        .get_value("Path")
-        .ok()?;
+        .filter_map(|entry| entry.ok())
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(name)
+        })
+        .map(|entry| entry.path())
+}
+
+/// Decodes a string stored in one of the game's 8-bit codepages.
+///
+/// Every byte is mapped to the code point of the same value, so the result can
+/// be turned back into the original bytes with [`encode_string`].
+pub(crate) fn decode_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+/// Encodes a string produced by [`decode_string`] back into its original bytes.
+pub(crate) fn encode_string(string: &str) -> Vec<u8> {
+    string
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+/// The registry keys the installers write the install path to, under `HKEY_LOCAL_MACHINE`.
+#[cfg(target_os = "windows")]
+const REGISTRY_KEYS: [&str; 4] = [
+    "SOFTWARE\\BioWare\\SW\\KOTOR",
+    "SOFTWARE\\WOW6432Node\\BioWare\\SW\\KOTOR",
+    "SOFTWARE\\LucasArts\\KotOR2",
+    "SOFTWARE\\WOW6432Node\\LucasArts\\KotOR2",
+];
+
+#[cfg(target_os = "windows")]
+pub(crate) fn resolve_windows_registry_keys() -> Vec<PathBuf> {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
 
-    PathBuf::from_str(path.as_ref()).ok()
+    REGISTRY_KEYS
+        .iter()
+        .filter_map(|key| {
+            hklm.open_subkey(key)
+                .ok()?
+                .get_value::<String, _>("Path")
+                .ok()
+        })
+        .map(PathBuf::from)
+        .collect()
 }
 
 #[cfg(not(target_os = "windows"))]
-pub(crate) fn resolve_windows_registry_key() -> Option<PathBuf> {
-    None
+pub(crate) fn resolve_windows_registry_keys() -> Vec<PathBuf> {
+    Vec::new()
 }
@@ -0,0 +1,253 @@
+use crate::dds::Dds;
+use crate::tga::Tga;
+use crate::tpc::{Tpc, TpcFormat};
+use crate::txi::Txi;
+use crate::{Error, Result};
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+use std::path::Path;
+
+/// The texture formats that can be converted to and from PNG.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextureKind {
+    Tpc,
+    Tga,
+    Dds,
+}
+
+impl TextureKind {
+    /// Picks the format from a file extension such as `.tpc`, ignoring case.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        match extension.as_str() {
+            "tpc" => Some(TextureKind::Tpc),
+            "tga" => Some(TextureKind::Tga),
+            "dds" => Some(TextureKind::Dds),
+            _ => None,
+        }
+    }
+}
+
+/// A PNG decoded to RGBA8 pixels, top row first.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PngImage {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<u8>,
+    /// Whether the PNG stored a single grey channel.
+    pub greyscale: bool,
+    /// Whether the PNG stored an alpha channel or a transparent colour.
+    pub alpha: bool,
+}
+
+/// Encodes RGBA8 pixels as a PNG with the smallest colour type that keeps every pixel.
+///
+/// Images where every pixel is grey are written as greyscale, and the alpha channel
+/// is only kept when some pixel is not fully opaque.
+pub fn encode_png(width: u16, height: u16, pixels: &[u8]) -> Result<Vec<u8>> {
+    let greyscale = pixels
+        .chunks_exact(4)
+        .all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+    let alpha = pixels.chunks_exact(4).any(|pixel| pixel[3] != 0xFF);
+
+    let (colour_type, data): (ColorType, Vec<u8>) = match (greyscale, alpha) {
+        (true, false) => (
+            ColorType::Grayscale,
+            pixels.chunks_exact(4).map(|pixel| pixel[0]).collect(),
+        ),
+        (true, true) => (
+            ColorType::GrayscaleAlpha,
+            pixels
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[3]])
+                .collect(),
+        ),
+        (false, false) => (
+            ColorType::Rgb,
+            pixels
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect(),
+        ),
+        (false, true) => (ColorType::Rgba, pixels.to_vec()),
+    };
+
+    let mut buffer = Vec::new();
+    let mut encoder = Encoder::new(&mut buffer, width as u32, height as u32);
+    encoder.set_color(colour_type);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+
+    Ok(buffer)
+}
+
+/// Decodes a PNG of any colour type and bit depth to RGBA8.
+pub fn decode_png(bytes: &[u8]) -> Result<PngImage> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let (width, height) = match (u16::try_from(info.width), u16::try_from(info.height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => {
+            return Err(Error::InvalidData(format!(
+                "{}x{} is too large for a texture",
+                info.width, info.height
+            )))
+        }
+    };
+
+    let pixels = match info.color_type {
+        ColorType::Grayscale => buffer
+            .iter()
+            .flat_map(|&value| [value, value, value, 0xFF])
+            .collect(),
+        ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xFF])
+            .collect(),
+        ColorType::Rgba => buffer,
+        ColorType::Indexed => {
+            return Err(Error::InvalidData(
+                "indexed PNG images were not expanded".to_owned(),
+            ))
+        }
+    };
+
+    Ok(PngImage {
+        width,
+        height,
+        pixels,
+        greyscale: matches!(
+            info.color_type,
+            ColorType::Grayscale | ColorType::GrayscaleAlpha
+        ),
+        alpha: matches!(info.color_type, ColorType::GrayscaleAlpha | ColorType::Rgba),
+    })
+}
+
+/// Converts a TPC, TGA or DDS texture to a PNG.
+///
+/// Every face of a cube map is exported, stacked from top to bottom.
+pub fn texture_to_png(bytes: &[u8], kind: TextureKind) -> Result<Vec<u8>> {
+    match kind {
+        TextureKind::Tpc => Tpc::from_bytes(bytes)?.to_png(),
+        TextureKind::Tga => Tga::from_bytes(bytes)?.to_png(),
+        TextureKind::Dds => Dds::from_bytes(bytes)?.to_png(),
+    }
+}
+
+/// Converts a PNG to a TPC, TGA or DDS texture.
+///
+/// TPC textures use `format` when given, or otherwise one picked from the PNG, and
+/// have `txi` appended. A `cube_map` reads six faces stacked from top to bottom, as
+/// [`texture_to_png`] exports them. These are ignored for the other kinds.
+pub fn png_to_texture(
+    png: &[u8],
+    kind: TextureKind,
+    format: Option<TpcFormat>,
+    cube_map: bool,
+    txi: Option<Txi>,
+) -> Result<Vec<u8>> {
+    match kind {
+        TextureKind::Tpc => Tpc::from_png(png, format, cube_map, txi)?.to_bytes(),
+        TextureKind::Tga => Tga::from_png(png)?.to_bytes(),
+        TextureKind::Dds => Dds::from_png(png)?.to_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colour_type(png: &[u8]) -> ColorType {
+        Decoder::new(png).read_info().unwrap().info().color_type
+    }
+
+    #[test]
+    fn png_colour_types_round_trip() {
+        let cases: [(&[u8], ColorType, bool, bool); 4] = [
+            (
+                &[7, 7, 7, 255, 200, 200, 200, 255],
+                ColorType::Grayscale,
+                true,
+                false,
+            ),
+            (
+                &[7, 7, 7, 0, 200, 200, 200, 255],
+                ColorType::GrayscaleAlpha,
+                true,
+                true,
+            ),
+            (
+                &[255, 0, 0, 255, 0, 0, 255, 255],
+                ColorType::Rgb,
+                false,
+                false,
+            ),
+            (
+                &[255, 0, 0, 128, 0, 0, 255, 255],
+                ColorType::Rgba,
+                false,
+                true,
+            ),
+        ];
+
+        for (pixels, expected, greyscale, alpha) in cases {
+            let png = encode_png(2, 1, pixels).unwrap();
+            assert_eq!(colour_type(&png), expected);
+
+            let image = decode_png(&png).unwrap();
+            assert_eq!(
+                image,
+                PngImage {
+                    width: 2,
+                    height: 1,
+                    pixels: pixels.to_vec(),
+                    greyscale,
+                    alpha,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn expands_sixteen_bit_pngs() {
+        let mut buffer = Vec::new();
+        let mut encoder = Encoder::new(&mut buffer, 1, 1);
+        encoder.set_color(ColorType::Rgb);
+        encoder.set_depth(BitDepth::Sixteen);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let image = decode_png(&buffer).unwrap();
+        assert_eq!(image.pixels, [0x12, 0x56, 0x9A, 0xFF]);
+        assert!(!image.greyscale && !image.alpha);
+    }
+
+    #[test]
+    fn picks_kind_from_extension() {
+        assert_eq!(
+            TextureKind::from_path(Path::new("lda_envmap.TPC")),
+            Some(TextureKind::Tpc)
+        );
+        assert_eq!(
+            TextureKind::from_path(Path::new("icon.tga")),
+            Some(TextureKind::Tga)
+        );
+        assert_eq!(TextureKind::from_path(Path::new("icon.png")), None);
+    }
+}
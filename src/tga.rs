@@ -0,0 +1,416 @@
+use crate::texture::{decode_png, encode_png};
+use crate::{Error, Result};
+use binrw::{binrw, io::Cursor, BinRead, BinWrite};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+
+const HEADER_SIZE: usize = 18;
+
+const IMAGE_TYPE_TRUE_COLOUR: u8 = 2;
+const IMAGE_TYPE_GREYSCALE: u8 = 3;
+/// Added to an image type when the pixels are run-length encoded.
+const IMAGE_TYPE_RLE: u8 = 8;
+
+/// Descriptor bit set when the first pixel is on the right.
+const ORIGIN_RIGHT: u8 = 0x10;
+/// Descriptor bit set when the first row is the top one.
+const ORIGIN_TOP: u8 = 0x20;
+
+#[binrw]
+#[brw(little)]
+#[derive(Debug, PartialEq)]
+struct TgaHeaders {
+    id_length: u8,
+    colour_map_type: u8,
+    image_type: u8,
+    colour_map_start: u16,
+    colour_map_length: u16,
+    colour_map_depth: u8,
+    x_origin: u16,
+    y_origin: u16,
+    width: u16,
+    height: u16,
+    pixel_depth: u8,
+    /// The number of alpha bits, plus the `ORIGIN_*` flags.
+    descriptor: u8,
+}
+
+/// A decoded Targa image, such as the `Screen.tga` thumbnail of a save.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tga {
+    pub width: u16,
+    pub height: u16,
+    /// RGBA8 pixels, top row first.
+    pub pixels: Vec<u8>,
+}
+
+impl Tga {
+    pub fn new(tga_filename: &str) -> Result<Self> {
+        let mut file = File::open(tga_filename)?;
+
+        Self::from_reader(&mut file)
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        Self::from_bytes(&buffer)
+    }
+
+    /// Decodes an uncompressed or RLE, 24/32-bit colour or 8-bit greyscale image.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let headers =
+            TgaHeaders::read(&mut Cursor::new(bytes)).map_err(|_| Error::MissingHeader)?;
+
+        let bytes_per_pixel = match (headers.image_type & !IMAGE_TYPE_RLE, headers.pixel_depth) {
+            (IMAGE_TYPE_TRUE_COLOUR, 24) => 3,
+            (IMAGE_TYPE_TRUE_COLOUR, 32) => 4,
+            (IMAGE_TYPE_GREYSCALE, 8) => 1,
+            (image_type, pixel_depth) => {
+                return Err(Error::InvalidData(format!(
+                    "unsupported TGA image type {} with {} bits per pixel",
+                    image_type, pixel_depth
+                )))
+            }
+        };
+
+        // Colour maps are allowed, but unused, alongside true colour images.
+        let colour_map_size = if headers.colour_map_type != 0 {
+            (headers.colour_map_length as usize * headers.colour_map_depth as usize).div_ceil(8)
+        } else {
+            0
+        };
+        let offset = HEADER_SIZE + headers.id_length as usize + colour_map_size;
+        let data = bytes
+            .get(offset..)
+            .ok_or_else(|| Error::InvalidData("TGA pixel data is truncated".to_owned()))?;
+
+        let width = headers.width as usize;
+        let height = headers.height as usize;
+        let size = width * height * bytes_per_pixel;
+        let data = if headers.image_type & IMAGE_TYPE_RLE != 0 {
+            Self::decode_rle(data, size, bytes_per_pixel)?
+        } else {
+            data.get(..size)
+                .ok_or_else(|| Error::InvalidData("TGA pixel data is truncated".to_owned()))?
+                .to_vec()
+        };
+
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let row = if headers.descriptor & ORIGIN_TOP != 0 {
+                y
+            } else {
+                height - 1 - y
+            };
+            for x in 0..width {
+                let column = if headers.descriptor & ORIGIN_RIGHT != 0 {
+                    width - 1 - x
+                } else {
+                    x
+                };
+
+                let offset = (row * width + column) * bytes_per_pixel;
+                let pixel = &data[offset..offset + bytes_per_pixel];
+                pixels.extend(match pixel {
+                    [value] => [*value, *value, *value, 0xFF],
+                    [b, g, r] => [*r, *g, *b, 0xFF],
+                    [b, g, r, a] => [*r, *g, *b, *a],
+                    _ => unreachable!(),
+                });
+            }
+        }
+
+        Ok(Tga {
+            width: headers.width,
+            height: headers.height,
+            pixels,
+        })
+    }
+
+    /// Builds an image from RGBA8 pixels, top row first.
+    pub fn from_rgba(width: u16, height: u16, pixels: Vec<u8>) -> Result<Self> {
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(Error::InvalidData(format!(
+                "expected {} bytes of RGBA data for a {}x{} image, found {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                pixels.len()
+            )));
+        }
+
+        Ok(Tga {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn from_png(bytes: &[u8]) -> Result<Self> {
+        let image = decode_png(bytes)?;
+
+        Self::from_rgba(image.width, image.height, image.pixels)
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        encode_png(self.width, self.height, &self.pixels)
+    }
+
+    pub fn save(&self, tga_filename: &str) -> Result<()> {
+        let mut file = File::create(tga_filename)?;
+
+        self.write_to(&mut file)
+    }
+
+    /// Writes an uncompressed image, bottom row first like the game's own screenshots.
+    ///
+    /// Images that are fully opaque are written as 24-bit, everything else as 32-bit.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_bytes()?)?;
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let opaque = self.pixels.chunks_exact(4).all(|pixel| pixel[3] == 0xFF);
+        let (pixel_depth, alpha_bits) = if opaque { (24, 0) } else { (32, 8) };
+
+        let mut buffer = Cursor::new(Vec::new());
+        TgaHeaders {
+            id_length: 0,
+            colour_map_type: 0,
+            image_type: IMAGE_TYPE_TRUE_COLOUR,
+            colour_map_start: 0,
+            colour_map_length: 0,
+            colour_map_depth: 0,
+            x_origin: 0,
+            y_origin: 0,
+            width: self.width,
+            height: self.height,
+            pixel_depth,
+            descriptor: alpha_bits,
+        }
+        .write(&mut buffer)?;
+
+        let mut buffer = buffer.into_inner();
+        let stride = self.width as usize * 4;
+        if stride != 0 {
+            for row in self.pixels.chunks_exact(stride).rev() {
+                for pixel in row.chunks_exact(4) {
+                    buffer.extend([pixel[2], pixel[1], pixel[0]]);
+                    if !opaque {
+                        buffer.push(pixel[3]);
+                    }
+                }
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    /// Expands run-length encoded packets into exactly `size` bytes of pixels.
+    fn decode_rle(data: &[u8], size: usize, bytes_per_pixel: usize) -> Result<Vec<u8>> {
+        let truncated = || Error::InvalidData("TGA pixel data is truncated".to_owned());
+
+        // No packet expands to more than 128 times its own size, so neither can the image.
+        let mut pixels = Vec::with_capacity(size.min(data.len().saturating_mul(128)));
+        let mut offset = 0;
+        while pixels.len() < size {
+            let packet = *data.get(offset).ok_or_else(truncated)?;
+            offset += 1;
+
+            let count = (packet & 0x7F) as usize + 1;
+            if packet & 0x80 != 0 {
+                let pixel = data
+                    .get(offset..offset + bytes_per_pixel)
+                    .ok_or_else(truncated)?;
+                offset += bytes_per_pixel;
+                for _ in 0..count {
+                    pixels.extend_from_slice(pixel);
+                }
+            } else {
+                let run = data
+                    .get(offset..offset + count * bytes_per_pixel)
+                    .ok_or_else(truncated)?;
+                offset += count * bytes_per_pixel;
+                pixels.extend_from_slice(run);
+            }
+        }
+
+        if pixels.len() != size {
+            return Err(Error::InvalidData(
+                "TGA run-length packet overruns the image".to_owned(),
+            ));
+        }
+
+        Ok(pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tga_bytes(
+        image_type: u8,
+        width: u16,
+        height: u16,
+        pixel_depth: u8,
+        descriptor: u8,
+    ) -> Vec<u8> {
+        let mut bytes = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.extend([pixel_depth, descriptor]);
+
+        bytes
+    }
+
+    /// A 3x2 image with a red top row and translucent blue bottom row.
+    fn sample_tga() -> Tga {
+        Tga::from_rgba(
+            3,
+            2,
+            [[255, 0, 0, 255]; 3]
+                .into_iter()
+                .chain([[0, 0, 255, 128]; 3])
+                .flatten()
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let tga = sample_tga();
+        let bytes = tga.to_bytes().unwrap();
+
+        assert_eq!(bytes[16], 32);
+        assert_eq!(bytes[17], 8);
+        assert_eq!(bytes[HEADER_SIZE..HEADER_SIZE + 4], [255, 0, 0, 128]);
+        assert_eq!(Tga::from_bytes(&bytes).unwrap(), tga);
+
+        let opaque = Tga::from_rgba(1, 2, vec![1, 2, 3, 255, 4, 5, 6, 255]).unwrap();
+        let bytes = opaque.to_bytes().unwrap();
+        assert_eq!(bytes[16], 24);
+        assert_eq!(bytes.len(), HEADER_SIZE + 6);
+        assert_eq!(Tga::from_bytes(&bytes).unwrap(), opaque);
+    }
+
+    #[test]
+    fn reads_both_origins() {
+        let rows: [&[u8]; 2] = [
+            &[0, 0, 255, 10, 0, 255, 0, 20],
+            &[255, 0, 0, 30, 9, 9, 9, 40],
+        ];
+
+        let mut top = tga_bytes(IMAGE_TYPE_TRUE_COLOUR, 2, 2, 32, 8 | ORIGIN_TOP);
+        top.extend(rows.concat());
+        let mut bottom = tga_bytes(IMAGE_TYPE_TRUE_COLOUR, 2, 2, 32, 8);
+        bottom.extend(rows[1]);
+        bottom.extend(rows[0]);
+        let mut right = tga_bytes(
+            IMAGE_TYPE_TRUE_COLOUR,
+            2,
+            2,
+            32,
+            8 | ORIGIN_TOP | ORIGIN_RIGHT,
+        );
+        right.extend(&rows[0][4..]);
+        right.extend(&rows[0][..4]);
+        right.extend(&rows[1][4..]);
+        right.extend(&rows[1][..4]);
+
+        let expected = vec![255, 0, 0, 10, 0, 255, 0, 20, 0, 0, 255, 30, 9, 9, 9, 40];
+        for bytes in [top, bottom, right] {
+            let tga = Tga::from_bytes(&bytes).unwrap();
+            assert_eq!(tga.pixels, expected);
+            assert_eq!(Tga::from_bytes(&tga.to_bytes().unwrap()).unwrap(), tga);
+        }
+    }
+
+    #[test]
+    fn reads_run_length_encoding() {
+        for descriptor in [0, ORIGIN_TOP] {
+            let mut bytes = tga_bytes(
+                IMAGE_TYPE_TRUE_COLOUR | IMAGE_TYPE_RLE,
+                3,
+                2,
+                24,
+                descriptor,
+            );
+            // A run of three red pixels, then three raw pixels.
+            bytes.extend([0x82, 0, 0, 255]);
+            bytes.extend([0x02, 255, 0, 0, 0, 255, 0, 9, 9, 9]);
+
+            let tga = Tga::from_bytes(&bytes).unwrap();
+            let (first, second) = tga.pixels.split_at(12);
+            let (red, raw) = if descriptor == ORIGIN_TOP {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            assert_eq!(red, [255, 0, 0, 255].repeat(3));
+            assert_eq!(raw, [0, 0, 255, 255, 0, 255, 0, 255, 9, 9, 9, 255]);
+            assert_eq!(Tga::from_bytes(&tga.to_bytes().unwrap()).unwrap(), tga);
+        }
+    }
+
+    #[test]
+    fn reads_greyscale() {
+        let mut bytes = tga_bytes(IMAGE_TYPE_GREYSCALE, 2, 1, 8, 0);
+        bytes.extend([0x40, 0xC0]);
+        let tga = Tga::from_bytes(&bytes).unwrap();
+        assert_eq!(tga.pixels, [0x40, 0x40, 0x40, 255, 0xC0, 0xC0, 0xC0, 255]);
+
+        let mut rle = tga_bytes(IMAGE_TYPE_GREYSCALE | IMAGE_TYPE_RLE, 2, 1, 8, 0);
+        rle.extend([0x00, 0x40, 0x00, 0xC0]);
+        assert_eq!(Tga::from_bytes(&rle).unwrap(), tga);
+
+        assert_eq!(Tga::from_bytes(&tga.to_bytes().unwrap()).unwrap(), tga);
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let mut overrun = tga_bytes(IMAGE_TYPE_GREYSCALE | IMAGE_TYPE_RLE, 2, 1, 8, 0);
+        overrun.extend([0x82, 0x40]);
+        assert!(matches!(
+            Tga::from_bytes(&overrun),
+            Err(Error::InvalidData(_))
+        ));
+
+        let mut truncated = tga_bytes(IMAGE_TYPE_TRUE_COLOUR, 2, 2, 24, 0);
+        truncated.extend([0; 9]);
+        assert!(matches!(
+            Tga::from_bytes(&truncated),
+            Err(Error::InvalidData(_))
+        ));
+
+        let mut huge_rle = tga_bytes(IMAGE_TYPE_TRUE_COLOUR | IMAGE_TYPE_RLE, 65535, 65535, 32, 0);
+        huge_rle.extend([0xFF, 1, 2, 3, 4]);
+        assert!(matches!(
+            Tga::from_bytes(&huge_rle),
+            Err(Error::InvalidData(_))
+        ));
+
+        let mut short_rle = tga_bytes(IMAGE_TYPE_TRUE_COLOUR | IMAGE_TYPE_RLE, 2, 1, 24, 0);
+        short_rle.extend([0x00, 1, 2, 3]);
+        assert!(matches!(
+            Tga::from_bytes(&short_rle),
+            Err(Error::InvalidData(_))
+        ));
+
+        let colour_mapped = tga_bytes(1, 2, 2, 8, 0);
+        assert!(matches!(
+            Tga::from_bytes(&colour_mapped),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(
+            Tga::from_bytes(&[0; 10]),
+            Err(Error::MissingHeader)
+        ));
+        assert!(Tga::from_rgba(2, 2, vec![0; 12]).is_err());
+    }
+}
@@ -1 +1,579 @@
-pub struct Tlk {}
+use crate::gff::ExoLocString;
+use crate::shared::{decode_string, encode_string};
+use crate::{Error, Result};
+use binrw::{binrw, io::Cursor, BinRead, BinWrite};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+/// The entry has text in the string data table.
+pub const TEXT_PRESENT: u32 = 0x0001;
+/// The entry has a sound ResRef.
+pub const SND_PRESENT: u32 = 0x0002;
+/// The entry has a sound length.
+pub const SNDLENGTH_PRESENT: u32 = 0x0004;
+
+/// StrRefs with this bit set refer to the custom (alternate) talk table.
+pub const CUSTOM_TLK_FLAG: u32 = 0x0100_0000;
+
+const HEADER_SIZE: u32 = 20;
+const STRING_DATA_SIZE: u32 = 40;
+
+#[binrw]
+#[brw(little, magic = b"TLK ")]
+#[derive(Debug, Eq, PartialEq)]
+struct TlkHeader {
+    version: [u8; 4],
+    language_id: u32,
+    string_count: u32,
+    string_entries_offset: u32,
+}
+
+#[binrw]
+#[brw(little)]
+#[derive(Debug, PartialEq)]
+struct TlkStringData {
+    flags: u32,
+    #[br(map = |x: [u8; 16]| decode_string(&x).trim_end_matches('\0').to_owned())]
+    #[bw(map = |s| encode_string(s), pad_size_to = 16)]
+    sound_resref: String,
+    volume_variance: u32,
+    pitch_variance: u32,
+    offset_to_string: u32,
+    string_size: u32,
+    sound_length: f32,
+}
+
+/// A single talk table entry.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TlkEntry {
+    /// Any of [`TEXT_PRESENT`], [`SND_PRESENT`] and [`SNDLENGTH_PRESENT`].
+    pub flags: u32,
+    pub sound_resref: String,
+    pub volume_variance: u32,
+    pub pitch_variance: u32,
+    /// The length of the sound in seconds.
+    pub sound_length: f32,
+    pub text: String,
+}
+
+impl TlkEntry {
+    pub fn new(text: &str) -> Self {
+        TlkEntry {
+            flags: TEXT_PRESENT,
+            text: text.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A struct representing a parsed talk table (TLK V3.0), e.g. `dialog.tlk`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tlk {
+    /// The language of every string in the table, 0 being English.
+    pub language_id: u32,
+    /// The entries, indexed by StrRef.
+    pub entries: Vec<TlkEntry>,
+}
+
+impl Tlk {
+    pub fn new(tlk_filename: &str) -> Result<Self> {
+        let mut file = Self::open_file(tlk_filename)?;
+
+        Self::from_reader(&mut file)
+    }
+
+    fn open_file(filename: &str) -> Result<File> {
+        File::open(filename).map_err(Into::into)
+    }
+
+    /// Parses a talk table starting at the current position of `reader`.
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        Self::from_bytes(&buffer)
+    }
+
+    /// Reads only the number of strings from the header of a talk table.
+    pub fn read_string_count<R: Read + Seek>(reader: &mut R) -> Result<u32> {
+        let header = TlkHeader::read(reader).map_err(|_| Error::MissingHeader)?;
+        if &header.version != b"V3.0" {
+            return Err(Error::MissingHeader);
+        }
+
+        Ok(header.string_count)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(bytes);
+
+        let header = TlkHeader::read(&mut cursor).map_err(|_| Error::MissingHeader)?;
+        if &header.version != b"V3.0" {
+            return Err(Error::MissingHeader);
+        }
+
+        // The count comes from the file, so only reserve what the data could hold.
+        let available =
+            bytes.len().saturating_sub(HEADER_SIZE as usize) / STRING_DATA_SIZE as usize;
+        let mut entries = Vec::with_capacity(available.min(header.string_count as usize));
+        for _ in 0..header.string_count {
+            let data = TlkStringData::read(&mut cursor)?;
+
+            let start = header.string_entries_offset as usize + data.offset_to_string as usize;
+            let text = bytes
+                .get(start..start + data.string_size as usize)
+                .map(|text| decode_string(text).trim_end_matches('\0').to_owned())
+                .ok_or_else(|| {
+                    Error::InvalidData(format!("TLK string data at {} out of range", start))
+                })?;
+
+            entries.push(TlkEntry {
+                flags: data.flags,
+                sound_resref: data.sound_resref,
+                volume_variance: data.volume_variance,
+                pitch_variance: data.pitch_variance,
+                sound_length: data.sound_length,
+                text,
+            });
+        }
+
+        Ok(Tlk {
+            language_id: header.language_id,
+            entries,
+        })
+    }
+
+    pub fn save(&self, tlk_filename: &str) -> Result<()> {
+        let mut file = File::create(tlk_filename)?;
+
+        self.write_to(&mut file)
+    }
+
+    pub fn write_to<W: Write + Seek>(&self, writer: &mut W) -> Result<()> {
+        let string_entries_offset = HEADER_SIZE + STRING_DATA_SIZE * self.entries.len() as u32;
+
+        TlkHeader {
+            version: *b"V3.0",
+            language_id: self.language_id,
+            string_count: self.entries.len() as u32,
+            string_entries_offset,
+        }
+        .write(writer)?;
+
+        let mut strings = Vec::new();
+        for entry in &self.entries {
+            if encode_string(&entry.sound_resref).len() > 16 {
+                return Err(Error::InvalidData(format!(
+                    "sound ResRef {} is longer than 16 bytes",
+                    entry.sound_resref
+                )));
+            }
+            let text = encode_string(&entry.text);
+
+            TlkStringData {
+                flags: entry.flags,
+                sound_resref: entry.sound_resref.clone(),
+                volume_variance: entry.volume_variance,
+                pitch_variance: entry.pitch_variance,
+                offset_to_string: strings.len() as u32,
+                string_size: text.len() as u32,
+                sound_length: entry.sound_length,
+            }
+            .write(writer)?;
+
+            strings.extend(text);
+        }
+
+        writer.write_all(&strings)?;
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write_to(&mut cursor)?;
+
+        Ok(cursor.into_inner())
+    }
+
+    /// Appends an entry, returning its StrRef.
+    pub fn push(&mut self, entry: TlkEntry) -> u32 {
+        self.entries.push(entry);
+
+        (self.entries.len() - 1) as u32
+    }
+
+    pub fn get_entry_mut(&mut self, str_ref: u32) -> Option<&mut TlkEntry> {
+        self.entries.get_mut(str_ref as usize)
+    }
+
+    /// Replaces the text of an existing entry, returning `false` if there is none.
+    pub fn set_string(&mut self, str_ref: u32, text: &str) -> bool {
+        match self.get_entry_mut(str_ref) {
+            Some(entry) => {
+                entry.text = text.to_owned();
+                entry.flags |= TEXT_PRESENT;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get_entry(&self, str_ref: u32) -> Option<&TlkEntry> {
+        self.entries.get(str_ref as usize)
+    }
+
+    pub fn get_string(&self, str_ref: u32) -> Option<&str> {
+        self.get_entry(str_ref).map(|entry| entry.text.as_str())
+    }
+
+    /// The text of an entry, or `None` if it has no [`TEXT_PRESENT`] flag.
+    pub fn get_text(&self, str_ref: u32) -> Option<&str> {
+        self.get_entry(str_ref)
+            .filter(|entry| entry.flags & TEXT_PRESENT != 0)
+            .map(|entry| entry.text.as_str())
+    }
+
+    /// Resolves a GFF localised string to display text.
+    ///
+    /// The talk table entry wins when the string has a StrRef whose entry has
+    /// text, otherwise the embedded text for this table's language is used.
+    pub fn resolve(&self, loc_string: &ExoLocString) -> Option<String> {
+        if let Some(text) = loc_string
+            .get_str_ref()
+            .and_then(|str_ref| self.get_text(str_ref))
+        {
+            return Some(text.to_owned());
+        }
+
+        loc_string
+            .strings
+            .iter()
+            .find(|substring| substring.language() == self.language_id)
+            .or_else(|| loc_string.strings.first())
+            .map(|substring| substring.string.clone())
+    }
+}
+
+/// The talk tables the game resolves StrRefs against.
+///
+/// Languages with gendered text ship a `dialogf.tlk` next to `dialog.tlk`; the
+/// female table is consulted first for feminine speakers and falls back to the
+/// male one. StrRefs carrying [`CUSTOM_TLK_FLAG`] are looked up in the custom
+/// tables instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TalkTables {
+    pub male: Tlk,
+    pub female: Option<Tlk>,
+    pub custom_male: Option<Tlk>,
+    pub custom_female: Option<Tlk>,
+}
+
+impl TalkTables {
+    pub fn new(male: Tlk) -> Self {
+        TalkTables {
+            male,
+            ..Default::default()
+        }
+    }
+
+    /// Loads `dialog.tlk` and, when present, `dialogf.tlk` from an installation.
+    pub fn from_installation(installation_path: &Path) -> Result<Self> {
+        let male = Tlk::new(&installation_path.join("dialog.tlk").to_string_lossy())?;
+
+        let female_path = installation_path.join("dialogf.tlk");
+        let female = if female_path.is_file() {
+            Some(Tlk::new(&female_path.to_string_lossy())?)
+        } else {
+            None
+        };
+
+        Ok(TalkTables {
+            male,
+            female,
+            ..Default::default()
+        })
+    }
+
+    /// Looks up a StrRef, skipping entries that have no text.
+    ///
+    /// Either table of a pair may be missing, in which case the other one is used.
+    pub fn get_string(&self, str_ref: u32, feminine: bool) -> Option<&str> {
+        let (male, female, index) = if str_ref & CUSTOM_TLK_FLAG != 0 {
+            (
+                self.custom_male.as_ref(),
+                self.custom_female.as_ref(),
+                str_ref & !CUSTOM_TLK_FLAG,
+            )
+        } else {
+            (Some(&self.male), self.female.as_ref(), str_ref)
+        };
+
+        let female_text = || {
+            female
+                .and_then(|table| table.get_text(index))
+                .filter(|text| !text.is_empty())
+        };
+        let male_text = || male.and_then(|table| table.get_text(index));
+
+        if feminine {
+            female_text().or_else(male_text)
+        } else {
+            male_text().or_else(female_text)
+        }
+    }
+
+    pub fn resolve(&self, loc_string: &ExoLocString, feminine: bool) -> Option<String> {
+        if let Some(text) = loc_string
+            .get_str_ref()
+            .and_then(|str_ref| self.get_string(str_ref, feminine))
+        {
+            return Some(text.to_owned());
+        }
+
+        let id = self.male.language_id * 2 + u32::from(feminine);
+        loc_string
+            .get_string(id)
+            .or_else(|| loc_string.get_string(id & !1))
+            .or_else(|| loc_string.strings.first().map(|s| s.string.as_str()))
+            .map(ToOwned::to_owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gff::ExoLocSubString;
+
+    /// Builds a talk table by hand, one `(flags, sound, text)` tuple per entry.
+    fn tlk_bytes(language_id: u32, entries: &[(u32, &str, &str)]) -> Vec<u8> {
+        let string_entries_offset = HEADER_SIZE + STRING_DATA_SIZE * entries.len() as u32;
+
+        let mut bytes = b"TLK V3.0".to_vec();
+        bytes.extend(language_id.to_le_bytes());
+        bytes.extend((entries.len() as u32).to_le_bytes());
+        bytes.extend(string_entries_offset.to_le_bytes());
+
+        let mut strings: Vec<u8> = Vec::new();
+        for (flags, sound, text) in entries {
+            let mut sound_resref = [0u8; 16];
+            sound_resref[..sound.len()].copy_from_slice(sound.as_bytes());
+
+            bytes.extend(flags.to_le_bytes());
+            bytes.extend(sound_resref);
+            bytes.extend(0u32.to_le_bytes());
+            bytes.extend(0u32.to_le_bytes());
+            bytes.extend((strings.len() as u32).to_le_bytes());
+            bytes.extend((text.len() as u32).to_le_bytes());
+            bytes.extend(1.5f32.to_le_bytes());
+            strings.extend(text.as_bytes());
+        }
+        bytes.extend(strings);
+
+        bytes
+    }
+
+    fn loc_string(str_ref: u32, text: &str) -> ExoLocString {
+        ExoLocString {
+            str_ref,
+            strings: vec![ExoLocSubString {
+                id: 0,
+                string: text.to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn reads_entries() {
+        let bytes = tlk_bytes(
+            0,
+            &[
+                (TEXT_PRESENT, "", "Bastila"),
+                (
+                    TEXT_PRESENT | SND_PRESENT | SNDLENGTH_PRESENT,
+                    "n_bastila",
+                    "Hi",
+                ),
+            ],
+        );
+
+        let tlk = Tlk::from_bytes(&bytes).unwrap();
+
+        assert_eq!(tlk.len(), 2);
+        assert_eq!(tlk.get_string(0), Some("Bastila"));
+        let entry = tlk.get_entry(1).unwrap();
+        assert_eq!(entry.sound_resref, "n_bastila");
+        assert_eq!(entry.sound_length, 1.5);
+        assert_eq!(entry.text, "Hi");
+        assert_eq!(tlk.get_string(2), None);
+    }
+
+    #[test]
+    fn reads_string_count() {
+        let bytes = tlk_bytes(0, &[(TEXT_PRESENT, "", "a"), (TEXT_PRESENT, "", "b")]);
+
+        assert_eq!(Tlk::read_string_count(&mut Cursor::new(bytes)).unwrap(), 2);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut bytes = tlk_bytes(0, &[(TEXT_PRESENT, "", "a")]);
+        bytes[4..8].copy_from_slice(b"V4.0");
+        assert!(matches!(Tlk::from_bytes(&bytes), Err(Error::MissingHeader)));
+
+        assert!(matches!(Tlk::from_bytes(b"TLK"), Err(Error::MissingHeader)));
+    }
+
+    #[test]
+    fn rejects_out_of_range_strings() {
+        let mut bytes = tlk_bytes(0, &[(TEXT_PRESENT, "", "abc")]);
+        bytes.truncate(bytes.len() - 1);
+
+        assert!(matches!(
+            Tlk::from_bytes(&bytes),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_overstated_string_count() {
+        let mut bytes = tlk_bytes(0, &[(TEXT_PRESENT, "", "abc")]);
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(Tlk::from_bytes(&bytes).is_err());
+        assert!(Tlk::from_bytes(&bytes[..HEADER_SIZE as usize]).is_err());
+    }
+
+    #[test]
+    fn resolve_prefers_talk_table_text() {
+        let tlk = Tlk::from_bytes(&tlk_bytes(0, &[(TEXT_PRESENT, "", "From TLK")])).unwrap();
+
+        assert_eq!(tlk.resolve(&loc_string(0, "Embedded")).unwrap(), "From TLK");
+        assert_eq!(
+            tlk.resolve(&loc_string(u32::MAX, "Embedded")).unwrap(),
+            "Embedded"
+        );
+    }
+
+    #[test]
+    fn resolve_skips_entries_without_text() {
+        let tlk = Tlk::from_bytes(&tlk_bytes(0, &[(SND_PRESENT, "n_sound", "")])).unwrap();
+
+        assert_eq!(tlk.get_text(0), None);
+        assert_eq!(tlk.resolve(&loc_string(0, "Embedded")).unwrap(), "Embedded");
+    }
+
+    fn tlk(texts: &[&str]) -> Tlk {
+        Tlk {
+            language_id: 0,
+            entries: texts.iter().map(|text| TlkEntry::new(text)).collect(),
+        }
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let mut tlk = tlk(&["Bastila", "Carth"]);
+        let str_ref = tlk.push(TlkEntry {
+            flags: TEXT_PRESENT | SND_PRESENT | SNDLENGTH_PRESENT,
+            sound_resref: "n_carth_01".to_owned(),
+            volume_variance: 1,
+            pitch_variance: 2,
+            sound_length: 3.5,
+            text: "Onasi \u{e9}".to_owned(),
+        });
+        assert!(tlk.set_string(0, "Shan"));
+        assert!(!tlk.set_string(10, "Missing"));
+
+        let bytes = tlk.to_bytes().unwrap();
+
+        assert_eq!(str_ref, 2);
+        assert_eq!(bytes, tlk_bytes_from(&tlk));
+        assert_eq!(Tlk::from_bytes(&bytes).unwrap(), tlk);
+    }
+
+    /// The writer's output, rebuilt with the hand-written layout above.
+    fn tlk_bytes_from(tlk: &Tlk) -> Vec<u8> {
+        let mut bytes = tlk_bytes(
+            tlk.language_id,
+            &tlk.entries
+                .iter()
+                .map(|entry| (entry.flags, entry.sound_resref.as_str(), ""))
+                .collect::<Vec<_>>(),
+        );
+
+        let mut strings = Vec::new();
+        for (idx, entry) in tlk.entries.iter().enumerate() {
+            let start = (HEADER_SIZE + STRING_DATA_SIZE * idx as u32) as usize;
+            let text = encode_string(&entry.text);
+            bytes[start + 20..start + 24].copy_from_slice(&entry.volume_variance.to_le_bytes());
+            bytes[start + 24..start + 28].copy_from_slice(&entry.pitch_variance.to_le_bytes());
+            bytes[start + 28..start + 32].copy_from_slice(&(strings.len() as u32).to_le_bytes());
+            bytes[start + 32..start + 36].copy_from_slice(&(text.len() as u32).to_le_bytes());
+            bytes[start + 36..start + 40].copy_from_slice(&entry.sound_length.to_le_bytes());
+            strings.extend(text);
+        }
+        bytes.extend(strings);
+
+        bytes
+    }
+
+    #[test]
+    fn rejects_overlong_sound_resrefs() {
+        let mut tlk = tlk(&["Bastila"]);
+        tlk.entries[0].sound_resref = "a_resref_too_long".to_owned();
+
+        assert!(matches!(tlk.to_bytes(), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn female_table_falls_back_to_male() {
+        let tables = TalkTables {
+            female: Some(tlk(&["Her", ""])),
+            ..TalkTables::new(tlk(&["His", "Shared"]))
+        };
+
+        assert_eq!(tables.get_string(0, true), Some("Her"));
+        assert_eq!(tables.get_string(0, false), Some("His"));
+        assert_eq!(tables.get_string(1, true), Some("Shared"));
+        assert_eq!(tables.get_string(2, true), None);
+    }
+
+    #[test]
+    fn custom_tables_fall_back_to_each_other() {
+        let female_only = TalkTables {
+            custom_female: Some(tlk(&["Custom her"])),
+            ..TalkTables::new(tlk(&["His"]))
+        };
+        assert_eq!(
+            female_only.get_string(CUSTOM_TLK_FLAG, false),
+            Some("Custom her")
+        );
+        assert_eq!(
+            female_only.get_string(CUSTOM_TLK_FLAG, true),
+            Some("Custom her")
+        );
+
+        let male_only = TalkTables {
+            custom_male: Some(tlk(&["Custom his"])),
+            ..TalkTables::new(tlk(&["His"]))
+        };
+        assert_eq!(
+            male_only.get_string(CUSTOM_TLK_FLAG, true),
+            Some("Custom his")
+        );
+
+        assert_eq!(
+            TalkTables::new(tlk(&["His"])).get_string(CUSTOM_TLK_FLAG, false),
+            None
+        );
+    }
+}
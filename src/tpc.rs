@@ -1,118 +1,1247 @@
+use crate::texture::{decode_png, encode_png};
+use crate::txi::Txi;
 use crate::{Error, Result};
-use binrw::{binrw, BinRead, BinWrite, BinWriterExt};
-use bitvec::prelude::*;
+use binrw::{binrw, io::Cursor, BinRead, BinWrite};
 use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
+
+const HEADER_SIZE: usize = 128;
+
+/// Cube maps store their six faces one after another.
+const CUBE_MAP_FACES: usize = 6;
+
+/// One byte per pixel, used for masks and some GUI textures.
+pub const ENCODING_GREYSCALE: u8 = 1;
+/// Uncompressed RGB, or DXT1 when the header has a data size.
+pub const ENCODING_RGB: u8 = 2;
+/// Uncompressed RGBA, or DXT5 when the header has a data size.
+pub const ENCODING_RGBA: u8 = 4;
 
 #[binrw]
 #[brw(little)]
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub struct TpcHeaders {
+    /// The size of the compressed top level image, or 0 for uncompressed textures.
     data_size: u32,
-    reserved: u32,
+    /// Pixels with an alpha below this are discarded when rendering.
+    alpha_test: f32,
     x_size: u16,
+    /// The height of every face together, six times the width for cube maps.
     y_size: u16,
     encoding: u8,
-    dummy: [u8; 115],
+    #[brw(pad_after = 114)]
+    mip_count: u8,
 }
 
-#[binrw]
-#[brw(little)]
-#[derive(Debug, Eq, PartialEq)]
+/// How [`Tpc::from_rgba`] stores the pixel data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TpcFormat {
+    /// One byte per pixel, taken from the luminance of each pixel.
+    Greyscale,
+    /// Uncompressed, without alpha.
+    Rgb,
+    /// Uncompressed, with alpha.
+    Rgba,
+    /// DXT1 compressed, with pixels below half alpha made fully transparent.
+    Dxt1,
+    /// DXT5 compressed, with interpolated alpha.
+    Dxt5,
+}
+
+impl TpcFormat {
+    fn get_encoding(self) -> (u8, bool) {
+        match self {
+            TpcFormat::Greyscale => (ENCODING_GREYSCALE, false),
+            TpcFormat::Rgb => (ENCODING_RGB, false),
+            TpcFormat::Rgba => (ENCODING_RGBA, false),
+            TpcFormat::Dxt1 => (ENCODING_RGB, true),
+            TpcFormat::Dxt5 => (ENCODING_RGBA, true),
+        }
+    }
+}
+
+/// A single mip level of a texture face.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TpcMipmap {
+    pub width: u16,
+    pub height: u16,
+    /// RGBA8 pixels, top row first.
+    pub pixels: Vec<u8>,
+}
+
+/// A struct representing a decoded TPC texture.
+#[derive(Debug, PartialEq)]
 pub struct Tpc {
     headers: TpcHeaders,
-    #[br(ignore)]
-    pixels: Vec<u8>,
+    /// Every mip level of every face, largest first. Only cube maps have more than one face.
+    faces: Vec<Vec<TpcMipmap>>,
+    /// The TXI text stored after the pixel data, if any.
+    txi: Option<Txi>,
 }
 
 impl Tpc {
-    pub fn new(tpc_filename: &str) -> Self {
-        let mut buffer = Self::open_file(tpc_filename).unwrap();
-
-        let mut self_return = Self::read(&mut buffer).unwrap();
-
-        buffer
-            .seek(std::io::SeekFrom::Start(128))
-            .expect("Not a valid TPC file, not long enough");
-
-        let pixels = if self_return.headers.data_size == 0 && self_return.headers.encoding == 2 {
-            Self::calculate_second_encoding_pixels(&self_return, &mut buffer)
-        } else if self_return.headers.data_size == 0 && self_return.headers.encoding == 4 {
-            Self::calculate_fourth_encoding_pixels(&self_return, &mut buffer)
-        } else if self_return.headers.encoding == 2 {
-            Self::decode_dxt1(&self_return, &mut buffer)
-        } else if self_return.headers.encoding == 4 {
-            Self::decode_dxt5(&self_return, &mut buffer)
+    pub fn new(tpc_filename: &str) -> Result<Self> {
+        let mut file = Self::open_file(tpc_filename)?;
+
+        Self::from_reader(&mut file)
+    }
+
+    fn open_file(filename: &str) -> Result<File> {
+        File::open(filename).map_err(Into::into)
+    }
+
+    /// Parses a texture starting at the current position of `reader`.
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        Self::from_bytes(&buffer)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let headers =
+            TpcHeaders::read(&mut Cursor::new(bytes)).map_err(|_| Error::MissingHeader)?;
+        let compressed = headers.data_size != 0;
+        if !matches!(
+            (headers.encoding, compressed),
+            (ENCODING_GREYSCALE, false) | (ENCODING_RGB, _) | (ENCODING_RGBA, _)
+        ) {
+            return Err(Error::InvalidData(format!(
+                "unsupported TPC encoding {}",
+                headers.encoding
+            )));
+        }
+
+        if headers.x_size == 0 || headers.y_size == 0 {
+            return Err(Error::InvalidData(format!(
+                "TPC is {}x{} pixels",
+                headers.x_size, headers.y_size
+            )));
+        }
+
+        let width = headers.x_size as usize;
+        let (face_count, height) = if headers.y_size as usize == width * CUBE_MAP_FACES {
+            (CUBE_MAP_FACES, width)
         } else {
-            Err(Error::MissingHeader)
+            (1, headers.y_size as usize)
         };
+        let mip_count = headers.mip_count.max(1) as usize;
 
-        self_return.pixels = pixels.expect("Not a valid TPC file.");
+        let mut offset = HEADER_SIZE;
+        let mut faces = Vec::with_capacity(face_count);
+        for _ in 0..face_count {
+            let mut mipmaps = Vec::with_capacity(mip_count);
+            for level in 0..mip_count {
+                let mip_width = (width >> level).max(1);
+                let mip_height = (height >> level).max(1);
 
-        return self_return;
+                let size = Self::get_data_size(headers.encoding, compressed, mip_width, mip_height);
+                let data = bytes
+                    .get(offset..offset + size)
+                    .ok_or_else(|| Error::InvalidData("TPC pixel data is truncated".to_owned()))?;
+                offset += size;
+
+                mipmaps.push(TpcMipmap {
+                    width: mip_width as u16,
+                    height: mip_height as u16,
+                    pixels: Self::decode(headers.encoding, compressed, data, mip_width, mip_height),
+                });
+            }
+            faces.push(mipmaps);
+        }
+
+        let txi = bytes
+            .get(offset..)
+            .filter(|trailer| trailer.iter().any(|&byte| byte != 0))
+            .map(Txi::from_bytes)
+            .filter(|txi| !txi.is_empty());
+
+        Ok(Tpc {
+            headers,
+            faces,
+            txi,
+        })
     }
 
-    fn open_file(filename: &str) -> Result<File> {
-        File::open(filename).map_err(Into::into)
+    /// Builds a texture from RGBA8 pixels, top row first, generating every mip level.
+    ///
+    /// A `cube_map` takes its six faces stacked from top to bottom, so the image
+    /// must be six times as tall as it is wide. Other images cannot have that shape,
+    /// as readers take it to mean a cube map. The pixels are kept as given and
+    /// only encoded into `format` when the texture is written.
+    pub fn from_rgba(
+        width: u16,
+        height: u16,
+        pixels: Vec<u8>,
+        format: TpcFormat,
+        cube_map: bool,
+        txi: Option<Txi>,
+    ) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidData("TPC images cannot be empty".to_owned()));
+        }
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(Error::InvalidData(format!(
+                "expected {} bytes of RGBA data for a {}x{} image, found {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                pixels.len()
+            )));
+        }
+
+        let (face_count, face_height) = if cube_map {
+            if height as usize != width as usize * CUBE_MAP_FACES {
+                return Err(Error::InvalidData(format!(
+                    "a {}x{} image cannot hold six square cube map faces",
+                    width, height
+                )));
+            }
+
+            (CUBE_MAP_FACES, width)
+        } else {
+            if height as usize == width as usize * CUBE_MAP_FACES {
+                return Err(Error::InvalidData(format!(
+                    "a {}x{} image would be read back as a cube map",
+                    width, height
+                )));
+            }
+
+            (1, height)
+        };
+        let mip_count = (width.max(face_height).ilog2() + 1) as u8;
+        let (encoding, compressed) = format.get_encoding();
+
+        let face_size = width as usize * face_height as usize * 4;
+        let faces = pixels
+            .chunks_exact(face_size)
+            .take(face_count)
+            .map(|face| {
+                let mut mipmaps = vec![TpcMipmap {
+                    width,
+                    height: face_height,
+                    pixels: face.to_vec(),
+                }];
+                while mipmaps.len() < mip_count as usize {
+                    let next = Self::downsample(mipmaps.last().unwrap());
+                    mipmaps.push(next);
+                }
+
+                mipmaps
+            })
+            .collect();
+
+        let data_size = if compressed {
+            Self::get_data_size(encoding, true, width as usize, face_height as usize) as u32
+        } else {
+            0
+        };
+
+        Ok(Tpc {
+            headers: TpcHeaders {
+                data_size,
+                alpha_test: 0.0,
+                x_size: width,
+                y_size: height,
+                encoding,
+                mip_count,
+            },
+            faces,
+            txi,
+        })
     }
 
-    fn bgr24_to_rgb24(bgr24: u32) -> u32 {
-        ((bgr24 & 0xFF) << 16) | (bgr24 & 0xFF00FF00) | ((bgr24 >> 16) & 0xFF)
+    /// Builds a texture from a PNG, see [`Tpc::from_rgba`].
+    ///
+    /// Without a `format`, greyscale PNGs stay greyscale, PNGs with transparent pixels
+    /// become DXT5 and everything else becomes DXT1. A PNG exported by [`Tpc::to_png`]
+    /// from a cube map is read back as one when `cube_map` is set.
+    pub fn from_png(
+        bytes: &[u8],
+        format: Option<TpcFormat>,
+        cube_map: bool,
+        txi: Option<Txi>,
+    ) -> Result<Self> {
+        let image = decode_png(bytes)?;
+        let transparent = image.alpha && image.pixels.chunks_exact(4).any(|pixel| pixel[3] != 0xFF);
+        let format = format.unwrap_or(match (image.greyscale, transparent) {
+            (true, false) => TpcFormat::Greyscale,
+            (_, true) => TpcFormat::Dxt5,
+            (false, false) => TpcFormat::Dxt1,
+        });
+
+        Self::from_rgba(
+            image.width,
+            image.height,
+            image.pixels,
+            format,
+            cube_map,
+            txi,
+        )
     }
 
-    fn calculate_second_encoding_pixels(&self, file: &mut File) -> Result<Vec<u8>> {
-        for y in (1..self.headers.y_size).rev() {
-            let offset = self.headers.x_size * (y - 1);
+    /// Encodes the full size image as a PNG, with every face of a cube map stacked
+    /// from top to bottom.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let pixels: Vec<u8> = self
+            .faces
+            .iter()
+            .flat_map(|mipmaps| mipmaps[0].pixels.iter().copied())
+            .collect();
 
-            let mut packed: Vec<u8> = vec![0; 3 * self.headers.y_size as usize];
-            file.read_exact(&mut packed)?;
+        let height = self.get_height() as usize * self.get_face_count();
+        let height = u16::try_from(height).map_err(|_| {
+            Error::InvalidData(format!(
+                "{} faces of {}x{} are too tall for one PNG",
+                self.get_face_count(),
+                self.get_width(),
+                self.get_height()
+            ))
+        })?;
 
-            let mut bytes = Vec::new();
-            for idx in 0..self.headers.y_size {
-                let start = idx * 3;
-                let end = start + 3;
+        encode_png(self.get_width(), height, &pixels)
+    }
+
+    pub fn save(&self, tpc_filename: &str) -> Result<()> {
+        let mut file = File::create(tpc_filename)?;
+
+        self.write_to(&mut file)
+    }
 
-                for j in start..end {
-                    bytes.push(packed[j as usize]);
+    /// Writes the texture, encoding every mip level of every face followed by the TXI text.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_bytes()?)?;
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buffer = Cursor::new(Vec::new());
+        self.headers.write(&mut buffer)?;
+
+        let mut buffer = buffer.into_inner();
+        let compressed = self.is_compressed();
+        for mipmap in self.faces.iter().flatten() {
+            buffer.extend(Self::encode(self.headers.encoding, compressed, mipmap));
+        }
+        if let Some(txi) = self.txi.as_ref().filter(|txi| !txi.is_empty()) {
+            buffer.extend(txi.to_text().into_bytes());
+        }
+
+        Ok(buffer)
+    }
+
+    /// The width of each face.
+    pub fn get_width(&self) -> u16 {
+        self.headers.x_size
+    }
+
+    /// The height of each face, which for cube maps is the same as the width.
+    pub fn get_height(&self) -> u16 {
+        self.faces[0][0].height
+    }
+
+    /// The encoding the texture was stored with, one of the `ENCODING_*` constants.
+    pub fn get_encoding(&self) -> u8 {
+        self.headers.encoding
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.headers.data_size != 0
+    }
+
+    pub fn get_alpha_test(&self) -> f32 {
+        self.headers.alpha_test
+    }
+
+    /// Whether the texture is a cube map, such as an environment map.
+    pub fn is_cube_map(&self) -> bool {
+        self.faces.len() == CUBE_MAP_FACES
+    }
+
+    pub fn get_face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    /// The mip levels of a face, largest first.
+    pub fn get_mipmaps(&self, face: usize) -> &[TpcMipmap] {
+        self.faces.get(face).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The decoded RGBA8 pixels of the full size image, top row first.
+    ///
+    /// For cube maps this is the first face.
+    pub fn get_pixels(&self) -> &[u8] {
+        &self.faces[0][0].pixels
+    }
+
+    pub fn get_txi(&self) -> Option<&Txi> {
+        self.txi.as_ref()
+    }
+
+    pub fn set_txi(&mut self, txi: Option<Txi>) {
+        self.txi = txi;
+    }
+
+    pub fn set_alpha_test(&mut self, alpha_test: f32) {
+        self.headers.alpha_test = alpha_test;
+    }
+
+    fn get_data_size(encoding: u8, compressed: bool, width: usize, height: usize) -> usize {
+        match (encoding, compressed) {
+            (ENCODING_RGB, true) => Self::get_dxt_size(width, height, 8),
+            (ENCODING_RGBA, true) => Self::get_dxt_size(width, height, 16),
+            (ENCODING_GREYSCALE, _) => width * height,
+            (ENCODING_RGB, _) => width * height * 3,
+            _ => width * height * 4,
+        }
+    }
+
+    fn decode(encoding: u8, compressed: bool, data: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let mut pixels = match (encoding, compressed) {
+            (ENCODING_GREYSCALE, _) => Self::decode_greyscale(data),
+            (ENCODING_RGB, false) => Self::decode_rgb(data),
+            (ENCODING_RGB, true) => Self::decode_dxt1(data, width, height),
+            (_, false) => data.to_vec(),
+            (_, true) => Self::decode_dxt5(data, width, height),
+        };
+
+        // Textures are stored bottom row first.
+        Self::flip_vertically(&mut pixels, width, height);
+
+        pixels
+    }
+
+    fn encode(encoding: u8, compressed: bool, mipmap: &TpcMipmap) -> Vec<u8> {
+        let width = mipmap.width as usize;
+        let height = mipmap.height as usize;
+        let mut pixels = mipmap.pixels.clone();
+        Self::flip_vertically(&mut pixels, width, height);
+
+        match (encoding, compressed) {
+            (ENCODING_GREYSCALE, _) => pixels
+                .chunks_exact(4)
+                .map(|pixel| {
+                    ((pixel[0] as u32 * 77 + pixel[1] as u32 * 150 + pixel[2] as u32 * 29) >> 8)
+                        as u8
+                })
+                .collect(),
+            (ENCODING_RGB, false) => pixels
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect(),
+            (ENCODING_RGB, true) => Self::encode_dxt1(&pixels, width, height),
+            (_, false) => pixels,
+            (_, true) => Self::encode_dxt5(&pixels, width, height),
+        }
+    }
+
+    /// Compresses RGBA8 pixels to DXT1, keeping their row order.
+    pub(crate) fn encode_dxt1(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+        Self::encode_blocks(pixels, width, height, |block| {
+            Self::encode_colour_block(block, true).to_vec()
+        })
+    }
+
+    /// Compresses RGBA8 pixels to DXT5, keeping their row order.
+    pub(crate) fn encode_dxt5(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+        Self::encode_blocks(pixels, width, height, |block| {
+            let mut data = Self::encode_alpha_block(block).to_vec();
+            data.extend(Self::encode_colour_block(block, false));
+            data
+        })
+    }
+
+    /// Halves an image with a 2x2 box filter.
+    pub(crate) fn downsample(mipmap: &TpcMipmap) -> TpcMipmap {
+        let width = mipmap.width as usize;
+        let height = mipmap.height as usize;
+        let next_width = (width / 2).max(1);
+        let next_height = (height / 2).max(1);
+
+        let mut pixels = Vec::with_capacity(next_width * next_height * 4);
+        for y in 0..next_height {
+            for x in 0..next_width {
+                let samples = [
+                    (x * 2, y * 2),
+                    ((x * 2 + 1).min(width - 1), y * 2),
+                    (x * 2, (y * 2 + 1).min(height - 1)),
+                    ((x * 2 + 1).min(width - 1), (y * 2 + 1).min(height - 1)),
+                ];
+                for channel in 0..4 {
+                    let total: u32 = samples
+                        .iter()
+                        .map(|&(x, y)| mipmap.pixels[(y * width + x) * 4 + channel] as u32)
+                        .sum();
+                    pixels.push(((total + 2) / 4) as u8);
                 }
             }
+        }
 
-            let mut bytes2: Vec<u8> = Vec::new();
-            let mut outer_idx: u8 = 0;
+        TpcMipmap {
+            width: next_width as u16,
+            height: next_height as u16,
+            pixels,
+        }
+    }
 
-            for idx in 0..bytes.len() {
-                if idx % 3 == 0 && idx > 0 {
-                    bytes2.push(0);
-                    outer_idx += 1;
+    /// The size of a DXT image, which is stored in blocks of 4x4 pixels.
+    pub(crate) fn get_dxt_size(width: usize, height: usize, block_size: usize) -> usize {
+        width.div_ceil(4).max(1) * height.div_ceil(4).max(1) * block_size
+    }
+
+    fn flip_vertically(pixels: &mut [u8], width: usize, height: usize) {
+        let stride = width * 4;
+        for y in 0..height / 2 {
+            let (top, bottom) = pixels.split_at_mut((height - 1 - y) * stride);
+            top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+    }
+
+    fn decode_greyscale(data: &[u8]) -> Vec<u8> {
+        data.iter()
+            .flat_map(|&value| [value, value, value, 0xFF])
+            .collect()
+    }
+
+    fn decode_rgb(data: &[u8]) -> Vec<u8> {
+        data.chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+            .collect()
+    }
+
+    /// Expands an RGB565 colour to RGB888.
+    fn rgb565_to_rgb888(colour: u16) -> [u8; 3] {
+        let r = ((colour >> 11) & 0x1F) as u32;
+        let g = ((colour >> 5) & 0x3F) as u32;
+        let b = (colour & 0x1F) as u32;
+
+        [
+            ((r * 255 + 15) / 31) as u8,
+            ((g * 255 + 31) / 63) as u8,
+            ((b * 255 + 15) / 31) as u8,
+        ]
+    }
+
+    /// The four colours a DXT colour block picks from.
+    ///
+    /// DXT1 blocks whose first colour is not greater than the second use the
+    /// fourth palette entry for transparent black.
+    fn get_colour_palette(colour0: u16, colour1: u16, allow_transparency: bool) -> [[u8; 4]; 4] {
+        let c0 = Self::rgb565_to_rgb888(colour0);
+        let c1 = Self::rgb565_to_rgb888(colour1);
+
+        let mix =
+            |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+        let mut palette = [[0u8; 4]; 4];
+        palette[0] = [c0[0], c0[1], c0[2], 0xFF];
+        palette[1] = [c1[0], c1[1], c1[2], 0xFF];
+        if colour0 > colour1 || !allow_transparency {
+            for channel in 0..3 {
+                palette[2][channel] = mix(c0[channel], c1[channel], 2, 1);
+                palette[3][channel] = mix(c0[channel], c1[channel], 1, 2);
+            }
+            palette[2][3] = 0xFF;
+            palette[3][3] = 0xFF;
+        } else {
+            for channel in 0..3 {
+                palette[2][channel] = mix(c0[channel], c1[channel], 1, 1);
+            }
+            palette[2][3] = 0xFF;
+        }
+
+        palette
+    }
+
+    /// Decodes the colour half of a DXT block into 16 RGBA pixels.
+    fn decode_colour_block(block: &[u8], allow_transparency: bool) -> [[u8; 4]; 16] {
+        let colour0 = u16::from_le_bytes([block[0], block[1]]);
+        let colour1 = u16::from_le_bytes([block[2], block[3]]);
+        let palette = Self::get_colour_palette(colour0, colour1, allow_transparency);
+
+        let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+        let mut pixels = [[0u8; 4]; 16];
+        for (idx, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = palette[((indices >> (idx * 2)) & 0b11) as usize];
+        }
+
+        pixels
+    }
+
+    /// The eight alphas a DXT5 alpha block picks from.
+    fn get_alpha_palette(alpha0: u8, alpha1: u8) -> [u8; 8] {
+        let (alpha0, alpha1) = (alpha0 as u32, alpha1 as u32);
+
+        let mut palette = [0u8; 8];
+        palette[0] = alpha0 as u8;
+        palette[1] = alpha1 as u8;
+        if alpha0 > alpha1 {
+            for idx in 1..7 {
+                palette[idx + 1] = ((alpha0 * (7 - idx as u32) + alpha1 * idx as u32) / 7) as u8;
+            }
+        } else {
+            for idx in 1..5 {
+                palette[idx + 1] = ((alpha0 * (5 - idx as u32) + alpha1 * idx as u32) / 5) as u8;
+            }
+            palette[6] = 0;
+            palette[7] = 0xFF;
+        }
+
+        palette
+    }
+
+    /// Decodes the interpolated alpha half of a DXT5 block into 16 values.
+    fn decode_alpha_block(block: &[u8]) -> [u8; 16] {
+        let palette = Self::get_alpha_palette(block[0], block[1]);
+
+        let mut bits = [0u8; 8];
+        bits[..6].copy_from_slice(&block[2..8]);
+        let indices = u64::from_le_bytes(bits);
+
+        let mut alphas = [0u8; 16];
+        for (idx, alpha) in alphas.iter_mut().enumerate() {
+            *alpha = palette[((indices >> (idx * 3)) & 0b111) as usize];
+        }
+
+        alphas
+    }
+
+    /// Writes decoded 4x4 blocks into an RGBA image, clipping blocks at the edges.
+    fn decode_blocks(
+        data: &[u8],
+        width: usize,
+        height: usize,
+        block_size: usize,
+        decode_block: impl Fn(&[u8]) -> [[u8; 4]; 16],
+    ) -> Vec<u8> {
+        let mut pixels = vec![0u8; width * height * 4];
+        let blocks_wide = width.div_ceil(4).max(1);
+
+        for (idx, block) in data.chunks_exact(block_size).enumerate() {
+            let block_x = (idx % blocks_wide) * 4;
+            let block_y = (idx / blocks_wide) * 4;
+
+            for (pixel_idx, pixel) in decode_block(block).iter().enumerate() {
+                let x = block_x + pixel_idx % 4;
+                let y = block_y + pixel_idx / 4;
+                if x < width && y < height {
+                    let offset = (y * width + x) * 4;
+                    pixels[offset..offset + 4].copy_from_slice(pixel);
                 }
-                bytes2.push(bytes[idx]);
-                outer_idx += 1;
             }
-            bytes.insert(outer_idx as usize, 0);
-            let mut pixels: Vec<u32> = packed
-                .chunks_exact(4)
-                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
-                .collect();
-            pixels.resize((self.headers.x_size * self.headers.y_size).into(), 0);
+        }
+
+        pixels
+    }
+
+    pub(crate) fn decode_dxt1(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+        Self::decode_blocks(data, width, height, 8, |block| {
+            Self::decode_colour_block(block, true)
+        })
+    }
+
+    pub(crate) fn decode_dxt5(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+        Self::decode_blocks(data, width, height, 16, |block| {
+            let alphas = Self::decode_alpha_block(&block[..8]);
+            let mut pixels = Self::decode_colour_block(&block[8..], false);
+            for (pixel, alpha) in pixels.iter_mut().zip(alphas) {
+                pixel[3] = alpha;
+            }
+
+            pixels
+        })
+    }
+
+    /// Splits an RGBA image into 4x4 blocks, repeating edge pixels to fill partial blocks.
+    fn encode_blocks(
+        pixels: &[u8],
+        width: usize,
+        height: usize,
+        encode_block: impl Fn(&[[u8; 4]; 16]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        let blocks_wide = width.div_ceil(4).max(1);
+        let blocks_high = height.div_ceil(4).max(1);
+
+        let mut data = Vec::new();
+        for block_y in (0..blocks_high).map(|y| y * 4) {
+            for block_x in (0..blocks_wide).map(|x| x * 4) {
+                let mut block = [[0u8; 4]; 16];
+                for (pixel_idx, pixel) in block.iter_mut().enumerate() {
+                    let x = (block_x + pixel_idx % 4).min(width - 1);
+                    let y = (block_y + pixel_idx / 4).min(height - 1);
+                    let offset = (y * width + x) * 4;
+                    pixel.copy_from_slice(&pixels[offset..offset + 4]);
+                }
+                data.extend(encode_block(&block));
+            }
+        }
+
+        data
+    }
+
+    fn rgb888_to_rgb565(pixel: &[u8; 4]) -> u16 {
+        ((pixel[0] as u16 >> 3) << 11) | ((pixel[1] as u16 >> 2) << 5) | (pixel[2] as u16 >> 3)
+    }
+
+    /// Encodes the colour half of a DXT block from the bounding box of its colours.
+    ///
+    /// With `allow_transparency`, pixels below half alpha use the transparent palette entry.
+    fn encode_colour_block(block: &[[u8; 4]; 16], allow_transparency: bool) -> [u8; 8] {
+        let transparent = |pixel: &[u8; 4]| allow_transparency && pixel[3] < 0x80;
+        let has_transparency = block.iter().any(transparent);
+
+        let mut low = [0xFFu8; 4];
+        let mut high = [0u8; 4];
+        for pixel in block.iter().filter(|pixel| !transparent(pixel)) {
+            for channel in 0..3 {
+                low[channel] = low[channel].min(pixel[channel]);
+                high[channel] = high[channel].max(pixel[channel]);
+            }
+        }
+
+        // The bounding box has four diagonals. Follow the one that matches how the
+        // other channels vary against the widest one.
+        let widest = (0..3)
+            .max_by_key(|&channel| high[channel].saturating_sub(low[channel]))
+            .unwrap();
+        for channel in (0..3).filter(|&channel| channel != widest) {
+            let centre = |channel: usize| (low[channel] as i32 + high[channel] as i32) / 2;
+            let covariance: i32 = block
+                .iter()
+                .filter(|pixel| !transparent(pixel))
+                .map(|pixel| {
+                    (pixel[widest] as i32 - centre(widest))
+                        * (pixel[channel] as i32 - centre(channel))
+                })
+                .sum();
+            if covariance < 0 {
+                std::mem::swap(&mut low[channel], &mut high[channel]);
+            }
+        }
+
+        let mut colour0 = Self::rgb888_to_rgb565(&high);
+        let mut colour1 = Self::rgb888_to_rgb565(&low);
+        if has_transparency {
+            // The transparent palette is only used when the first colour is not greater.
+            if colour0 > colour1 {
+                std::mem::swap(&mut colour0, &mut colour1);
+            }
+        } else if colour0 < colour1 {
+            std::mem::swap(&mut colour0, &mut colour1);
+        }
+
+        let palette = Self::get_colour_palette(colour0, colour1, allow_transparency);
+        let usable = if allow_transparency && colour0 <= colour1 {
+            3
+        } else {
+            4
+        };
+
+        let mut indices = 0u32;
+        for (idx, pixel) in block.iter().enumerate() {
+            let index = if transparent(pixel) {
+                3
+            } else {
+                (0..usable)
+                    .min_by_key(|&entry| {
+                        (0..3)
+                            .map(|channel| {
+                                let diff = pixel[channel] as i32 - palette[entry][channel] as i32;
+                                diff * diff
+                            })
+                            .sum::<i32>()
+                    })
+                    .unwrap()
+            };
+            indices |= (index as u32) << (idx * 2);
+        }
+
+        let mut data = [0u8; 8];
+        data[..2].copy_from_slice(&colour0.to_le_bytes());
+        data[2..4].copy_from_slice(&colour1.to_le_bytes());
+        data[4..].copy_from_slice(&indices.to_le_bytes());
+
+        data
+    }
+
+    /// Encodes the alpha half of a DXT5 block, interpolating between its extremes.
+    fn encode_alpha_block(block: &[[u8; 4]; 16]) -> [u8; 8] {
+        let alpha0 = block.iter().map(|pixel| pixel[3]).max().unwrap();
+        let alpha1 = block.iter().map(|pixel| pixel[3]).min().unwrap();
+        let palette = Self::get_alpha_palette(alpha0, alpha1);
+
+        let mut indices = 0u64;
+        for (idx, pixel) in block.iter().enumerate() {
+            let index = (0..palette.len())
+                .min_by_key(|&entry| (pixel[3] as i32 - palette[entry] as i32).abs())
+                .unwrap();
+            indices |= (index as u64) << (idx * 3);
+        }
+
+        let mut data = [0u8; 8];
+        data[0] = alpha0;
+        data[1] = alpha1;
+        data[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Indices picking palette entries 0, 1, 2 and 3 across every row of a block.
+    const COLOUR_INDICES: [u8; 4] = [0xE4; 4];
+
+    /// Indices picking alpha palette entries 0 to 7 across each pair of rows.
+    const ALPHA_INDICES: [u8; 6] = [0x88, 0xC6, 0xFA, 0x88, 0xC6, 0xFA];
+
+    fn colour_block(colour0: u16, colour1: u16) -> Vec<u8> {
+        let mut block = colour0.to_le_bytes().to_vec();
+        block.extend(colour1.to_le_bytes());
+        block.extend(COLOUR_INDICES);
+
+        block
+    }
+
+    fn alpha_block(alpha0: u8, alpha1: u8) -> Vec<u8> {
+        let mut block = vec![alpha0, alpha1];
+        block.extend(ALPHA_INDICES);
+        block.extend(colour_block(0xFFFF, 0xFFFF));
+
+        block
+    }
+
+    fn first_row(pixels: &[u8]) -> Vec<[u8; 4]> {
+        pixels[..16]
+            .chunks_exact(4)
+            .map(|pixel| pixel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_dxt1_four_colour_blocks() {
+        let pixels = Tpc::decode_dxt1(&colour_block(0xF800, 0x001F), 4, 4);
+
+        let row = [
+            [255, 0, 0, 255],
+            [0, 0, 255, 255],
+            [170, 0, 85, 255],
+            [85, 0, 170, 255],
+        ];
+        assert_eq!(first_row(&pixels), row);
+        assert!(pixels
+            .chunks_exact(16)
+            .all(|line| line == pixels[..16].as_ref()));
+    }
+
+    #[test]
+    fn decodes_dxt1_transparent_blocks() {
+        let pixels = Tpc::decode_dxt1(&colour_block(0x001F, 0xF800), 4, 4);
+
+        assert_eq!(
+            first_row(&pixels),
+            [
+                [0, 0, 255, 255],
+                [255, 0, 0, 255],
+                [127, 0, 127, 255],
+                [0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_dxt5_eight_alpha_blocks() {
+        let pixels = Tpc::decode_dxt5(&alpha_block(255, 0), 4, 4);
+        let alphas: Vec<u8> = pixels.chunks_exact(4).map(|pixel| pixel[3]).collect();
+
+        assert_eq!(alphas[..8], [255, 0, 218, 182, 145, 109, 72, 36]);
+        assert_eq!(alphas[8..], alphas[..8]);
+        assert!(pixels
+            .chunks_exact(4)
+            .all(|pixel| pixel[..3] == [255, 255, 255]));
+    }
+
+    #[test]
+    fn decodes_dxt5_six_alpha_blocks() {
+        let pixels = Tpc::decode_dxt5(&alpha_block(0, 255), 4, 4);
+        let alphas: Vec<u8> = pixels.chunks_exact(4).map(|pixel| pixel[3]).collect();
+
+        assert_eq!(alphas[..8], [0, 255, 51, 102, 153, 204, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_dxt5_colours_without_transparency() {
+        let mut block = vec![255, 255, 0, 0, 0, 0, 0, 0];
+        block.extend(colour_block(0x001F, 0xF800));
+        let pixels = Tpc::decode_dxt5(&block, 4, 4);
 
-            let pixels_offset = offset..offset + self.headers.y_size;
+        assert_eq!(
+            first_row(&pixels),
+            [
+                [0, 0, 255, 255],
+                [255, 0, 0, 255],
+                [85, 0, 170, 255],
+                [170, 0, 85, 255],
+            ]
+        );
+    }
+
+    #[test]
+    fn clips_blocks_at_the_edges() {
+        let mut data = colour_block(0xF800, 0xF800);
+        data.extend(colour_block(0x001F, 0x001F));
+        let pixels = Tpc::decode_dxt1(&data, 6, 2);
+
+        assert_eq!(pixels.len(), 6 * 2 * 4);
+        assert_eq!(pixels[..4], [255, 0, 0, 255]);
+        assert_eq!(pixels[16..20], [0, 0, 255, 255]);
+        assert_eq!(pixels[24..28], [255, 0, 0, 255]);
+        assert_eq!(Tpc::get_dxt_size(6, 2, 8), 16);
+        assert_eq!(Tpc::get_dxt_size(1, 1, 16), 16);
+    }
+
+    fn tpc_bytes(data_size: u32, width: u16, height: u16, encoding: u8, mip_count: u8) -> Vec<u8> {
+        let mut bytes = data_size.to_le_bytes().to_vec();
+        bytes.extend(0.5f32.to_le_bytes());
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.push(encoding);
+        bytes.push(mip_count);
+        bytes.resize(HEADER_SIZE, 0);
 
-            pixels_offset
-                .zip(pixels.clone().into_iter())
-                .for_each(|(idx, pixel)| pixels[idx as usize] = pixel);
+        bytes
+    }
+
+    #[test]
+    fn reads_uncompressed_mipmaps() {
+        let mut bytes = tpc_bytes(0, 2, 2, ENCODING_RGB, 2);
+        bytes.extend([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        bytes.extend([13, 14, 15]);
+
+        let tpc = Tpc::from_bytes(&bytes).unwrap();
+        assert_eq!((tpc.get_width(), tpc.get_height()), (2, 2));
+        assert_eq!(tpc.get_alpha_test(), 0.5);
+        assert!(!tpc.is_compressed());
+        assert!(!tpc.is_cube_map());
+        assert_eq!(
+            tpc.get_pixels(),
+            [7, 8, 9, 255, 10, 11, 12, 255, 1, 2, 3, 255, 4, 5, 6, 255]
+        );
+
+        let mipmaps = tpc.get_mipmaps(0);
+        assert_eq!(mipmaps.len(), 2);
+        assert_eq!((mipmaps[1].width, mipmaps[1].height), (1, 1));
+        assert_eq!(mipmaps[1].pixels, [13, 14, 15, 255]);
+        assert!(tpc.get_txi().is_none());
+    }
+
+    #[test]
+    fn reads_greyscale_without_mip_count() {
+        let mut bytes = tpc_bytes(0, 2, 1, ENCODING_GREYSCALE, 0);
+        bytes.extend([0x10, 0x20]);
+
+        let tpc = Tpc::from_bytes(&bytes).unwrap();
+        assert_eq!(tpc.get_mipmaps(0).len(), 1);
+        assert_eq!(
+            tpc.get_pixels(),
+            [0x10, 0x10, 0x10, 255, 0x20, 0x20, 0x20, 255]
+        );
+    }
+
+    #[test]
+    fn reads_cube_map_faces() {
+        let mut bytes = tpc_bytes(0, 1, 6, ENCODING_RGBA, 1);
+        for face in 0..6u8 {
+            bytes.extend([face, face, face, 255]);
         }
 
-        Ok(Vec::new()) // todo!
+        let tpc = Tpc::from_bytes(&bytes).unwrap();
+        assert!(tpc.is_cube_map());
+        assert_eq!((tpc.get_width(), tpc.get_height()), (1, 1));
+        assert_eq!(tpc.get_mipmaps(5)[0].pixels, [5, 5, 5, 255]);
+        assert!(tpc.get_mipmaps(6).is_empty());
     }
-    fn calculate_fourth_encoding_pixels(&self, file: &mut File) -> Result<Vec<u8>> {
-        todo!()
+
+    #[test]
+    fn reads_compressed_data_and_txi() {
+        let mut bytes = tpc_bytes(8, 4, 4, ENCODING_RGB, 1);
+        bytes.extend(colour_block(0xF800, 0x001F));
+        bytes.extend(b"envmaptexture CM_Baremetal\r\nblending additive\r\n\0");
+
+        let tpc = Tpc::from_bytes(&bytes).unwrap();
+        assert!(tpc.is_compressed());
+        assert_eq!(tpc.get_encoding(), ENCODING_RGB);
+        assert_eq!(first_row(tpc.get_pixels())[1], [0, 0, 255, 255]);
+
+        let txi = tpc.get_txi().unwrap();
+        assert_eq!(txi.get("EnvMapTexture"), Some("CM_Baremetal"));
+        assert_eq!(txi.get("blending"), Some("additive"));
     }
 
-    fn decode_dxt1(&self, file: &mut File) -> Result<Vec<u8>> {
-        todo!()
+    #[test]
+    fn ignores_padding_after_pixels() {
+        let mut bytes = tpc_bytes(0, 1, 1, ENCODING_RGBA, 1);
+        bytes.extend([1, 2, 3, 4, 0, 0, 0, 0]);
+
+        assert!(Tpc::from_bytes(&bytes).unwrap().get_txi().is_none());
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert!(matches!(
+            Tpc::from_bytes(&[0; 10]),
+            Err(Error::MissingHeader)
+        ));
+
+        for (data_size, width, height, encoding) in [
+            (0, 0, 4, ENCODING_RGBA),
+            (0, 4, 0, ENCODING_RGBA),
+            (8, 0, 0, ENCODING_RGB),
+            (0, 4, 4, 3),
+            (8, 4, 4, ENCODING_GREYSCALE),
+        ] {
+            let mut bytes = tpc_bytes(data_size, width, height, encoding, 1);
+            bytes.resize(HEADER_SIZE + 64, 0);
+
+            assert!(matches!(
+                Tpc::from_bytes(&bytes),
+                Err(Error::InvalidData(_))
+            ));
+        }
+
+        let mut truncated = tpc_bytes(0, 4, 4, ENCODING_RGBA, 3);
+        truncated.resize(HEADER_SIZE + 64 + 16, 0);
+        assert!(matches!(
+            Tpc::from_bytes(&truncated),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    /// A smooth gradient with varying alpha, which DXT compresses well.
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|idx| {
+                let (x, y) = (idx % width, idx / width);
+                let value = (x * 255 / (width - 1)) as u8;
+                [value, value / 2, 255 - value, (y * 255 / height) as u8]
+            })
+            .collect()
+    }
+
+    fn encode_round_trip(format: TpcFormat, pixels: &[u8], width: u16, height: u16) -> Tpc {
+        let tpc = Tpc::from_rgba(width, height, pixels.to_vec(), format, false, None).unwrap();
+
+        Tpc::from_bytes(&tpc.to_bytes().unwrap()).unwrap()
+    }
+
+    fn max_difference(a: &[u8], b: &[u8], channels: usize) -> u8 {
+        a.chunks_exact(4)
+            .zip(b.chunks_exact(4))
+            .flat_map(|(a, b)| (0..channels).map(move |channel| a[channel].abs_diff(b[channel])))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn uncompressed_round_trip() {
+        let pixels = gradient(8, 4);
+
+        let rgba = encode_round_trip(TpcFormat::Rgba, &pixels, 8, 4);
+        assert_eq!(rgba.get_encoding(), ENCODING_RGBA);
+        assert_eq!(rgba.get_pixels(), pixels);
+
+        let rgb = encode_round_trip(TpcFormat::Rgb, &pixels, 8, 4);
+        assert_eq!(rgb.get_encoding(), ENCODING_RGB);
+        assert!(!rgb.is_compressed());
+        assert_eq!(max_difference(rgb.get_pixels(), &pixels, 3), 0);
+        assert!(rgb
+            .get_pixels()
+            .chunks_exact(4)
+            .all(|pixel| pixel[3] == 255));
+
+        let grey: Vec<u8> = (0..32u8)
+            .flat_map(|value| [value * 8; 3].into_iter().chain([255]))
+            .collect();
+        let greyscale = encode_round_trip(TpcFormat::Greyscale, &grey, 8, 4);
+        assert_eq!(greyscale.get_encoding(), ENCODING_GREYSCALE);
+        assert!(max_difference(greyscale.get_pixels(), &grey, 4) <= 1);
     }
 
-    fn decode_dxt5(&self, file: &mut File) -> Result<Vec<u8>> {
-        todo!()
+    #[test]
+    fn compressed_round_trip() {
+        let opaque: Vec<u8> = gradient(16, 8)
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect();
+        let dxt1 = encode_round_trip(TpcFormat::Dxt1, &opaque, 16, 8);
+        assert!(dxt1.is_compressed());
+        assert_eq!(dxt1.get_encoding(), ENCODING_RGB);
+        assert!(max_difference(dxt1.get_pixels(), &opaque, 4) <= 24);
+
+        let pixels = gradient(16, 8);
+        let dxt5 = encode_round_trip(TpcFormat::Dxt5, &pixels, 16, 8);
+        assert!(dxt5.is_compressed());
+        assert_eq!(dxt5.get_encoding(), ENCODING_RGBA);
+        assert!(max_difference(dxt5.get_pixels(), &pixels, 3) <= 24);
+        assert!(max_difference(dxt5.get_pixels(), &pixels, 4) <= 24);
+    }
+
+    #[test]
+    fn dxt1_keeps_punch_through_alpha() {
+        let mut pixels = vec![200u8; 4 * 4 * 4];
+        for pixel in pixels.chunks_exact_mut(4).step_by(3) {
+            pixel[3] = 0;
+        }
+        for pixel in pixels.chunks_exact_mut(4).filter(|pixel| pixel[3] != 0) {
+            pixel[3] = 255;
+        }
+
+        let dxt1 = encode_round_trip(TpcFormat::Dxt1, &pixels, 4, 4);
+        for (decoded, original) in dxt1
+            .get_pixels()
+            .chunks_exact(4)
+            .zip(pixels.chunks_exact(4))
+        {
+            assert_eq!(decoded[3], original[3]);
+        }
+    }
+
+    #[test]
+    fn generates_mipmaps() {
+        let pixels: Vec<u8> = (0..4 * 2).flat_map(|idx| [idx * 10, 0, 0, 255]).collect();
+        let tpc = Tpc::from_rgba(4, 2, pixels, TpcFormat::Rgba, false, None).unwrap();
+
+        let sizes: Vec<(u16, u16)> = tpc
+            .get_mipmaps(0)
+            .iter()
+            .map(|mipmap| (mipmap.width, mipmap.height))
+            .collect();
+        assert_eq!(sizes, [(4, 2), (2, 1), (1, 1)]);
+        assert_eq!(tpc.get_mipmaps(0)[1].pixels, [25, 0, 0, 255, 45, 0, 0, 255]);
+        assert_eq!(tpc.get_mipmaps(0)[2].pixels, [35, 0, 0, 255]);
+
+        let parsed = Tpc::from_bytes(&tpc.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.get_mipmaps(0), tpc.get_mipmaps(0));
+    }
+
+    #[test]
+    fn cube_maps_are_explicit() {
+        let pixels: Vec<u8> = (0..6u8).flat_map(|face| [face * 40; 4 * 4 * 4]).collect();
+
+        assert!(matches!(
+            Tpc::from_rgba(4, 24, pixels.clone(), TpcFormat::Rgba, false, None),
+            Err(Error::InvalidData(_))
+        ));
+
+        let tall = Tpc::from_rgba(
+            4,
+            20,
+            pixels[..4 * 20 * 4].to_vec(),
+            TpcFormat::Dxt5,
+            false,
+            None,
+        )
+        .unwrap();
+        assert!(!tall.is_cube_map());
+        assert_eq!(tall.get_height(), 20);
+        let parsed = Tpc::from_bytes(&tall.to_bytes().unwrap()).unwrap();
+        assert!(!parsed.is_cube_map());
+        assert_eq!(parsed.get_height(), 20);
+        assert_eq!(parsed.get_mipmaps(0).len(), tall.get_mipmaps(0).len());
+
+        let cube = Tpc::from_rgba(4, 24, pixels.clone(), TpcFormat::Dxt5, true, None).unwrap();
+        assert!(cube.is_cube_map());
+        assert_eq!(cube.get_height(), 4);
+        assert_eq!(cube.get_mipmaps(0).len(), 3);
+
+        let parsed = Tpc::from_bytes(&cube.to_bytes().unwrap()).unwrap();
+        assert!(parsed.is_cube_map());
+        assert!(max_difference(&parsed.get_mipmaps(5)[0].pixels, &pixels[5 * 64..], 4) <= 8);
+
+        assert!(matches!(
+            Tpc::from_rgba(4, 20, vec![0; 4 * 20 * 4], TpcFormat::Rgba, true, None),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn cube_map_png_round_trip() {
+        let pixels: Vec<u8> = (0..6u8).flat_map(|face| [face * 40; 4 * 4 * 4]).collect();
+        let cube = Tpc::from_rgba(4, 24, pixels.clone(), TpcFormat::Rgba, true, None).unwrap();
+        let png = cube.to_png().unwrap();
+
+        assert!(matches!(
+            Tpc::from_png(&png, Some(TpcFormat::Rgba), false, None),
+            Err(Error::InvalidData(_))
+        ));
+
+        let parsed = Tpc::from_png(&png, Some(TpcFormat::Rgba), true, None).unwrap();
+        assert!(parsed.is_cube_map());
+        for face in 0..6 {
+            assert_eq!(parsed.get_mipmaps(face), cube.get_mipmaps(face));
+        }
+    }
+
+    #[test]
+    fn picks_png_formats() {
+        let png = |pixels: &[u8]| encode_png(2, 1, pixels).unwrap();
+        let format = |png: &[u8]| {
+            let tpc = Tpc::from_png(png, None, false, None).unwrap();
+            (tpc.get_encoding(), tpc.is_compressed())
+        };
+
+        let grey = png(&[9, 9, 9, 255, 80, 80, 80, 255]);
+        assert_eq!(format(&grey), (ENCODING_GREYSCALE, false));
+        let opaque = png(&[255, 0, 0, 255, 0, 0, 255, 255]);
+        assert_eq!(format(&opaque), (ENCODING_RGB, true));
+        let transparent = png(&[9, 9, 9, 0, 80, 80, 80, 255]);
+        assert_eq!(format(&transparent), (ENCODING_RGBA, true));
+    }
+
+    #[test]
+    fn rejects_stacked_faces_too_tall_for_png() {
+        let face = vec![TpcMipmap {
+            width: 1,
+            height: 12_000,
+            pixels: Vec::new(),
+        }];
+        let tpc = Tpc {
+            headers: TpcHeaders {
+                data_size: 0,
+                alpha_test: 1.0,
+                x_size: 1,
+                y_size: 0,
+                encoding: ENCODING_RGBA,
+                mip_count: 1,
+            },
+            faces: vec![face; CUBE_MAP_FACES],
+            txi: None,
+        };
+
+        assert!(matches!(tpc.to_png(), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn rejects_mismatched_pixels() {
+        assert!(matches!(
+            Tpc::from_rgba(0, 4, Vec::new(), TpcFormat::Rgba, false, None),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(
+            Tpc::from_rgba(2, 2, vec![0; 15], TpcFormat::Rgba, false, None),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn flips_rows() {
+        let mut pixels: Vec<u8> = (0..2 * 3 * 4).collect();
+        Tpc::flip_vertically(&mut pixels, 2, 3);
+
+        assert_eq!(pixels[..8], [16, 17, 18, 19, 20, 21, 22, 23]);
+        assert_eq!(pixels[8..16], [8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(pixels[16..], [0, 1, 2, 3, 4, 5, 6, 7]);
     }
 }
@@ -1,128 +1,914 @@
-use std::{
-    fs::File,
-    io::{Read, Result},
-};
-
+use crate::shared::{decode_string, encode_string};
+use crate::{Error, Result};
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Seek, SeekFrom};
-use std::path::Path;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+
+const BINARY_HEADER: &[u8; 9] = b"2DA V2.b\n";
+const TEXT_HEADER: &str = "2DA V2.0";
+
+/// How blank cells are written in text 2DAs. Binary 2DAs store them as empty strings.
+pub const BLANK: &str = "****";
+
+/// The header of the row label column in CSV and the key of the row label in JSON.
+///
+/// Tables with a column of this name cannot be written as JSON.
+pub const ROW_LABEL: &str = "(row label)";
+
+/// How blank cells are written when converting to CSV or JSON.
+///
+/// Reading accepts all of them, whichever was used to write the file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Blank {
+    /// An empty string.
+    #[default]
+    Empty,
+    /// `****`, as in text 2DAs.
+    Stars,
+    /// `null` in JSON. CSV has no null, so an empty field is written instead.
+    Null,
+}
+
+/// A single row of a 2DA, e.g. one appearance in `appearance.2da`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TwoDARow {
+    /// The row label, which is nearly always the row number.
+    pub label: String,
+    /// One value per column, empty for blank cells.
+    pub cells: Vec<String>,
+}
+
+/// Something a 2DA column can be looked up by: its index or its name.
+pub trait TwoDAColumn {
+    fn column_index(&self, twoda: &TwoDA) -> Option<usize>;
+}
 
+impl TwoDAColumn for usize {
+    fn column_index(&self, twoda: &TwoDA) -> Option<usize> {
+        (*self < twoda.columns.len()).then_some(*self)
+    }
+}
+
+impl TwoDAColumn for &str {
+    fn column_index(&self, twoda: &TwoDA) -> Option<usize> {
+        twoda
+            .columns
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(self))
+    }
+}
+
+/// A struct representing a parsed 2DA table, e.g. `appearance.2da`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct TwoDA {
-    read_data: Vec<Vec<u8>>,
+    pub columns: Vec<String>,
+    pub rows: Vec<TwoDARow>,
 }
 
 impl TwoDA {
-    const CHUNK_SIZE: usize = 0x4000;
+    pub fn new(filename: &str) -> Result<Self> {
+        let mut file = Self::open_file(filename)?;
 
-    pub fn new(filename: &str) -> TwoDA {
-        let data = TwoDA::open_file(filename).unwrap();
-        TwoDA { read_data: data }
+        Self::from_reader(&mut file)
     }
 
-    fn open_file(filename: &str) -> Result<Vec<Vec<u8>>> {
-        let mut file = match File::open(filename) {
-            Ok(file) => file,
-            Err(_) => panic!("Could not open the file named {}.", filename),
+    fn open_file(filename: &str) -> Result<File> {
+        File::open(filename).map_err(Into::into)
+    }
+
+    /// Parses a 2DA starting at the current position of `reader`.
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        Self::from_bytes(&buffer)
+    }
+
+    /// Parses a binary (V2.b) or text (V2.0) 2DA.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(TEXT_HEADER.as_bytes()) {
+            Self::from_text(&decode_string(bytes))
+        } else {
+            Self::from_binary(bytes)
+        }
+    }
+
+    fn from_binary(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader {
+            bytes: bytes
+                .strip_prefix(BINARY_HEADER)
+                .ok_or(Error::MissingHeader)?,
+            position: 0,
         };
 
-        let mut list_of_chunks: Vec<Vec<u8>> = Vec::new();
+        let mut columns = Vec::new();
+        while reader.peek()? != 0 {
+            columns.push(reader.read_until(b'\t')?);
+        }
+        reader.position += 1;
+
+        // Every label takes at least its terminator, so a larger count cannot be right.
+        let row_count = reader.read_u32()? as usize;
+        if row_count > reader.remaining() {
+            return Err(Error::InvalidData(format!(
+                "2DA claims {} rows but only {} bytes follow",
+                row_count,
+                reader.remaining()
+            )));
+        }
+        let mut labels = Vec::with_capacity(row_count);
+        for _ in 0..row_count {
+            labels.push(reader.read_until(b'\t')?);
+        }
+
+        let cell_count = row_count
+            .checked_mul(columns.len())
+            .filter(|&count| count <= reader.remaining() / 2)
+            .ok_or_else(|| {
+                Error::InvalidData(format!(
+                    "2DA offsets for {} rows of {} columns exceed the file",
+                    row_count,
+                    columns.len()
+                ))
+            })?;
+        let mut offsets = Vec::with_capacity(cell_count);
+        for _ in 0..cell_count {
+            offsets.push(reader.read_u16()? as usize);
+        }
+
+        // The size of the string data, which is implied by the end of the file.
+        reader.read_u16()?;
+        let string_data = &reader.bytes[reader.position..];
+
+        let mut offsets = offsets.into_iter();
+        let rows = labels
+            .into_iter()
+            .map(|label| {
+                let cells = (0..columns.len())
+                    .map(|_| {
+                        let offset = offsets.next().unwrap();
+                        let string = string_data.get(offset..).ok_or_else(|| {
+                            Error::InvalidData(format!("2DA string offset {} out of range", offset))
+                        })?;
+                        let end = string
+                            .iter()
+                            .position(|&byte| byte == 0)
+                            .unwrap_or(string.len());
+
+                        Ok(decode_string(&string[..end]))
+                    })
+                    .collect::<Result<_>>()?;
+
+                Ok(TwoDARow { label, cells })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(TwoDA { columns, rows })
+    }
+
+    /// Parses a text (V2.0) 2DA.
+    ///
+    /// The second line may hold a `DEFAULT:` value, which is ignored. Values
+    /// containing spaces are quoted, [`BLANK`] marks a blank value and missing
+    /// cells at the end of a row are blank.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim_end) != Some(TEXT_HEADER) {
+            return Err(Error::MissingHeader);
+        }
+        lines.next();
+
+        let columns = lines
+            .next()
+            .map(Self::split_text_line)
+            .ok_or_else(|| Error::InvalidData("2DA has no column headers".to_owned()))?;
+
+        let rows = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut values = Self::split_text_line(line).into_iter();
+                let label = values.next().unwrap_or_default();
+                let mut cells: Vec<String> = values.collect();
+                cells.resize(columns.len(), String::new());
 
-        loop {
-            let mut chunk = Vec::with_capacity(TwoDA::CHUNK_SIZE);
-            let read = file
-                .by_ref()
-                .take(TwoDA::CHUNK_SIZE as u64)
-                .read_to_end(&mut chunk)?;
+                TwoDARow { label, cells }
+            })
+            .collect();
 
-            if read == 0 {
-                break;
+        Ok(TwoDA { columns, rows })
+    }
+
+    fn split_text_line(line: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        let mut chars = line.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '"' {
+                chars.next();
+                values.push(chars.by_ref().take_while(|&c| c != '"').collect());
+            } else {
+                let mut value = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+                values.push(value);
             }
-            list_of_chunks.push(chunk);
-            if read < TwoDA::CHUNK_SIZE {
-                break;
+        }
+
+        // Blank values are written as `****` wherever they appear.
+        for value in values.iter_mut() {
+            if value == BLANK {
+                value.clear();
             }
         }
 
-        Ok(list_of_chunks)
+        values
     }
 
-    pub fn get_rows_and_column(&self, filename: &str) -> HashMap<usize, String> {
-        let path = Path::new(filename);
-        let file = File::open(path).unwrap();
-        let mut reader = BufReader::new(file);
+    /// Saves the table as a binary (V2.b) 2DA.
+    pub fn save(&self, twoda_filename: &str) -> Result<()> {
+        let mut file = File::create(twoda_filename)?;
+
+        self.write_to(&mut file)
+    }
+
+    /// Writes the table as a binary (V2.b) 2DA.
+    ///
+    /// Every distinct value is stored once, and cells refer to it with a 16-bit
+    /// offset, so the string data cannot exceed 64 KiB.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut data = Vec::new();
+        let mut offsets: HashMap<&str, u16> = HashMap::new();
+        let mut cell_offsets = Vec::with_capacity(self.rows.len() * self.columns.len());
+
+        for row in &self.rows {
+            for column in 0..self.columns.len() {
+                let value = match row.cells.get(column).map(String::as_str) {
+                    Some(BLANK) | None => "",
+                    Some(value) => value,
+                };
+
+                let offset = match offsets.get(value) {
+                    Some(offset) => *offset,
+                    None => {
+                        let offset = u16::try_from(data.len()).map_err(|_| {
+                            Error::InvalidData("2DA string data exceeds 65535 bytes".to_owned())
+                        })?;
+                        data.extend(encode_string(value));
+                        data.push(0);
+                        offsets.insert(value, offset);
+
+                        offset
+                    }
+                };
+                cell_offsets.push(offset);
+            }
+        }
+
+        let data_size = u16::try_from(data.len())
+            .map_err(|_| Error::InvalidData("2DA string data exceeds 65535 bytes".to_owned()))?;
+
+        let mut buffer = BINARY_HEADER.to_vec();
+        for column in &self.columns {
+            buffer.extend(encode_string(column));
+            buffer.push(b'\t');
+        }
+        buffer.push(0);
 
-        // Read header
-        let mut header_packed = [0u8; 9];
-        reader.read_exact(&mut header_packed).unwrap();
-        let header = String::from_utf8_lossy(&header_packed);
-        if header.trim() != "2DA V2.b\n" {
-            println!("No header located.");
-            return HashMap::new();
+        buffer.extend((self.rows.len() as u32).to_le_bytes());
+        for row in &self.rows {
+            buffer.extend(encode_string(&row.label));
+            buffer.push(b'\t');
         }
 
-        // Read entire 2da file as bytes
-        let mut twoda = Vec::new();
-        reader.read_to_end(&mut twoda).unwrap();
+        for offset in cell_offsets {
+            buffer.extend(offset.to_le_bytes());
+        }
+        buffer.extend(data_size.to_le_bytes());
+        buffer.extend(data);
+
+        writer.write_all(&buffer)?;
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.write_to(&mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    /// Saves the table as a text (V2.0) 2DA.
+    pub fn save_text(&self, twoda_filename: &str) -> Result<()> {
+        std::fs::write(twoda_filename, encode_string(&self.to_text()?))?;
+
+        Ok(())
+    }
 
-        // Find null position
-        let mut the_null_pos = None;
-        for (idx, b) in twoda.iter().enumerate() {
-            if *b == 0 {
-                the_null_pos = Some(idx);
-                break;
+    /// Formats the table as a text (V2.0) 2DA, with every column aligned.
+    ///
+    /// Blank values are written as [`BLANK`] and values containing whitespace
+    /// are quoted. Text 2DAs cannot escape quotes or line breaks, so values
+    /// containing them are rejected.
+    pub fn to_text(&self) -> Result<String> {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|name| Self::quote_text_value(name))
+            .collect::<Result<_>>()?;
+        let labels: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| Self::quote_text_value(&row.label))
+            .collect::<Result<_>>()?;
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                (0..self.columns.len())
+                    .map(|column| {
+                        Self::quote_text_value(row.cells.get(column).map_or("", String::as_str))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_>>()?;
+
+        let label_width = labels.iter().map(String::len).max().unwrap_or_default();
+        let widths: Vec<usize> = columns
+            .iter()
+            .enumerate()
+            .map(|(column, name)| {
+                cells
+                    .iter()
+                    .map(|row| row[column].len())
+                    .chain([name.len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let mut text = format!("{}\n\n", TEXT_HEADER);
+
+        let mut line = " ".repeat(label_width);
+        for (name, width) in columns.iter().zip(&widths) {
+            line.push_str(&format!(" {:<width$}", name, width = width));
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+
+        for (label, row) in labels.iter().zip(&cells) {
+            let mut line = format!("{:<width$}", label, width = label_width);
+            for (value, width) in row.iter().zip(&widths) {
+                line.push_str(&format!(" {:<width$}", value, width = width));
             }
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+
+        Ok(text)
+    }
+
+    fn quote_text_value(value: &str) -> Result<String> {
+        if value.contains(['"', '\n', '\r']) {
+            return Err(Error::InvalidData(format!(
+                "2DA value {:?} cannot be written as text",
+                value
+            )));
         }
-        let the_null_pos = the_null_pos.unwrap();
 
-        // Read number of rows
-        let num_of_rows_packed = &twoda[the_null_pos + 1..the_null_pos + 5];
-        let num_of_rows = u32::from_le_bytes(num_of_rows_packed.try_into().unwrap()) as usize;
+        Ok(if value.is_empty() {
+            BLANK.to_owned()
+        } else if value.contains(char::is_whitespace) {
+            format!("\"{}\"", value)
+        } else {
+            value.to_owned()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn get_column_index(&self, column: impl TwoDAColumn) -> Option<usize> {
+        column.column_index(self)
+    }
+
+    /// The value of a cell, or `None` if the cell is blank or out of range.
+    pub fn get(&self, row: usize, column: impl TwoDAColumn) -> Option<&str> {
+        let column = column.column_index(self)?;
+
+        self.rows
+            .get(row)?
+            .cells
+            .get(column)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty() && *value != BLANK)
+    }
+
+    /// Integers may be written in decimal or, prefixed with `0x`, in hexadecimal.
+    pub fn get_int(&self, row: usize, column: impl TwoDAColumn) -> Option<i32> {
+        let value = self.get(row, column)?;
+
+        match value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16).ok().map(|value| value as i32),
+            None => value.parse().ok(),
+        }
+    }
 
-        // Find number of columns
-        let num_of_cols = twoda
+    pub fn get_float(&self, row: usize, column: impl TwoDAColumn) -> Option<f32> {
+        self.get(row, column)?.parse().ok()
+    }
+
+    /// A reference into the talk table, e.g. the `name` column of `classes.2da`.
+    pub fn get_str_ref(&self, row: usize, column: impl TwoDAColumn) -> Option<u32> {
+        self.get_int(row, column)
+            .and_then(|str_ref| u32::try_from(str_ref).ok())
+    }
+
+    /// Finds the first row whose cell in `column` equals `value`, ignoring case.
+    pub fn find_row(&self, column: impl TwoDAColumn, value: &str) -> Option<usize> {
+        let column = column.column_index(self)?;
+
+        self.rows.iter().position(|row| {
+            row.cells
+                .get(column)
+                .is_some_and(|cell| cell.eq_ignore_ascii_case(value))
+        })
+    }
+
+    /// Formats the table as CSV, with the row labels as the first column.
+    ///
+    /// Pass `b'\t'` as the delimiter for TSV.
+    pub fn to_csv(&self, delimiter: u8, blank: Blank) -> Result<String> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(Vec::new());
+
+        writer.write_record(
+            [ROW_LABEL]
+                .into_iter()
+                .chain(self.columns.iter().map(String::as_str)),
+        )?;
+        for row in &self.rows {
+            let cells = (0..self.columns.len()).map(|column| {
+                match row.cells.get(column).map(String::as_str) {
+                    Some(BLANK) | Some("") | None if blank == Blank::Stars => BLANK,
+                    Some(BLANK) | None => "",
+                    Some(value) => value,
+                }
+            });
+            writer.write_record([row.label.as_str()].into_iter().chain(cells))?;
+        }
+
+        let bytes = writer
+            .into_inner()
+            .map_err(|error| Error::Io(error.into_error()))?;
+
+        Ok(String::from_utf8(bytes).expect("CSV is written from strings"))
+    }
+
+    /// Parses CSV written by [`TwoDA::to_csv`], or edited in a spreadsheet since.
+    pub fn from_csv(text: &str, delimiter: u8) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+
+        let columns: Vec<String> = reader
+            .headers()?
             .iter()
-            .take(the_null_pos)
-            .filter(|byte| **byte == b'\t')
-            .count();
+            .skip(1)
+            .map(ToOwned::to_owned)
+            .collect();
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let mut values = record.iter();
+
+            let label = values.next().unwrap_or_default().to_owned();
+            let mut cells: Vec<String> = values
+                .map(|value| {
+                    if value == BLANK {
+                        String::new()
+                    } else {
+                        value.to_owned()
+                    }
+                })
+                .collect();
+            cells.resize(columns.len(), String::new());
+
+            rows.push(TwoDARow { label, cells });
+        }
+
+        Ok(TwoDA { columns, rows })
+    }
+
+    /// Formats the table as a JSON array with one object per row.
+    ///
+    /// Each object holds the row label under [`ROW_LABEL`] followed by the
+    /// columns in table order, so every column name must be unique.
+    pub fn to_json(&self, blank: Blank) -> Result<String> {
+        for (idx, name) in self.columns.iter().enumerate() {
+            if name == ROW_LABEL || self.columns[..idx].contains(name) {
+                return Err(Error::InvalidData(format!(
+                    "2DA column {:?} cannot be written as a JSON key",
+                    name
+                )));
+            }
+        }
+
+        let rows: Vec<serde_json::Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut object = serde_json::Map::new();
+                object.insert(ROW_LABEL.to_owned(), row.label.clone().into());
 
-        // Find position after row names
-        let mut count = 0;
-        let mut after_rownames_pos = 0;
-        for (i, b) in twoda.iter().enumerate() {
-            if *b == b'\t' && i > the_null_pos {
-                if count == num_of_rows - 1 {
-                    after_rownames_pos = i;
-                    break;
+                for (column, name) in self.columns.iter().enumerate() {
+                    let value = match row.cells.get(column).map(String::as_str) {
+                        Some(BLANK) | Some("") | None => match blank {
+                            Blank::Empty => "".into(),
+                            Blank::Stars => BLANK.into(),
+                            Blank::Null => serde_json::Value::Null,
+                        },
+                        Some(value) => value.into(),
+                    };
+                    object.insert(name.clone(), value);
+                }
+
+                serde_json::Value::Object(object)
+            })
+            .collect();
+
+        Ok(serde_json::to_string_pretty(&rows)?)
+    }
+
+    /// Parses JSON written by [`TwoDA::to_json`].
+    ///
+    /// The columns are taken from the first row in order, followed by any new
+    /// keys of later rows. Numbers and booleans are kept as their text.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let objects: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_str(text)?;
+
+        let mut columns: Vec<String> = Vec::new();
+        for object in &objects {
+            for key in object.keys() {
+                if key != ROW_LABEL && !columns.contains(key) {
+                    columns.push(key.clone());
                 }
-                count += 1;
             }
         }
 
-        // Find number of pointers and data area size
-        let num_of_pointers = num_of_rows * num_of_cols;
-        let data_area = after_rownames_pos + (num_of_pointers * 2) + 2;
-
-        // Read row labels and values
-        let mut row_to_label = HashMap::new();
-        let mut row = 0;
-        for i in (0..num_of_pointers).step_by(num_of_cols) {
-            let pointer_packed =
-                &twoda[after_rownames_pos + (i * 2)..after_rownames_pos + ((i + 1) * 2)];
-            let pointer = u16::from_le_bytes(pointer_packed.try_into().unwrap()) as i64;
-            let value = {
-                reader.seek(SeekFrom::Start(data_area as u64)).unwrap();
-                reader.seek(SeekFrom::Current(pointer)).unwrap();
-                let mut buf = String::new();
-                reader.read_line(&mut buf).unwrap();
-                buf.trim().to_string()
-            };
-            if value.is_empty() {
-                row += 1;
-                continue;
+        let to_cell = |value: Option<&serde_json::Value>| match value {
+            None | Some(serde_json::Value::Null) => String::new(),
+            Some(serde_json::Value::String(value)) if value == BLANK => String::new(),
+            Some(serde_json::Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+        };
+
+        let rows = objects
+            .iter()
+            .enumerate()
+            .map(|(idx, object)| TwoDARow {
+                label: match object.get(ROW_LABEL) {
+                    Some(label) => to_cell(Some(label)),
+                    None => idx.to_string(),
+                },
+                cells: columns
+                    .iter()
+                    .map(|column| to_cell(object.get(column)))
+                    .collect(),
+            })
+            .collect();
+
+        Ok(TwoDA { columns, rows })
+    }
+}
+
+/// Reads the tab separated lists and little endian integers a binary 2DA is made of.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl ByteReader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or_else(|| Error::InvalidData("2DA ends unexpectedly".to_owned()))?;
+        self.position += count;
+
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.position)
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.bytes
+            .get(self.position)
+            .copied()
+            .ok_or_else(|| Error::InvalidData("2DA ends unexpectedly".to_owned()))
+    }
+
+    fn read_until(&mut self, terminator: u8) -> Result<String> {
+        let length = self.bytes[self.position..]
+            .iter()
+            .position(|&byte| byte == terminator)
+            .ok_or_else(|| Error::InvalidData("2DA ends unexpectedly".to_owned()))?;
+        let string = decode_string(self.take(length)?);
+        self.position += 1;
+
+        Ok(string)
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary 2DA laid out by hand, with the string data shared between cells.
+    fn binary_bytes() -> Vec<u8> {
+        let mut bytes = BINARY_HEADER.to_vec();
+        bytes.extend_from_slice(b"label\tname\tvalue\t\0");
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(b"0\t1\t");
+
+        let data = [&b"Bastila\0"[..], b"0x10\0", b"\0", b"Carth\0", b"-2.5\0"].concat();
+        for offset in [0u16, 8, 13, 14, 13, 20] {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&data);
+
+        bytes
+    }
+
+    #[test]
+    fn reads_binary() {
+        let twoda = TwoDA::from_bytes(&binary_bytes()).unwrap();
+
+        assert_eq!(twoda.columns, ["label", "name", "value"]);
+        assert_eq!(twoda.len(), 2);
+        assert_eq!(twoda.rows[1].label, "1");
+        assert_eq!(twoda.rows[0].cells, ["Bastila", "0x10", ""]);
+        assert_eq!(twoda.rows[1].cells, ["Carth", "", "-2.5"]);
+    }
+
+    #[test]
+    fn looks_up_cells() {
+        let twoda = TwoDA::from_bytes(&binary_bytes()).unwrap();
+
+        assert_eq!(twoda.get(0, "LABEL"), Some("Bastila"));
+        assert_eq!(twoda.get(0, 2), None);
+        assert_eq!(twoda.get(2, 0), None);
+        assert_eq!(twoda.get(0, "missing"), None);
+        assert_eq!(twoda.get_int(0, "name"), Some(16));
+        assert_eq!(twoda.get_str_ref(0, "name"), Some(16));
+        assert_eq!(twoda.get_float(1, "value"), Some(-2.5));
+        assert_eq!(twoda.get_int(1, "value"), None);
+        assert_eq!(twoda.find_row("label", "carth"), Some(1));
+        assert_eq!(twoda.get_column_index("Value"), Some(2));
+    }
+
+    fn sample_twoda() -> TwoDA {
+        TwoDA {
+            columns: vec!["label".to_owned(), "two words".to_owned(), String::new()],
+            rows: vec![
+                TwoDARow {
+                    label: "0".to_owned(),
+                    cells: vec![
+                        "Bastila".to_owned(),
+                        "Jedi Guardian".to_owned(),
+                        "1".to_owned(),
+                    ],
+                },
+                TwoDARow {
+                    label: "row one".to_owned(),
+                    cells: vec![String::new(), "Bastila".to_owned(), BLANK.to_owned()],
+                },
+                TwoDARow {
+                    label: String::new(),
+                    cells: vec!["1".to_owned(), String::new(), String::new()],
+                },
+            ],
+        }
+    }
+
+    /// The table as it reads back, with [`BLANK`] cells stored as empty strings.
+    fn without_stars(mut twoda: TwoDA) -> TwoDA {
+        for cell in twoda.rows.iter_mut().flat_map(|row| row.cells.iter_mut()) {
+            if cell == BLANK {
+                cell.clear();
             }
-            row_to_label.insert(row, value);
-            row += 1;
         }
-        row_to_label
+
+        twoda
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let twoda = sample_twoda();
+        let bytes = twoda.to_bytes().unwrap();
+
+        assert!(bytes.starts_with(BINARY_HEADER));
+        assert_eq!(TwoDA::from_bytes(&bytes).unwrap(), without_stars(twoda));
+
+        let rewritten = TwoDA::from_bytes(&bytes).unwrap().to_bytes().unwrap();
+        assert_eq!(rewritten, bytes);
+    }
+
+    #[test]
+    fn binary_stores_each_value_once() {
+        let bytes = sample_twoda().to_bytes().unwrap();
+        let bastila = bytes
+            .windows(b"Bastila\0".len())
+            .filter(|window| window == b"Bastila\0")
+            .count();
+
+        assert_eq!(bastila, 1);
+    }
+
+    #[test]
+    fn binary_rejects_too_much_string_data() {
+        let twoda = TwoDA {
+            columns: vec!["value".to_owned()],
+            rows: (0..700)
+                .map(|idx| TwoDARow {
+                    label: idx.to_string(),
+                    cells: vec![format!("{:0>100}", idx)],
+                })
+                .collect(),
+        };
+
+        assert!(matches!(twoda.to_bytes(), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let twoda = sample_twoda();
+        let text = twoda.to_text().unwrap();
+
+        assert!(text.starts_with("2DA V2.0\n\n"));
+        assert!(text.contains("\"two words\""));
+        assert!(text.contains("\"row one\""));
+        assert!(text.contains("\"Jedi Guardian\""));
+        assert_eq!(
+            TwoDA::from_text(&text).unwrap(),
+            without_stars(twoda.clone())
+        );
+        assert_eq!(
+            TwoDA::from_bytes(text.as_bytes())
+                .unwrap()
+                .to_text()
+                .unwrap(),
+            text
+        );
+    }
+
+    #[test]
+    fn reads_hand_written_text() {
+        let text = "2DA V2.0\r\nDEFAULT: ****\r\n\tlabel   name\r\n0 a \"b c\"\r\n\r\n1 ****\r\n";
+        let twoda = TwoDA::from_text(text).unwrap();
+
+        assert_eq!(twoda.columns, ["label", "name"]);
+        assert_eq!(twoda.rows[0].cells, ["a", "b c"]);
+        assert_eq!(twoda.rows[1].cells, ["", ""]);
+        assert_eq!(twoda.len(), 2);
+    }
+
+    #[test]
+    fn text_rejects_unwritable_values() {
+        let mut twoda = sample_twoda();
+        twoda.rows[0].cells[0] = "say \"hello\"".to_owned();
+        assert!(matches!(twoda.to_text(), Err(Error::InvalidData(_))));
+
+        let mut twoda = sample_twoda();
+        twoda.columns[0] = "two\nlines".to_owned();
+        assert!(matches!(twoda.to_text(), Err(Error::InvalidData(_))));
+
+        assert!(matches!(
+            TwoDA::from_text("2DA V2.b\n"),
+            Err(Error::MissingHeader)
+        ));
+    }
+
+    #[test]
+    fn csv_and_tsv_round_trip() {
+        let twoda = sample_twoda();
+
+        for delimiter in [b',', b'\t'] {
+            for blank in [Blank::Empty, Blank::Stars, Blank::Null] {
+                let csv = twoda.to_csv(delimiter, blank).unwrap();
+
+                assert_eq!(csv.contains(BLANK), blank == Blank::Stars);
+                assert_eq!(
+                    TwoDA::from_csv(&csv, delimiter).unwrap(),
+                    without_stars(twoda.clone())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let twoda = sample_twoda();
+
+        for blank in [Blank::Empty, Blank::Stars, Blank::Null] {
+            let json = twoda.to_json(blank).unwrap();
+            let values: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+
+            let expected_blank = match blank {
+                Blank::Empty => serde_json::json!(""),
+                Blank::Stars => serde_json::json!(BLANK),
+                Blank::Null => serde_json::Value::Null,
+            };
+            assert_eq!(values[1]["label"], expected_blank);
+            assert_eq!(values[1][ROW_LABEL], "row one");
+            assert_eq!(values[0][""], "1");
+            assert_eq!(
+                TwoDA::from_json(&json).unwrap(),
+                without_stars(twoda.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn json_reads_numbers_and_missing_labels() {
+        let twoda = TwoDA::from_json(r#"[{"a": 1, "b": true}, {"c": null, "a": "x"}]"#).unwrap();
+
+        assert_eq!(twoda.columns, ["a", "b", "c"]);
+        assert_eq!(twoda.rows[0].label, "0");
+        assert_eq!(twoda.rows[0].cells, ["1", "true", ""]);
+        assert_eq!(twoda.rows[1].label, "1");
+        assert_eq!(twoda.rows[1].cells, ["x", "", ""]);
+    }
+
+    #[test]
+    fn json_rejects_clashing_columns() {
+        let mut twoda = sample_twoda();
+        twoda.columns[1] = ROW_LABEL.to_owned();
+        assert!(matches!(
+            twoda.to_json(Blank::Empty),
+            Err(Error::InvalidData(_))
+        ));
+
+        let mut twoda = sample_twoda();
+        twoda.columns[1] = "label".to_owned();
+        assert!(matches!(
+            twoda.to_json(Blank::Empty),
+            Err(Error::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_binary() {
+        let bytes = binary_bytes();
+
+        assert!(matches!(
+            TwoDA::from_bytes(b"2DA V9.9\n"),
+            Err(Error::MissingHeader)
+        ));
+        assert!(matches!(
+            TwoDA::from_bytes(&bytes[..40]),
+            Err(Error::InvalidData(_))
+        ));
+
+        let mut huge_rows = bytes.clone();
+        let row_count = BINARY_HEADER.len() + 18;
+        huge_rows[row_count..row_count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            TwoDA::from_bytes(&huge_rows),
+            Err(Error::InvalidData(_))
+        ));
+
+        let mut bad_offset = bytes.clone();
+        let first_offset = BINARY_HEADER.len() + 18 + 4 + 4;
+        bad_offset[first_offset..first_offset + 2].copy_from_slice(&500u16.to_le_bytes());
+        assert!(matches!(
+            TwoDA::from_bytes(&bad_offset),
+            Err(Error::InvalidData(_))
+        ));
     }
 }
@@ -0,0 +1,157 @@
+/// Properties whose value is a count followed by that many lines of values.
+const LIST_PROPERTIES: [&str; 4] = [
+    "channelscale",
+    "channeltranslate",
+    "lowerrightcoords",
+    "upperleftcoords",
+];
+
+/// A parsed TXI texture description, e.g. `proceduretype cycle` or `envmaptexture`.
+///
+/// TXI text is either a loose `.txi` resource or the trailer of a TPC texture.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Txi {
+    /// The properties in file order. List properties hold one value per line.
+    pub properties: Vec<(String, String)>,
+}
+
+impl Txi {
+    pub fn from_text(text: &str) -> Self {
+        let mut properties = Vec::new();
+        let mut lines = text
+            .lines()
+            .map(|line| line.trim_matches(|c: char| c.is_whitespace() || c == '\0'))
+            .filter(|line| !line.is_empty());
+
+        while let Some(line) = lines.next() {
+            let (key, value) = line
+                .split_once(char::is_whitespace)
+                .map(|(key, value)| (key, value.trim()))
+                .unwrap_or((line, ""));
+
+            let value = match value.parse::<usize>() {
+                Ok(count) if LIST_PROPERTIES.contains(&key.to_ascii_lowercase().as_str()) => {
+                    lines.by_ref().take(count).collect::<Vec<_>>().join("\n")
+                }
+                _ => value.to_owned(),
+            };
+
+            properties.push((key.to_owned(), value));
+        }
+
+        Txi { properties }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::from_text(&String::from_utf8_lossy(bytes))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for (key, value) in &self.properties {
+            if LIST_PROPERTIES.contains(&key.to_ascii_lowercase().as_str()) {
+                let values: Vec<&str> = value.lines().collect();
+                text.push_str(&format!("{} {}\r\n", key, values.len()));
+                for value in values {
+                    text.push_str(value);
+                    text.push_str("\r\n");
+                }
+            } else if value.is_empty() {
+                text.push_str(&format!("{}\r\n", key));
+            } else {
+                text.push_str(&format!("{} {}\r\n", key, value));
+            }
+        }
+
+        text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i32> {
+        self.get(key)?.parse().ok()
+    }
+
+    pub fn get_float(&self, key: &str) -> Option<f32> {
+        self.get(key)?.parse().ok()
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self
+            .properties
+            .iter_mut()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(key))
+        {
+            Some((_, existing)) => *existing = value.to_owned(),
+            None => self.properties.push((key.to_owned(), value.to_owned())),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let position = self
+            .properties
+            .iter()
+            .position(|(existing, _)| existing.eq_ignore_ascii_case(key))?;
+
+        Some(self.properties.remove(position).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "proceduretype cycle\r\nnumx 2\r\nnumy 2\r\nfps 12.5\r\n\
+                          isbumpmap\r\nupperleftcoords 2\r\n0 0 0\r\n0.5 0 0\r\n\
+                          defaultwidth 1\0\0";
+
+    #[test]
+    fn parses_properties() {
+        let txi = Txi::from_text(SAMPLE);
+
+        assert_eq!(txi.properties.len(), 7);
+        assert_eq!(txi.get("ProcedureType"), Some("cycle"));
+        assert_eq!(txi.get_int("numx"), Some(2));
+        assert_eq!(txi.get_float("fps"), Some(12.5));
+        assert_eq!(txi.get("isbumpmap"), Some(""));
+        assert_eq!(txi.get("upperleftcoords"), Some("0 0 0\n0.5 0 0"));
+        assert_eq!(txi.get_int("defaultwidth"), Some(1));
+        assert_eq!(txi.get("missing"), None);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let txi = Txi::from_text(SAMPLE);
+        let text = txi.to_text();
+
+        assert!(text.contains("upperleftcoords 2\r\n0 0 0\r\n0.5 0 0\r\n"));
+        assert!(text.contains("isbumpmap\r\n"));
+        assert_eq!(Txi::from_bytes(text.as_bytes()), txi);
+    }
+
+    #[test]
+    fn edits_properties() {
+        let mut txi = Txi::from_text(SAMPLE);
+
+        txi.set("FPS", "24");
+        txi.set("blending", "additive");
+        assert_eq!(txi.remove("NUMY"), Some("2".to_owned()));
+        assert_eq!(txi.remove("numy"), None);
+
+        let parsed = Txi::from_text(&txi.to_text());
+        assert_eq!(parsed.get_int("fps"), Some(24));
+        assert_eq!(parsed.get("blending"), Some("additive"));
+        assert_eq!(parsed.get("numy"), None);
+        assert!(Txi::from_text("\r\n\0").is_empty());
+    }
+}